@@ -1,12 +1,39 @@
-#include <Stepper.h>
 
-const int stepsPerRevolution = 400;
+const int stepsPerRevolution = 200;
 
-Stepper stepper(stepsPerRevolution, 8, 9, 10, 11);
+/// step and direction inputs of the driver
+const int stepPin = 8;
+const int dirPin = 9;
+
+/// high and low time of a step pulse
+const int stepPulseMicros = 200;
 
 short stepCount = 0;
+short microsteps = 2;
+
+/// steps of a revolution of the driven output, a motor revolution until the host sets it
+long period = (long) stepsPerRevolution * microsteps;
+
+/// a step delta of this value starts a command frame: command code and argument follow
+const short command = -32768;
+const short setMicrosteps = 1;
+/// argument is the number of steps after which the step count wraps
+const short setPeriod = 7;
+/// argument is the number of step deltas following, answered once after the last one
+const short stepBatch = 3;
+
+/// microstep select pins of the driver
+const int ms1Pin = 4;
+const int ms2Pin = 5;
+const int ms3Pin = 6;
 
 void setup() {
+  pinMode(stepPin, OUTPUT);
+  pinMode(dirPin, OUTPUT);
+  pinMode(ms1Pin, OUTPUT);
+  pinMode(ms2Pin, OUTPUT);
+  pinMode(ms3Pin, OUTPUT);
+  writeMicrostepPins(microsteps);
   Serial.begin(9600);
 }
 
@@ -15,17 +42,74 @@ void loop() {
     byte steps0 = Serial.read();
     byte steps1 = Serial.read();
     short steps = beToShort(steps0, steps1);
+    if (steps == command) {
+      runCommand();
+      return;
+    }
     stepCount += steps;
-    stepCount = stepCount % stepsPerRevolution;
+    stepCount = stepCount % period;
     byte stepCount0 = shortToBe0(stepCount);
     byte stepCount1 = shortToBe1(stepCount);
     Serial.write(stepCount0);
     Serial.write(stepCount1);
-    stepper.step(steps);
+    step(steps);
     // read stepCount from motor if available
   }
 }
 
+void runCommand() {
+  short code = readShort();
+  short argument = readShort();
+  if (code == setMicrosteps) {
+    stepCount = (long) stepCount * argument / microsteps;
+    period = period * argument / microsteps;
+    microsteps = argument;
+    writeMicrostepPins(microsteps);
+  } else if (code == setPeriod && argument > 0) {
+    period = argument;
+    stepCount = stepCount % period;
+  } else if (code == stepBatch) {
+    for (short i = 0; i < argument; i++) {
+      short steps = readShort();
+      stepCount += steps;
+      stepCount = stepCount % period;
+      step(steps);
+    }
+  }
+  writeShort(stepCount);
+}
+
+/// pulses the step pin once per microstep, negative steps reverse the direction
+void step(short steps) {
+  digitalWrite(dirPin, steps < 0 ? LOW : HIGH);
+  long count = abs((long) steps);
+  for (long i = 0; i < count; i++) {
+    digitalWrite(stepPin, HIGH);
+    delayMicroseconds(stepPulseMicros);
+    digitalWrite(stepPin, LOW);
+    delayMicroseconds(stepPulseMicros);
+  }
+}
+
+/// pin levels for an A4988 driver
+void writeMicrostepPins(short factor) {
+  digitalWrite(ms1Pin, factor == 2 || factor == 8 || factor == 16);
+  digitalWrite(ms2Pin, factor == 4 || factor == 8 || factor == 16);
+  digitalWrite(ms3Pin, factor == 16);
+}
+
+short readShort() {
+  while (Serial.available() < 2) {}
+  byte a = Serial.read();
+  byte b = Serial.read();
+  return beToShort(a, b);
+}
+
+void writeShort(short x) {
+  Serial.write(shortToBe0(x));
+  Serial.write(shortToBe1(x));
+}
+
 /// convert big endian bytes to short and vice versa
 /// TODO: not sure if arduino uses little endian and if implementation is correct
 short beToShort(byte a, byte b) {
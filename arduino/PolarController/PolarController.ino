@@ -0,0 +1,222 @@
+#include <Servo.h>
+
+/// step and direction inputs of the drivers of the central and the beam motor
+const int centralStepPin = 8;
+const int centralDirPin = 9;
+const int beamStepPin = 10;
+const int beamDirPin = 11;
+
+/// high and low time of a step pulse
+const int stepPulseMicros = 200;
+
+/// of the central and the beam motor, in microsteps
+short centralStepCount = 0;
+short beamStepCount = 0;
+short microsteps = 2;
+
+/// a step delta of this value starts a command frame: command code and argument follow
+const short command = -32768;
+const short setMicrosteps = 1;
+const short setPen = 2;
+/// argument is the target central step, followed by the target beam step and the duration
+const short segmentCommand = 4;
+/// answered like a segment with the step counts and the free slots of the segment buffer
+const short segmentStatus = 5;
+/// stops both motors where they are and drops the buffered segments, answered like a status
+const short clearSegments = 6;
+
+/// duration of segments on the wire in microseconds per unit
+const unsigned long segmentTimeUnit = 100;
+
+/// moves of both motors to target steps, interpolated linearly in steps
+struct Segment {
+  short central;
+  short beam;
+  unsigned short duration;
+};
+
+/// ring buffer of the segments, the first one is running
+const int segmentCapacity = 16;
+Segment segments[segmentCapacity];
+int firstSegment = 0;
+int segmentCount = 0;
+
+/// of the running segment
+bool segmentRunning = false;
+unsigned long segmentStart = 0;
+short segmentFromCentral = 0;
+short segmentFromBeam = 0;
+
+/// servo lifting the pen, argument 0 of setPen raises it and 1 lowers it
+Servo pen;
+const int penPin = 3;
+const int penUpAngle = 90;
+const int penDownAngle = 30;
+
+/// microstep select pins of both drivers
+const int ms1Pin = 4;
+const int ms2Pin = 5;
+const int ms3Pin = 6;
+
+void setup() {
+  pinMode(centralStepPin, OUTPUT);
+  pinMode(centralDirPin, OUTPUT);
+  pinMode(beamStepPin, OUTPUT);
+  pinMode(beamDirPin, OUTPUT);
+  pinMode(ms1Pin, OUTPUT);
+  pinMode(ms2Pin, OUTPUT);
+  pinMode(ms3Pin, OUTPUT);
+  writeMicrostepPins(microsteps);
+  pen.attach(penPin);
+  pen.write(penUpAngle);
+  Serial.begin(9600);
+}
+
+/// every message is the step delta of the central motor followed by the one of the beam,
+/// answered with both step counts in the same order,
+/// buffered segments are interpolated between messages
+void loop() {
+  interpolate();
+  if (Serial.available() >= 2) {
+    short centralSteps = readShort();
+    if (centralSteps == command) {
+      runCommand();
+      return;
+    }
+    short beamSteps = readShort();
+    centralStepCount += centralSteps;
+    beamStepCount += beamSteps;
+    writeStepCounts();
+    step(centralSteps, beamSteps);
+  }
+}
+
+void runCommand() {
+  short code = readShort();
+  short argument = readShort();
+  if (code == setMicrosteps) {
+    centralStepCount = (long) centralStepCount * argument / microsteps;
+    beamStepCount = (long) beamStepCount * argument / microsteps;
+    microsteps = argument;
+    writeMicrostepPins(microsteps);
+  } else if (code == setPen) {
+    pen.write(argument == 0 ? penUpAngle : penDownAngle);
+  } else if (code == segmentCommand) {
+    short beam = readShort();
+    unsigned short duration = (unsigned short) readShort();
+    if (segmentCount < segmentCapacity) {
+      Segment &segment = segments[(firstSegment + segmentCount) % segmentCapacity];
+      segment.central = argument;
+      segment.beam = beam;
+      segment.duration = duration;
+      segmentCount++;
+    }
+  } else if (code == clearSegments) {
+    segmentCount = 0;
+    segmentRunning = false;
+  }
+  writeStepCounts();
+  if (code == segmentCommand || code == segmentStatus || code == clearSegments) {
+    writeShort(segmentCapacity - segmentCount);
+  }
+}
+
+/// steps both motors towards where the running segment is by now,
+/// at most one step each per call, and starts the next segment right where it ended
+void interpolate() {
+  if (segmentCount == 0) {
+    segmentRunning = false;
+    return;
+  }
+  Segment &segment = segments[firstSegment];
+  if (!segmentRunning) {
+    segmentRunning = true;
+    segmentStart = micros();
+    segmentFromCentral = centralStepCount;
+    segmentFromBeam = beamStepCount;
+  }
+  unsigned long duration = segment.duration * segmentTimeUnit;
+  unsigned long elapsed = micros() - segmentStart;
+  float fraction = elapsed >= duration ? 1.0 : (float) elapsed / duration;
+  short central = segmentFromCentral + lround((segment.central - segmentFromCentral) * fraction);
+  short beam = segmentFromBeam + lround((segment.beam - segmentFromBeam) * fraction);
+  short centralSteps = central > centralStepCount ? 1 : central < centralStepCount ? -1 : 0;
+  short beamSteps = beam > beamStepCount ? 1 : beam < beamStepCount ? -1 : 0;
+  centralStepCount += centralSteps;
+  beamStepCount += beamSteps;
+  step(centralSteps, beamSteps);
+  if (fraction >= 1.0 && centralStepCount == segment.central && beamStepCount == segment.beam) {
+    firstSegment = (firstSegment + 1) % segmentCapacity;
+    segmentCount--;
+    // the next segment starts when this one should have ended, not when its last step was taken
+    segmentStart += duration;
+    segmentFromCentral = centralStepCount;
+    segmentFromBeam = beamStepCount;
+    segmentRunning = segmentCount > 0;
+  }
+}
+
+/// pulses both step pins, spreading the steps of the motor with fewer of them
+/// evenly over those of the other one so both finish together
+void step(short centralSteps, short beamSteps) {
+  digitalWrite(centralDirPin, centralSteps < 0 ? LOW : HIGH);
+  digitalWrite(beamDirPin, beamSteps < 0 ? LOW : HIGH);
+  long central = abs((long) centralSteps);
+  long beam = abs((long) beamSteps);
+  long count = max(central, beam);
+  long centralError = 0;
+  long beamError = 0;
+  for (long i = 0; i < count; i++) {
+    centralError += central;
+    beamError += beam;
+    bool centralStep = centralError >= count;
+    bool beamStep = beamError >= count;
+    if (centralStep) {
+      centralError -= count;
+      digitalWrite(centralStepPin, HIGH);
+    }
+    if (beamStep) {
+      beamError -= count;
+      digitalWrite(beamStepPin, HIGH);
+    }
+    delayMicroseconds(stepPulseMicros);
+    digitalWrite(centralStepPin, LOW);
+    digitalWrite(beamStepPin, LOW);
+    delayMicroseconds(stepPulseMicros);
+  }
+}
+
+/// pin levels for A4988 drivers
+void writeMicrostepPins(short factor) {
+  digitalWrite(ms1Pin, factor == 2 || factor == 8 || factor == 16);
+  digitalWrite(ms2Pin, factor == 4 || factor == 8 || factor == 16);
+  digitalWrite(ms3Pin, factor == 16);
+}
+
+void writeStepCounts() {
+  writeShort(centralStepCount);
+  writeShort(beamStepCount);
+}
+
+short readShort() {
+  while (Serial.available() < 2) {}
+  byte a = Serial.read();
+  byte b = Serial.read();
+  return beToShort(a, b);
+}
+
+void writeShort(short x) {
+  Serial.write(shortToBe0(x));
+  Serial.write(shortToBe1(x));
+}
+
+/// convert big endian bytes to short and vice versa
+short beToShort(byte a, byte b) {
+  return ((unsigned short) (a << 8)) | b;
+}
+byte shortToBe0(short x) {
+  return (byte) (x >> 8);
+}
+byte shortToBe1(short x) {
+  return (byte) x;
+}
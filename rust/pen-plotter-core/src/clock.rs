@@ -0,0 +1,213 @@
+use std::{
+    fmt,
+    sync::{Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Tells the time of the motions and waits for their deadlines.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Returns at `deadline`, right away if it passed.
+    fn sleep_until(&self, deadline: Instant);
+
+    /// Counts the calling thread among the ones stepping through motions
+    /// until [`Clock::leave`].
+    fn enter(&self) {}
+
+    fn leave(&self) {}
+
+    /// Lets the clock pass an entered thread blocking on something else until [`Clock::wake`],
+    /// as if it slept.
+    fn idle(&self) {}
+
+    fn wake(&self) {}
+}
+
+/// The wall clock.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    }
+}
+
+/// Stands still while any thread that entered it is running
+/// and jumps to the earliest deadline once all of them sleep,
+/// so motions take no longer than computing their steps and run in the same order every time.
+#[derive(Debug)]
+pub struct VirtualClock {
+    start: Instant,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    elapsed: Duration,
+    entered: usize,
+    idle: usize,
+
+    /// of the sleeping threads since the start
+    deadlines: Vec<Duration>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+impl VirtualClock {
+    /// since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        self.lock().elapsed
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    /// Without entered threads a sleeping thread advances the clock on its own.
+    fn sleep_until(&self, deadline: Instant) {
+        let deadline = deadline.saturating_duration_since(self.start);
+        let mut state = self.lock();
+        if deadline <= state.elapsed {
+            return;
+        }
+        state.deadlines.push(deadline);
+        loop {
+            if state.elapsed >= deadline {
+                if let Some(index) = state.deadlines.iter().position(|d| *d == deadline) {
+                    state.deadlines.swap_remove(index);
+                }
+                return;
+            }
+            let all_sleeping = state.deadlines.len() + state.idle >= state.entered.max(1);
+            let next = state.deadlines.iter().min().copied().unwrap_or(deadline);
+            if all_sleeping && next > state.elapsed {
+                state.elapsed = next;
+                self.changed.notify_all();
+                continue;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn enter(&self) {
+        self.lock().entered += 1;
+    }
+
+    fn leave(&self) {
+        let mut state = self.lock();
+        state.entered = state.entered.saturating_sub(1);
+        self.changed.notify_all();
+    }
+
+    fn idle(&self) {
+        self.lock().idle += 1;
+        self.changed.notify_all();
+    }
+
+    fn wake(&self) {
+        let mut state = self.lock();
+        state.idle = state.idle.saturating_sub(1);
+    }
+}
+
+/// Enters a clock on creation and leaves it when dropped.
+#[derive(Debug)]
+pub struct Entered<'a> {
+    clock: &'a dyn Clock,
+}
+
+impl<'a> Entered<'a> {
+    pub fn new(clock: &'a dyn Clock) -> Self {
+        clock.enter();
+        Self { clock }
+    }
+}
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.clock.leave();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn virtual_sleep_is_instant() {
+        let clock = VirtualClock::default();
+        let start = Instant::now();
+        clock.sleep_until(clock.now() + Duration::from_secs(3600));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        clock.sleep_until(clock.now() - Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn virtual_clock_waits_for_entered_threads() {
+        let clock = Arc::new(VirtualClock::default());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let threads: Vec<_> = [3, 5]
+            .into_iter()
+            .map(|interval| {
+                clock.enter();
+                let (clock, log) = (Arc::clone(&clock), Arc::clone(&log));
+                thread::spawn(move || {
+                    let interval = Duration::from_millis(interval);
+                    let mut deadline = clock.now();
+                    for _ in 0..3 {
+                        deadline += interval;
+                        clock.sleep_until(deadline);
+                        let millis = clock.elapsed().as_millis();
+                        log.lock().unwrap().push((interval.as_millis(), millis));
+                    }
+                    clock.leave();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let mut log = log.lock().unwrap().clone();
+        log.sort_by_key(|(interval, millis)| (*millis, *interval));
+        assert_eq!(log, [(3, 3), (5, 5), (3, 6), (3, 9), (5, 10), (5, 15)]);
+        assert_eq!(clock.elapsed(), Duration::from_millis(15));
+    }
+
+    #[test]
+    fn virtual_clock_passes_idle_threads() {
+        let clock = VirtualClock::default();
+        clock.enter();
+        clock.enter();
+        clock.idle();
+        clock.sleep_until(clock.now() + Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        clock.wake();
+        clock.leave();
+        clock.leave();
+    }
+}
@@ -0,0 +1,327 @@
+use crate::clock::{Clock, RealClock};
+use std::{
+    error::Error,
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// to decelerate from full velocity to a hold and to accelerate back
+pub const RAMP: Duration = Duration::from_millis(300);
+
+/// Pauses, resumes and aborts running motions.
+///
+/// The motions follow a plot clock instead of the [`Clock`] the control runs on.
+/// Pausing slows the plot clock down to a hold within [`RAMP`] so the motors decelerate
+/// along their paths, and resuming speeds it up again.
+/// Motions after a pause are delayed by the time the plot clock lagged behind.
+#[derive(Debug)]
+pub struct Control {
+    clock: Arc<dyn Clock>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    /// of the last pause or resume
+    since: Instant,
+
+    /// plot clock time at `since`
+    plot_since: Instant,
+
+    /// of the plot clock relative to the clock at `since`
+    rate_from: f64,
+
+    /// reached after ramping
+    rate_to: f64,
+    aborted: bool,
+
+    /// threads waiting while held that let the clock pass them
+    idle: usize,
+
+    /// counts the times the idle threads were woken
+    woken: u64,
+}
+
+/// Returned by the motions after [`Control::abort`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("aborted")
+    }
+}
+
+impl Error for Aborted {}
+
+impl Default for Control {
+    /// Runs on the [`RealClock`].
+    fn default() -> Self {
+        Self::new(Arc::new(RealClock))
+    }
+}
+
+impl Control {
+    /// Runs with the plot clock equal to `clock`.
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            clock,
+            state: Mutex::new(State {
+                since: now,
+                plot_since: now,
+                rate_from: 1.0,
+                rate_to: 1.0,
+                aborted: false,
+                idle: 0,
+                woken: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    /// Decelerates to a hold.
+    pub fn pause(&self) {
+        self.ramp_to(0.0);
+    }
+
+    /// Accelerates from where the motions were paused.
+    pub fn resume(&self) {
+        self.ramp_to(1.0);
+    }
+
+    /// Stops the motions at their next interval.
+    pub fn abort(&self) {
+        let mut state = self.lock();
+        state.aborted = true;
+        self.wake(&mut state);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.lock().rate_to == 0.0
+    }
+
+    /// Whether the motions came to a hold after pausing.
+    pub fn is_held(&self) -> bool {
+        self.lock().is_held(self.clock.now())
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.lock().aborted
+    }
+
+    pub fn now(&self) -> Instant {
+        self.lock().plot_time(self.clock.now())
+    }
+
+    /// Blocks while the motions are held and returns how long they were,
+    /// or [`Aborted`] after [`Control::abort`].
+    /// The clock passes the blocked thread, like one sleeping on it,
+    /// so held time goes by on a virtual clock.
+    pub fn wait_while_held(&self) -> eyre::Result<Duration> {
+        let mut state = self.lock();
+        let mut idle_since = None;
+        let mut held_since = None;
+        let result = loop {
+            if state.aborted {
+                break Err(Aborted.into());
+            }
+            let now = self.clock.now();
+            if !state.is_held(now) {
+                let held = held_since.map(|since| now.saturating_duration_since(since));
+                break Ok(held.unwrap_or_default());
+            }
+            held_since.get_or_insert(now);
+            // woken threads were counted out all at once, so none of them falls behind the clock
+            if idle_since != Some(state.woken) {
+                idle_since = Some(state.woken);
+                state.idle += 1;
+                self.clock.idle();
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        };
+        if idle_since == Some(state.woken) {
+            state.idle -= 1;
+            self.clock.wake();
+        }
+        result
+    }
+
+    fn ramp_to(&self, rate: f64) {
+        let now = self.clock.now();
+        let mut state = self.lock();
+        *state = State {
+            since: now,
+            plot_since: state.plot_time(now),
+            rate_from: state.rate(now),
+            rate_to: rate,
+            ..*state
+        };
+        self.wake(&mut state);
+    }
+
+    /// Counts the idle threads out of the clock before they run again.
+    fn wake(&self, state: &mut State) {
+        for _ in 0..state.idle {
+            self.clock.wake();
+        }
+        state.idle = 0;
+        state.woken += 1;
+        self.changed.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    fn ramp_duration(&self) -> Duration {
+        RAMP.mul_f64((self.rate_to - self.rate_from).abs())
+    }
+
+    fn rate(&self, now: Instant) -> f64 {
+        let ramp = self.ramp_duration();
+        let elapsed = now.saturating_duration_since(self.since);
+        if elapsed >= ramp {
+            self.rate_to
+        } else {
+            let fraction = elapsed.as_secs_f64() / ramp.as_secs_f64();
+            self.rate_from + (self.rate_to - self.rate_from) * fraction
+        }
+    }
+
+    /// integrates the rate from `since` to `now`
+    fn plot_time(&self, now: Instant) -> Instant {
+        let ramp = self.ramp_duration().as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.since).as_secs_f64();
+        let ramping = elapsed.min(ramp);
+        let mut advance = (self.rate_from + self.rate(now)) / 2.0 * ramping;
+        advance += self.rate_to * (elapsed - ramping);
+        self.plot_since + Duration::from_secs_f64(advance)
+    }
+
+    /// a resume right after a hold starts at rate 0 but is not held
+    fn is_held(&self, now: Instant) -> bool {
+        self.rate_to == 0.0 && self.rate(now) == 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::{sync::Arc, thread};
+
+    fn state(rate_from: f64, rate_to: f64) -> (State, Instant) {
+        let now = Instant::now();
+        let state = State {
+            since: now,
+            plot_since: now,
+            rate_from,
+            rate_to,
+            aborted: false,
+            idle: 0,
+            woken: 0,
+        };
+        (state, now)
+    }
+
+    fn assert_near(actual: Instant, expected: Instant) {
+        let error = if actual > expected {
+            actual - expected
+        } else {
+            expected - actual
+        };
+        assert!(error < Duration::from_micros(10), "error = {error:?}");
+    }
+
+    #[test]
+    fn running_follows_wall_clock() {
+        let (state, now) = state(1.0, 1.0);
+        let later = now + Duration::from_secs(3);
+        assert_near(state.plot_time(later), later);
+    }
+
+    #[test]
+    fn pause_decelerates_to_hold() {
+        let (state, now) = state(1.0, 0.0);
+        assert_near(state.plot_time(now + RAMP / 2), now + RAMP * 3 / 8);
+        assert_near(state.plot_time(now + RAMP), now + RAMP / 2);
+        assert_near(state.plot_time(now + RAMP * 10), now + RAMP / 2);
+        assert!(!state.is_held(now + RAMP / 2));
+        assert!(state.is_held(now + RAMP));
+    }
+
+    #[test]
+    fn resume_from_hold_is_not_held() {
+        let (state, now) = state(0.0, 1.0);
+        assert!(!state.is_held(now));
+    }
+
+    #[test]
+    fn resume_accelerates() {
+        let (state, now) = state(0.0, 1.0);
+        assert_near(state.plot_time(now + RAMP), now + RAMP / 2);
+        assert_near(state.plot_time(now + RAMP * 2), now + RAMP * 3 / 2);
+    }
+
+    #[test]
+    fn abort_stops_waiting() {
+        let clock = Arc::new(VirtualClock::default());
+        let control = Arc::new(Control::new(clock.clone()));
+        control.pause();
+        clock.sleep_until(clock.now() + RAMP * 2);
+        assert!(control.is_held());
+        let waiting = {
+            let control = Arc::clone(&control);
+            thread::spawn(move || control.wait_while_held())
+        };
+        for _ in 0..100 {
+            thread::yield_now();
+        }
+        assert!(!waiting.is_finished());
+        control.abort();
+        let e = waiting.join().unwrap().unwrap_err();
+        assert!(e.is::<Aborted>());
+    }
+
+    #[test]
+    fn paused_control_reports_paused() {
+        let control = Control::default();
+        control.pause();
+        assert!(control.is_paused());
+        assert!(!control.is_held());
+        control.resume();
+        assert!(!control.is_paused());
+        assert_eq!(control.wait_while_held().unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn wait_returns_held_duration() {
+        let clock = Arc::new(VirtualClock::default());
+        let control = Arc::new(Control::new(clock.clone()));
+        control.pause();
+        clock.sleep_until(clock.now() + RAMP);
+        clock.enter();
+        clock.enter();
+        let resuming = {
+            let (clock, control) = (Arc::clone(&clock), Arc::clone(&control));
+            thread::spawn(move || {
+                clock.sleep_until(clock.now() + Duration::from_secs(2));
+                control.resume();
+                clock.leave();
+            })
+        };
+        let held = control.wait_while_held().unwrap();
+        clock.leave();
+        resuming.join().unwrap();
+        assert_eq!(held, Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,489 @@
+use crate::{
+    control::{Aborted, Control},
+    device::Device,
+    motion::Motion,
+    pen::Pen,
+    polar::PolarSegment,
+    serial,
+    stepper::{CurrentStep, DeltaSteps},
+    transport::Transport,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// between asking the controller for free segment slots
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// One microcontroller driving both motors,
+/// every message carries a step delta for each of them.
+struct Controller {
+    transport: Mutex<Box<dyn Transport>>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// of the central and the beam axis, waiting for the transport
+    pending: [Option<i16>; 2],
+
+    /// by the last reply
+    reported: [CurrentStep; 2],
+}
+
+/// The device of one axis of a controller driving both motors.
+pub struct ControllerAxis {
+    controller: Arc<Controller>,
+
+    /// 0 for the central axis, 1 for the beam
+    slot: usize,
+}
+
+/// Splits a controller into the devices of the central and the beam axis.
+/// A delta queued while the other axis is talking to the controller
+/// goes out together with the next delta of that axis.
+pub fn split(transport: Box<dyn Transport>) -> (ControllerAxis, ControllerAxis) {
+    let controller = Arc::new(Controller {
+        transport: Mutex::new(transport),
+        state: Mutex::default(),
+    });
+    let axis = |slot| ControllerAxis {
+        controller: Arc::clone(&controller),
+        slot,
+    };
+    (axis(0), axis(1))
+}
+
+impl Device for ControllerAxis {
+    fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+        {
+            let pending = &mut lock(&self.controller.state).pending[self.slot];
+            *pending = Some(pending.unwrap_or(0) + delta.0);
+        }
+        let mut transport = lock(&self.controller.transport);
+        let deltas = {
+            let mut state = lock(&self.controller.state);
+            if state.pending[self.slot].is_none() {
+                // sent along with the other axis
+                return Ok(state.reported[self.slot]);
+            }
+            let pending = std::mem::take(&mut state.pending);
+            pending.map(|delta| DeltaSteps(delta.unwrap_or(0)))
+        };
+        serial::write_multi_axis_steps(&mut *transport, deltas[0], deltas[1])?;
+        self.receive(&mut transport)
+    }
+
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+        let mut transport = lock(&self.controller.transport);
+        serial::write_pen(&mut *transport, pen)?;
+        self.receive(&mut transport)
+    }
+}
+
+impl ControllerAxis {
+    /// Streams segments to the controller of this axis.
+    /// Takes the free slots the controller reports as the size of its segment buffer,
+    /// so the buffer has to be empty.
+    pub fn streamer(&self) -> eyre::Result<Streamer> {
+        let mut streamer = Streamer {
+            controller: Arc::clone(&self.controller),
+            capacity: 0,
+            free: 0,
+            sent: VecDeque::new(),
+            pen: None,
+        };
+        streamer.status(&mut |_| Ok(()))?;
+        streamer.capacity = streamer.free;
+        log::info!("controller buffers {} segments", streamer.capacity);
+        Ok(streamer)
+    }
+
+    fn receive(&self, transport: &mut Box<dyn Transport>) -> eyre::Result<CurrentStep> {
+        let reported = serial::read_multi_axis_steps(&mut *transport)?;
+        log::info!("received {reported:?}");
+        lock(&self.controller.state).reported = reported;
+        Ok(reported[self.slot])
+    }
+}
+
+/// A segment the controller finished.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Finished<'a> {
+    pub segment: &'a PolarSegment,
+
+    /// reported by the controller after it finished the segment
+    pub steps: [CurrentStep; 2],
+
+    /// whether the controller finished all segments sent,
+    /// so `steps` are those it stands at after this one
+    pub settled: bool,
+}
+
+/// Sends polar segments to a controller without overflowing its segment buffer.
+pub struct Streamer {
+    controller: Arc<Controller>,
+
+    /// of the segment buffer of the controller
+    capacity: u16,
+    free: u16,
+
+    /// and not yet finished by the controller
+    sent: VecDeque<PolarSegment>,
+
+    /// as set by the streamer
+    pub pen: Option<Pen>,
+}
+
+impl Streamer {
+    /// Sends the segments in order and calls `on_done` with each one the controller finished,
+    /// stopping at its first error.
+    /// Waits for the buffer to run empty before lifting or lowering the pen for a motion,
+    /// lifts it and pauses before tool changes like the executor.
+    /// Pausing stops sending, the controller finishes the buffered segments.
+    /// Aborting clears the buffer, so the controller stops right away, and lifts the pen.
+    pub fn stream<F>(
+        &mut self,
+        motions: &[Motion],
+        segments: &[PolarSegment],
+        control: &Control,
+        mut on_done: F,
+    ) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        let result = self.try_stream(motions, segments, control, &mut on_done);
+        if let Err(e) = &result {
+            if e.is::<Aborted>() {
+                self.clear()?;
+                self.lift_pen()?;
+            }
+        }
+        result
+    }
+
+    fn try_stream<F>(
+        &mut self,
+        motions: &[Motion],
+        segments: &[PolarSegment],
+        control: &Control,
+        on_done: &mut F,
+    ) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        let mut motion_index = None;
+        for segment in segments {
+            if motion_index.replace(segment.motion) != Some(segment.motion) {
+                let motion = &motions[segment.motion];
+                if let Some(pen) = motion.tool_change {
+                    self.drain(control, on_done)?;
+                    self.lift_pen()?;
+                    log::warn!(
+                        "insert pen {pen}, then type p and Enter or send SIGUSR2 to continue"
+                    );
+                    control.pause();
+                }
+                control.wait_while_held()?;
+                if self.pen != Some(motion.pen) {
+                    self.drain(control, on_done)?;
+                    self.set_pen(motion.pen)?;
+                }
+            }
+            control.wait_while_held()?;
+            while self.free == 0 {
+                poll(control);
+                self.status(on_done)?;
+            }
+            self.send(*segment, on_done)?;
+        }
+        self.drain(control, on_done)
+    }
+
+    /// Waits until the controller finished all segments.
+    fn drain<F>(&mut self, control: &Control, on_done: &mut F) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        while !self.sent.is_empty() {
+            poll(control);
+            self.status(on_done)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the controller and drops the segments it didn't finish.
+    /// Those finished since the last status are not reported done.
+    fn clear(&mut self) -> eyre::Result<()> {
+        let (steps, free) = {
+            let mut transport = lock(&self.controller.transport);
+            serial::write_clear_segments(&mut *transport)?;
+            serial::read_segment_status(&mut *transport)?
+        };
+        lock(&self.controller.state).reported = steps;
+        self.free = free;
+        self.sent.clear();
+        log::info!("controller: cleared segments at {steps:?}");
+        Ok(())
+    }
+
+    fn lift_pen(&mut self) -> eyre::Result<()> {
+        if self.pen == Some(Pen::Down) {
+            self.set_pen(Pen::Up)?;
+        }
+        Ok(())
+    }
+
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<()> {
+        let mut transport = lock(&self.controller.transport);
+        serial::write_pen(&mut *transport, pen)?;
+        let reported = serial::read_multi_axis_steps(&mut *transport)?;
+        lock(&self.controller.state).reported = reported;
+        self.pen = Some(pen);
+        log::info!("controller: pen {pen:?}");
+        Ok(())
+    }
+
+    fn send<F>(&mut self, segment: PolarSegment, on_done: &mut F) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        let status = {
+            let mut transport = lock(&self.controller.transport);
+            serial::write_segment(
+                &mut *transport,
+                segment.central,
+                segment.beam,
+                segment.duration,
+            )?;
+            serial::read_segment_status(&mut *transport)?
+        };
+        self.sent.push_back(segment);
+        self.acknowledge(status, on_done)
+    }
+
+    fn status<F>(&mut self, on_done: &mut F) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        let status = {
+            let mut transport = lock(&self.controller.transport);
+            serial::write_segment_status(&mut *transport)?;
+            serial::read_segment_status(&mut *transport)?
+        };
+        self.acknowledge(status, on_done)
+    }
+
+    /// Segments beyond those left in the buffer are done.
+    fn acknowledge<F>(
+        &mut self,
+        (steps, free): ([CurrentStep; 2], u16),
+        on_done: &mut F,
+    ) -> eyre::Result<()>
+    where
+        F: FnMut(Finished) -> eyre::Result<()>,
+    {
+        lock(&self.controller.state).reported = steps;
+        self.free = free;
+        let buffered = usize::from(self.capacity.saturating_sub(free));
+        while self.sent.len() > buffered {
+            if let Some(segment) = self.sent.pop_front() {
+                on_done(Finished {
+                    segment: &segment,
+                    steps,
+                    settled: self.sent.is_empty(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Waits a [`POLL_INTERVAL`] on the clock of `control`.
+fn poll(control: &Control) {
+    let clock = control.clock();
+    clock.sleep_until(clock.now() + POLL_INTERVAL);
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport;
+    use std::{
+        io::{Read, Write},
+        thread,
+    };
+
+    /// Answers multi-axis frames like the firmware and returns how many it received.
+    fn firmware(mut device: transport::Memory) -> thread::JoinHandle<usize> {
+        thread::spawn(move || {
+            let mut steps = [0_i16; 2];
+            let mut frames = 0;
+            let mut frame = [0; 4];
+            while device.read_exact(&mut frame).is_ok() {
+                steps[0] += i16::from_be_bytes([frame[0], frame[1]]);
+                steps[1] += i16::from_be_bytes([frame[2], frame[3]]);
+                frames += 1;
+                let mut reply = [0; 4];
+                reply[0..2].copy_from_slice(&steps[0].to_be_bytes());
+                reply[2..4].copy_from_slice(&steps[1].to_be_bytes());
+                device.write_all(&reply).unwrap();
+            }
+            frames
+        })
+    }
+
+    /// Buffers segments like the firmware and finishes one each time it is asked for its status.
+    fn segment_firmware(mut device: transport::Memory, capacity: u16) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut steps = [0_i16; 2];
+            let mut buffer = VecDeque::new();
+            let mut frame = [0; 6];
+            while device.read_exact(&mut frame).is_ok() {
+                let code = i16::from_be_bytes([frame[2], frame[3]]);
+                match code {
+                    4 => {
+                        let mut rest = [0; 4];
+                        device.read_exact(&mut rest).unwrap();
+                        let beam = i16::from_be_bytes([rest[0], rest[1]]);
+                        buffer.push_back([i16::from_be_bytes([frame[4], frame[5]]), beam]);
+                        assert!(buffer.len() <= usize::from(capacity));
+                    }
+                    5 => {
+                        if let Some(targets) = buffer.pop_front() {
+                            steps = targets;
+                        }
+                    }
+                    6 => buffer.clear(),
+                    _ => {
+                        assert!(buffer.is_empty(), "pen changed with buffered segments");
+                        let mut reply = [0; 4];
+                        reply[0..2].copy_from_slice(&steps[0].to_be_bytes());
+                        reply[2..4].copy_from_slice(&steps[1].to_be_bytes());
+                        device.write_all(&reply).unwrap();
+                        continue;
+                    }
+                }
+                let mut reply = [0; 6];
+                reply[0..2].copy_from_slice(&steps[0].to_be_bytes());
+                reply[2..4].copy_from_slice(&steps[1].to_be_bytes());
+                let free = capacity - buffer.len() as u16;
+                reply[4..6].copy_from_slice(&free.to_be_bytes());
+                device.write_all(&reply).unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn streamer_keeps_buffer_from_overflowing() {
+        use crate::{clock::VirtualClock, motion::Segment, path::Path, stepper::TargetStep};
+        use nalgebra::Point2;
+
+        let (host, device) = transport::pair();
+        let firmware = segment_firmware(device, 3);
+        let (central, beam) = split(Box::new(host));
+        let mut streamer = central.streamer().unwrap();
+        let path = Path {
+            start: Point2::new(0.0, -10.0),
+            end: Point2::new(0.0, -20.0),
+            velocity: 10.0,
+        };
+        let segments = [Pen::Up, Pen::Down].map(|pen| Segment::new(path, pen));
+        let control = Control::new(Arc::new(VirtualClock::default()));
+        let motions = Motion::sequence(segments, control.clock().now()).unwrap();
+        let polar: Vec<_> = (0..10)
+            .map(|i| PolarSegment {
+                central: TargetStep(0),
+                beam: TargetStep(i),
+                duration: Duration::from_millis(10),
+                motion: usize::from(i >= 5),
+                fraction: 0.0,
+            })
+            .collect();
+        let mut done = Vec::new();
+        streamer
+            .stream(&motions, &polar, &control, |finished| {
+                assert_eq!(finished.steps[1].0, finished.segment.beam.0);
+                done.push((finished.segment.beam.0, finished.settled));
+                Ok(())
+            })
+            .unwrap();
+        let targets: Vec<_> = done.iter().map(|(beam, _)| *beam).collect();
+        assert_eq!(targets, (0..10).collect::<Vec<_>>());
+        // drained before lowering the pen and at the end
+        assert!(done[4].1 && done[9].1);
+        assert_eq!(streamer.pen, Some(Pen::Down));
+        drop((central, beam, streamer));
+        firmware.join().unwrap();
+    }
+
+    #[test]
+    fn abort_clears_buffered_segments() {
+        use crate::{clock::VirtualClock, motion::Segment, path::Path, stepper::TargetStep};
+        use nalgebra::Point2;
+
+        let (host, device) = transport::pair();
+        let firmware = segment_firmware(device, 3);
+        let (central, beam) = split(Box::new(host));
+        let mut streamer = central.streamer().unwrap();
+        let path = Path {
+            start: Point2::new(0.0, -10.0),
+            end: Point2::new(0.0, -20.0),
+            velocity: 10.0,
+        };
+        let control = Control::new(Arc::new(VirtualClock::default()));
+        let motions = Motion::sequence([Segment::new(path, Pen::Down)], control.clock().now());
+        let polar: Vec<_> = (0..10)
+            .map(|i| PolarSegment {
+                central: TargetStep(0),
+                beam: TargetStep(i),
+                duration: Duration::from_millis(10),
+                motion: 0,
+                fraction: 0.0,
+            })
+            .collect();
+        let mut done = Vec::new();
+        let e = streamer
+            .stream(&motions.unwrap(), &polar, &control, |finished| {
+                done.push(finished.segment.beam.0);
+                control.abort();
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(e.is::<Aborted>());
+        // the firmware asserts the buffer is empty when the pen is lifted
+        assert_eq!(done, [0]);
+        assert_eq!(streamer.pen, Some(Pen::Up));
+        drop((central, beam, streamer));
+        firmware.join().unwrap();
+    }
+
+    #[test]
+    fn axes_share_one_transport() {
+        let (host, device) = transport::pair();
+        let firmware = firmware(device);
+        let (mut central, mut beam) = split(Box::new(host));
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..50 {
+                    central.step(DeltaSteps(1)).unwrap();
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..30 {
+                    beam.step(DeltaSteps(-2)).unwrap();
+                }
+            });
+        });
+        assert_eq!(central.flush().unwrap(), CurrentStep(50));
+        assert_eq!(beam.flush().unwrap(), CurrentStep(-60));
+        drop((central, beam));
+        assert!(firmware.join().unwrap() <= 82);
+    }
+}
@@ -0,0 +1,377 @@
+use crate::{
+    pen::Pen,
+    serial,
+    stepper::{CurrentStep, DeltaSteps},
+    transport::Transport,
+};
+use eyre::eyre;
+use std::{
+    fmt,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/// of step deltas in one batch frame, the receive buffer of the firmware holds 64 bytes
+pub const MAX_BATCH: usize = 29;
+
+/// A microcontroller driving one stepper motor.
+pub trait Device: Send {
+    /// Steps by `delta` and returns the step number reported afterwards.
+    fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep>;
+
+    /// Lifts or lowers the pen and returns the current step number.
+    /// Only the microcontroller of the beam drives the pen.
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep>;
+
+    /// Waits until the device reported all steps and returns its step number.
+    fn flush(&mut self) -> eyre::Result<CurrentStep> {
+        self.step(DeltaSteps(0))
+    }
+
+    /// Whether [`Device::step`] returns the step the device reported after the delta,
+    /// rather than the one expected before it did.
+    fn reports_steps(&self) -> bool {
+        true
+    }
+}
+
+impl Device for Box<dyn Transport> {
+    fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+        serial::write_steps(&mut *self, delta)?;
+        let current_step = serial::read_current_step(&mut *self)?;
+        log::debug!("received {current_step:?}");
+        Ok(current_step)
+    }
+
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+        serial::write_pen(&mut *self, pen)?;
+        serial::read_current_step(&mut *self)
+    }
+}
+
+/// Counts steps like the firmware without any I/O.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SimulatedDevice {
+    pub step: CurrentStep,
+    pub pen: Pen,
+
+    /// after which the step number wraps like on the central microcontroller, never if `None`
+    pub period: Option<i16>,
+}
+
+impl SimulatedDevice {
+    /// Converts the step number and the period from microstep factor `from` to `to`.
+    pub fn set_microsteps(&mut self, from: i16, to: i16) {
+        self.step.0 = convert_microsteps(self.step.0, from, to);
+        self.period = self
+            .period
+            .map(|period| convert_microsteps(period, from, to));
+    }
+
+    /// Wraps the step number after `period` steps from now on.
+    pub fn set_period(&mut self, period: i16) {
+        if period > 0 {
+            self.period = Some(period);
+            self.step.0 %= period;
+        }
+    }
+}
+
+impl Device for SimulatedDevice {
+    fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+        self.step.0 = self.step.0.wrapping_add(delta.0);
+        if let Some(period) = self.period.filter(|period| *period > 0) {
+            self.step.0 %= period;
+        }
+        Ok(self.step)
+    }
+
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+        self.pen = pen;
+        Ok(self.step)
+    }
+}
+
+/// like the firmware, truncating towards zero
+fn convert_microsteps(steps: i16, from: i16, to: i16) -> i16 {
+    (i32::from(steps) * i32::from(to) / i32::from(from)) as i16
+}
+
+/// Runs the I/O of a request on the worker thread of a [`BatchedDevice`].
+pub type Scoped = Box<dyn FnOnce(&mut dyn FnMut()) + Send>;
+
+/// Called on the thread stepping a [`BatchedDevice`] for each request
+/// to carry its thread local state to the worker, like the context of a recording.
+pub type Scope = fn() -> Scoped;
+
+/// A microcontroller taking batches of step deltas in one frame
+/// and acknowledging each batch with its current step.
+/// A worker thread talks to it, so [`Device::step`] doesn't wait for the round trip
+/// and returns the step expected after the queued deltas.
+/// Deltas queue up while a batch is in flight and go out together in the next one.
+pub struct BatchedDevice {
+    requests: Sender<(Request, Option<Scoped>)>,
+    replies: Receiver<eyre::Result<CurrentStep>>,
+
+    /// last step number reported
+    reported: CurrentStep,
+
+    /// sum of the deltas of the batch in flight
+    in_flight: Option<i32>,
+    queued: Vec<DeltaSteps>,
+    scope: Option<Scope>,
+}
+
+#[derive(Debug)]
+enum Request {
+    Steps(Vec<DeltaSteps>),
+    Pen(Pen),
+}
+
+impl BatchedDevice {
+    /// The step number is unknown until the first [`Device::flush`].
+    pub fn new(transport: Box<dyn Transport>) -> Self {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_replies, replies) = mpsc::channel();
+        thread::spawn(move || serve(transport, worker_requests, worker_replies));
+        Self {
+            requests,
+            replies,
+            reported: CurrentStep::default(),
+            in_flight: None,
+            queued: Vec::new(),
+            scope: None,
+        }
+    }
+
+    /// Runs the I/O of each request within the scope captured on the stepping thread.
+    pub fn with_scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    fn expected(&self) -> eyre::Result<CurrentStep> {
+        let expected = i32::from(self.reported.0) + self.in_flight.unwrap_or(0) + sum(&self.queued);
+        let expected =
+            i16::try_from(expected).map_err(|_| eyre!("expected step {expected} is beyond i16"))?;
+        Ok(CurrentStep(expected))
+    }
+
+    /// Sends up to [`MAX_BATCH`] queued deltas, an empty batch if none are queued.
+    fn send_queued(&mut self) -> eyre::Result<()> {
+        let count = self.queued.len().min(MAX_BATCH);
+        let batch: Vec<_> = self.queued.drain(..count).collect();
+        self.in_flight = Some(sum(&batch));
+        self.send(Request::Steps(batch))
+    }
+
+    fn send(&self, request: Request) -> eyre::Result<()> {
+        self.requests
+            .send((request, self.scope.map(|scope| scope())))
+            .map_err(|_| eyre!("batch worker stopped"))
+    }
+
+    fn wait(&mut self) -> eyre::Result<()> {
+        let reply = self
+            .replies
+            .recv()
+            .map_err(|_| eyre!("batch worker stopped"))?;
+        self.acknowledge(reply)
+    }
+
+    fn acknowledge(&mut self, reply: eyre::Result<CurrentStep>) -> eyre::Result<()> {
+        self.in_flight = None;
+        self.reported = reply?;
+        Ok(())
+    }
+}
+
+impl Device for BatchedDevice {
+    fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+        self.queued.push(delta);
+        while let Ok(reply) = self.replies.try_recv() {
+            self.acknowledge(reply)?;
+        }
+        if self.in_flight.is_none() {
+            self.send_queued()?;
+        }
+        self.expected()
+    }
+
+    fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+        self.flush()?;
+        self.in_flight = Some(0);
+        self.send(Request::Pen(pen))?;
+        self.wait()?;
+        Ok(self.reported)
+    }
+
+    fn reports_steps(&self) -> bool {
+        false
+    }
+
+    fn flush(&mut self) -> eyre::Result<CurrentStep> {
+        if self.in_flight.is_some() {
+            self.wait()?;
+        }
+        loop {
+            self.send_queued()?;
+            self.wait()?;
+            if self.queued.is_empty() {
+                return Ok(self.reported);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BatchedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchedDevice")
+            .field("reported", &self.reported)
+            .field("in_flight", &self.in_flight)
+            .field("queued", &self.queued)
+            .field("scoped", &self.scope.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+fn sum(deltas: &[DeltaSteps]) -> i32 {
+    deltas.iter().map(|delta| i32::from(delta.0)).sum()
+}
+
+/// Answers the requests until the device is dropped or fails.
+fn serve(
+    mut transport: Box<dyn Transport>,
+    requests: Receiver<(Request, Option<Scoped>)>,
+    replies: Sender<eyre::Result<CurrentStep>>,
+) {
+    for (request, scoped) in requests {
+        let mut reply = None;
+        let mut answer = || {
+            let written = match &request {
+                Request::Steps(deltas) => serial::write_batch(&mut transport, deltas),
+                Request::Pen(pen) => serial::write_pen(&mut transport, *pen),
+            };
+            reply = Some(written.and_then(|()| serial::read_current_step(&mut transport)));
+        };
+        match scoped {
+            Some(scoped) => scoped(&mut answer),
+            None => answer(),
+        }
+        let reply =
+            reply.unwrap_or_else(|| Err(eyre!("scope of the batch worker skipped the request")));
+        match &reply {
+            Ok(current_step) => log::debug!("received {current_step:?} after {request:?}"),
+            Err(_) => {
+                let _ = replies.send(reply);
+                return;
+            }
+        }
+        if replies.send(reply).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport;
+    use std::{
+        io::{Read, Write},
+        time::Duration,
+    };
+
+    /// Answers batch frames like the firmware, slowly, and returns their sizes.
+    fn firmware(mut device: transport::Memory) -> thread::JoinHandle<Vec<usize>> {
+        thread::spawn(move || {
+            let mut step = 0_i16;
+            let mut sizes = Vec::new();
+            let mut header = [0; 6];
+            while device.read_exact(&mut header).is_ok() {
+                let count = i16::from_be_bytes([header[4], header[5]]) as usize;
+                let mut deltas = vec![0; 2 * count];
+                device.read_exact(&mut deltas).unwrap();
+                for delta in deltas.chunks(2) {
+                    step += i16::from_be_bytes([delta[0], delta[1]]);
+                }
+                sizes.push(count);
+                thread::sleep(Duration::from_millis(20));
+                device.write_all(&step.to_be_bytes()).unwrap();
+            }
+            sizes
+        })
+    }
+
+    #[test]
+    fn simulated_device_wraps_at_period() {
+        let mut device = SimulatedDevice {
+            period: Some(400),
+            ..Default::default()
+        };
+        assert_eq!(device.step(DeltaSteps(401)).unwrap(), CurrentStep(1));
+        assert_eq!(device.step(DeltaSteps(-3)).unwrap(), CurrentStep(-2));
+        device.set_microsteps(2, 4);
+        assert_eq!((device.step, device.period), (CurrentStep(-4), Some(800)));
+        assert_eq!(device.step(DeltaSteps(804)).unwrap(), CurrentStep(0));
+    }
+
+    #[test]
+    fn batches_queue_while_in_flight() {
+        let (host, device) = transport::pair();
+        let firmware = firmware(device);
+        let mut batched = BatchedDevice::new(Box::new(host));
+        assert_eq!(batched.flush().unwrap(), CurrentStep(0));
+        for _ in 0..10 {
+            batched.step(DeltaSteps(2)).unwrap();
+        }
+        assert_eq!(batched.step(DeltaSteps(-1)).unwrap(), CurrentStep(19));
+        assert_eq!(batched.flush().unwrap(), CurrentStep(19));
+        drop(batched);
+        let sizes = firmware.join().unwrap();
+        assert_eq!(sizes.iter().sum::<usize>(), 11);
+        assert_eq!(sizes[..2], [0, 1]);
+        assert!(sizes.len() < 6, "{sizes:?}");
+    }
+
+    #[test]
+    fn expected_step_beyond_i16_fails() {
+        let (host, _device) = transport::pair();
+        let mut batched = BatchedDevice::new(Box::new(host));
+        batched.step(DeltaSteps(i16::MAX)).unwrap();
+        let _ = batched.step(DeltaSteps(1)).unwrap_err();
+    }
+
+    #[test]
+    fn scope_is_captured_per_request() {
+        thread_local! {
+            static INTERVAL: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+        static SCOPED: std::sync::Mutex<Vec<usize>> = std::sync::Mutex::new(Vec::new());
+        fn scope() -> Scoped {
+            let interval = INTERVAL.with(|interval| interval.get());
+            Box::new(move |f| {
+                SCOPED.lock().unwrap().push(interval);
+                f();
+            })
+        }
+        let (host, device) = transport::pair();
+        let firmware = firmware(device);
+        let mut batched = BatchedDevice::new(Box::new(host)).with_scope(scope);
+        INTERVAL.with(|interval| interval.set(7));
+        batched.step(DeltaSteps(2)).unwrap();
+        INTERVAL.with(|interval| interval.set(8));
+        assert_eq!(batched.flush().unwrap(), CurrentStep(2));
+        drop(batched);
+        firmware.join().unwrap();
+        assert_eq!(*SCOPED.lock().unwrap(), [7, 8]);
+    }
+
+    #[test]
+    fn worker_errors_are_returned() {
+        let (host, device) = transport::pair();
+        drop(device);
+        let mut batched = BatchedDevice::new(Box::new(host));
+        let _ = batched.flush().unwrap_err();
+    }
+}
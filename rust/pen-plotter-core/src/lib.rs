@@ -0,0 +1,23 @@
+//! Kinematics, motion timing and the serial protocol of a pen plotter
+//! with a central stepper motor rotating a beam and a second one extending it.
+//!
+//! [`stepper`] converts points to the steps of each motor, [`motion`] times paths
+//! one after another, [`timer`] steps through them on the plot clock of a
+//! [`control::Control`], which follows a real or virtual [`clock::Clock`],
+//! and [`serial`] talks to the microcontrollers of the motors over a [`transport::Transport`].
+
+pub mod angle;
+pub mod clock;
+pub mod control;
+pub mod controller;
+pub mod device;
+pub mod motion;
+pub mod named;
+pub mod path;
+pub mod pen;
+pub mod polar;
+pub mod serial;
+pub mod stepper;
+pub mod timer;
+pub mod transport;
+pub mod vector_2;
@@ -0,0 +1,145 @@
+use crate::{
+    control::RAMP,
+    path::Path,
+    pen::{Pen, PenNumber},
+};
+use std::time::{Duration, Instant};
+
+/// for the pen to settle after lifting or lowering it
+pub const PEN_DELAY: Duration = Duration::from_millis(250);
+
+/// A path to follow with the pen up or down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub path: Path,
+    pub pen: Pen,
+
+    /// waited before the path when the pen goes up or down
+    pub pen_delay: Duration,
+
+    /// pen to hold for before the path until the operator inserted it and resumes
+    pub tool_change: Option<PenNumber>,
+}
+
+impl Segment {
+    /// Waits [`PEN_DELAY`] for the pen without a tool change.
+    pub fn new(path: Path, pen: Pen) -> Self {
+        Self {
+            path,
+            pen,
+            pen_delay: PEN_DELAY,
+            tool_change: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    pub path: Path,
+    pub pen: Pen,
+    pub start: Instant,
+    pub tool_change: Option<PenNumber>,
+}
+
+impl Motion {
+    /// Chains the segments so each motion starts when the previous one ends,
+    /// with an additional pen delay whenever the pen changes
+    /// and a [`RAMP`] to hold before tool changes.
+    pub fn sequence(
+        segments: impl IntoIterator<Item = Segment>,
+        start: Instant,
+    ) -> eyre::Result<Vec<Self>> {
+        let mut start = start;
+        let mut pen = None;
+        segments
+            .into_iter()
+            .map(|segment| {
+                let Segment {
+                    path,
+                    pen: next,
+                    pen_delay,
+                    tool_change,
+                } = segment;
+                if tool_change.is_some() {
+                    start += RAMP;
+                }
+                if pen.replace(next) != Some(next) {
+                    start += pen_delay;
+                }
+                let motion = Self {
+                    path,
+                    pen: next,
+                    start,
+                    tool_change,
+                };
+                start += path.duration()?;
+                Ok(motion)
+            })
+            .collect()
+    }
+
+    pub fn end(&self) -> eyre::Result<Instant> {
+        Ok(self.start + self.path.duration()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point2;
+
+    #[test]
+    fn sequence_starts_after_previous_path() {
+        let start = Instant::now();
+        let first = Path {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(3.0, 4.0),
+            velocity: 50.0,
+        };
+        let second = Path {
+            start: first.end,
+            end: Point2::new(0.0, 0.0),
+            velocity: 25.0,
+        };
+        let segments = [first, second].map(|path| Segment::new(path, Pen::Down));
+        let motions = Motion::sequence(segments, start).unwrap();
+        assert_eq!(motions[0].start, start + PEN_DELAY);
+        assert_eq!(
+            motions[1].start,
+            start + PEN_DELAY + Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn sequence_waits_for_pen() {
+        let start = Instant::now();
+        let path = Path {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(3.0, 4.0),
+            velocity: 50.0,
+        };
+        let segments = [Pen::Up, Pen::Down].map(|pen| Segment::new(path, pen));
+        let motions = Motion::sequence(segments, start).unwrap();
+        let expected = start + PEN_DELAY * 2 + Duration::from_millis(100);
+        assert_eq!(motions[1].start, expected);
+    }
+
+    #[test]
+    fn sequence_holds_before_tool_change() {
+        let start = Instant::now();
+        let path = Path {
+            start: Point2::new(0.0, 0.0),
+            end: Point2::new(3.0, 4.0),
+            velocity: 50.0,
+        };
+        let changed = Segment {
+            pen_delay: Duration::from_millis(400),
+            tool_change: Some(2),
+            ..Segment::new(path, Pen::Down)
+        };
+        let motions = Motion::sequence([Segment::new(path, Pen::Down), changed], start).unwrap();
+        let expected = start + PEN_DELAY + Duration::from_millis(100) + RAMP;
+        assert_eq!(motions[1].start, expected);
+        assert_eq!(motions[1].tool_change, Some(2));
+    }
+}
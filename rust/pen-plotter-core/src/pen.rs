@@ -0,0 +1,15 @@
+use serde::Serialize;
+
+/// Number of a pen of multi-pen jobs, starting at 1.
+pub type PenNumber = u8;
+
+/// of jobs which don't name their pens
+pub const DEFAULT_PEN: PenNumber = 1;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pen {
+    #[default]
+    Up,
+    Down,
+}
@@ -0,0 +1,247 @@
+use crate::{
+    motion::Motion,
+    path::Path,
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep, Stepper, TargetStep},
+    vector_2,
+};
+use eyre::WrapErr;
+use nalgebra::Point2;
+use std::time::Duration;
+
+/// of a single segment, longer motions and holds are split
+pub const MAX_DURATION: Duration = Duration::from_secs(1);
+
+/// of halving a segment to meet the tolerance
+const MAX_DEPTH: u32 = 12;
+
+/// A move of both motors to target steps in a duration,
+/// interpolated linearly in steps by the controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PolarSegment {
+    pub central: TargetStep,
+    pub beam: TargetStep,
+    pub duration: Duration,
+
+    /// index of the motion it belongs to
+    pub motion: usize,
+
+    /// of the motion at the end of the segment, from 0 to 1
+    pub fraction: f64,
+}
+
+/// Chops the motions into polar segments whose straight lines in steps
+/// deviate at most `tolerance` millimeter from the paths.
+/// Pen delays and holds between motions become segments without steps.
+/// Central targets are unwrapped to the nearest turn, starting from `central_step`.
+/// Backlash is not compensated.
+pub fn segments(
+    motions: &[Motion],
+    central: &CentralStepper,
+    beam: &BeamStepper,
+    central_step: CurrentStep,
+    tolerance: f64,
+) -> eyre::Result<Vec<PolarSegment>> {
+    let mut chopper = Chopper {
+        central,
+        beam,
+        tolerance,
+        segments: Vec::new(),
+    };
+    let mut near = central_step.0;
+    let mut previous: Option<(Motion, [i16; 2])> = None;
+    for (index, motion) in motions.iter().enumerate() {
+        let duration = motion.path.duration()?;
+        let start = chopper.targets(motion.path, 0.0, near)?;
+        if let Some((previous, targets)) = previous {
+            let hold = motion.start.saturating_duration_since(previous.end()?);
+            chopper.hold(targets, hold, index);
+        }
+        let motion_chop = MotionChop {
+            path: motion.path,
+            duration,
+            index,
+        };
+        let end = chopper.chop(&motion_chop, 0.0, 1.0, start, 0)?;
+        near = end[0];
+        previous = Some((*motion, end));
+    }
+    Ok(chopper.segments)
+}
+
+struct Chopper<'a> {
+    central: &'a CentralStepper,
+    beam: &'a BeamStepper,
+    tolerance: f64,
+    segments: Vec<PolarSegment>,
+}
+
+struct MotionChop {
+    path: Path,
+    duration: Duration,
+    index: usize,
+}
+
+impl Chopper<'_> {
+    /// Pushes the segments from fraction `a` to `b` and returns the targets at `b`.
+    fn chop(
+        &mut self,
+        motion: &MotionChop,
+        a: f64,
+        b: f64,
+        from: [i16; 2],
+        depth: u32,
+    ) -> eyre::Result<[i16; 2]> {
+        let to = self.targets(motion.path, b, from[0])?;
+        let duration = motion.duration.mul_f64(b - a);
+        let coarse = (to[0] - from[0]).abs() > 1 || (to[1] - from[1]).abs() > 1;
+        let deviates = coarse && self.deviation(motion.path, a, b, from, to) > self.tolerance;
+        if depth < MAX_DEPTH && (duration > MAX_DURATION || deviates) {
+            let middle = (a + b) / 2.0;
+            let targets = self.chop(motion, a, middle, from, depth + 1)?;
+            return self.chop(motion, middle, b, targets, depth + 1);
+        }
+        self.segments.push(PolarSegment {
+            central: TargetStep(to[0]),
+            beam: TargetStep(to[1]),
+            duration,
+            motion: motion.index,
+            fraction: b,
+        });
+        Ok(to)
+    }
+
+    fn hold(&mut self, targets: [i16; 2], mut duration: Duration, index: usize) {
+        while !duration.is_zero() {
+            let part = duration.min(MAX_DURATION);
+            duration -= part;
+            self.segments.push(PolarSegment {
+                central: TargetStep(targets[0]),
+                beam: TargetStep(targets[1]),
+                duration: part,
+                motion: index,
+                fraction: 0.0,
+            });
+        }
+    }
+
+    /// of the central and the beam motor, the central one in the turn nearest to `near`
+    fn targets(&self, path: Path, fraction: f64, near: i16) -> eyre::Result<[i16; 2]> {
+        let point = path.interpolate(fraction);
+        let central = self.central.target_step(point)?.0;
+        let steps_per_rev = i32::from(self.central.steps_per_rev());
+        let turns = f64::from(i32::from(near) - i32::from(central)) / f64::from(steps_per_rev);
+        let central = i32::from(central) + turns.round() as i32 * steps_per_rev;
+        let central = i16::try_from(central)
+            .wrap_err_with(|| format!("failed to convert central target step {central} to i16"))?;
+        Ok([central, self.beam.target_step(point)?.0])
+    }
+
+    /// largest distance in millimeter between the path and the straight line in steps
+    fn deviation(&self, path: Path, a: f64, b: f64, from: [i16; 2], to: [i16; 2]) -> f64 {
+        let [from, to] = [from, to].map(|[central, beam]| {
+            let orientation = self.central.orientation(CurrentStep(central));
+            (orientation, self.beam.distance(CurrentStep(beam)))
+        });
+        [0.25, 0.5, 0.75]
+            .iter()
+            .map(|t| {
+                let expected = path.interpolate(a + (b - a) * t).location;
+                let orientation = from.0 + (to.0 - from.0) * t;
+                let distance = from.1 + (to.1 - from.1) * t;
+                let actual = Point2::from(vector_2::polar(distance, orientation));
+                nalgebra::distance(&expected, &actual)
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        motion::{Segment, PEN_DELAY},
+        pen::Pen,
+        stepper::Microsteps,
+    };
+    use std::time::Instant;
+
+    fn motions(points: &[(f64, f64)], velocity: f64) -> Vec<Motion> {
+        let segments = points.windows(2).map(|pair| {
+            let path = Path {
+                start: Point2::new(pair[0].0, pair[0].1),
+                end: Point2::new(pair[1].0, pair[1].1),
+                velocity,
+            };
+            Segment::new(path, Pen::Down)
+        });
+        Motion::sequence(segments, Instant::now()).unwrap()
+    }
+
+    fn chop(motions: &[Motion], tolerance: f64) -> Vec<PolarSegment> {
+        let (central, beam) = (CentralStepper::default(), BeamStepper::default());
+        segments(motions, &central, &beam, CurrentStep(0), tolerance).unwrap()
+    }
+
+    fn fine_steppers() -> (CentralStepper, BeamStepper) {
+        let microsteps = Microsteps::Sixteenth;
+        (
+            CentralStepper::with_microsteps(microsteps).unwrap(),
+            BeamStepper::with_microsteps(microsteps).unwrap(),
+        )
+    }
+
+    #[test]
+    fn radial_line_is_one_segment() {
+        let motions = motions(&[(0.0, -10.0), (0.0, -20.0)], 20.0);
+        let segments = chop(&motions, 0.1);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].beam, TargetStep(400));
+        assert_eq!(segments[0].duration, Duration::from_millis(500));
+    }
+
+    #[test]
+    fn tangential_line_stays_within_tolerance() {
+        let motions = motions(&[(-20.0, -30.0), (20.0, -30.0)], 40.0);
+        let (central, beam) = fine_steppers();
+        let segments = segments(&motions, &central, &beam, CurrentStep(0), 0.1).unwrap();
+        assert!(segments.len() > 4, "{}", segments.len());
+        let total: Duration = segments.iter().map(|s| s.duration).sum();
+        assert!((total.as_secs_f64() - 1.0).abs() < 1e-6);
+        for segment in &segments {
+            let expected = motions[0].path.interpolate(segment.fraction).location;
+            let actual = vector_2::polar(
+                beam.distance(CurrentStep(segment.beam.0)),
+                central.orientation(CurrentStep(segment.central.0)),
+            );
+            // targets are rounded to whole steps
+            assert!(nalgebra::distance(&expected, &actual.into()) < 0.1);
+        }
+    }
+
+    #[test]
+    fn long_motions_and_holds_are_split() {
+        let mut motions = motions(&[(0.0, -10.0), (0.0, -20.0), (0.0, -30.0)], 4.0);
+        motions[1].start += PEN_DELAY * 5;
+        let segments = chop(&motions, 0.1);
+        let durations: Vec<_> = segments.iter().map(|s| s.duration).collect();
+        let second = Duration::from_secs(1);
+        let part = Duration::from_millis(625);
+        let hold = Duration::from_millis(250);
+        assert_eq!(
+            durations,
+            [part, part, part, part, second, hold, part, part, part, part]
+        );
+        assert_eq!(segments[5].beam, segments[4].beam);
+        assert_eq!((segments[5].motion, segments[5].fraction), (1, 0.0));
+    }
+
+    #[test]
+    fn central_targets_unwrap_across_the_back() {
+        let motions = motions(&[(-1.0, 20.0), (1.0, 20.0)], 5.0);
+        let segments = chop(&motions, 0.1);
+        let steps_per_rev = CentralStepper::default().steps_per_rev();
+        let first = segments.first().unwrap().central.0;
+        let last = segments.last().unwrap().central.0;
+        assert!((first - last).abs() < steps_per_rev / 4, "{first} {last}");
+    }
+}
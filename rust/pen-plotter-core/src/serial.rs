@@ -0,0 +1,408 @@
+use crate::{
+    pen::Pen,
+    stepper::{CurrentStep, DeltaSteps, Microsteps, TargetStep},
+};
+use eyre::{eyre, Context};
+use serialport::SerialPort;
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+/// Starts a command frame instead of a step delta.
+/// It's followed by a command code and its argument, both as i16.
+const COMMAND: i16 = i16::MIN;
+
+const SET_MICROSTEPS: i16 = 1;
+
+const SET_PEN: i16 = 2;
+
+const STEP_BATCH: i16 = 3;
+
+const SEGMENT: i16 = 4;
+
+const SEGMENT_STATUS: i16 = 5;
+
+const CLEAR_SEGMENTS: i16 = 6;
+
+const SET_PERIOD: i16 = 7;
+
+/// of segment durations on the wire
+pub const SEGMENT_TIME_UNIT: Duration = Duration::from_micros(100);
+
+/// on the wire per step interval: a step delta and the current step in reply,
+/// each byte framed by a start and a stop bit
+pub const MESSAGE_BITS: f64 = 40.0;
+
+/// on the wire per exchange with a controller driving both motors:
+/// two step deltas and two current steps in reply
+pub const MULTI_AXIS_MESSAGE_BITS: f64 = 80.0;
+
+/// on the wire per step of full batch frames, which carry two bytes per step delta
+pub const BATCHED_STEP_BITS: f64 = 20.0;
+
+/// share of the baud rate above which [`check_bandwidth`] warns
+const MAX_LOAD: f64 = 0.8;
+
+/// A message from the host as the firmware reads it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// one delta, or one per motor for a controller driving both
+    Steps(Vec<DeltaSteps>),
+    Microsteps(i16),
+    Period(i16),
+    Pen(Pen),
+    Batch(Vec<DeltaSteps>),
+    Segment {
+        central: TargetStep,
+        beam: TargetStep,
+        duration: Duration,
+    },
+    SegmentStatus,
+    ClearSegments,
+}
+
+impl Frame {
+    /// of the response in bytes
+    pub fn response_len(&self, multi_axis: bool) -> usize {
+        match self {
+            Self::Segment { .. } | Self::SegmentStatus | Self::ClearSegments => 6,
+            Self::Steps(_) | Self::Microsteps(_) | Self::Period(_) | Self::Pen(_) if multi_axis => {
+                4
+            }
+            _ => 2,
+        }
+    }
+}
+
+/// Parses the frame at the start of `bytes`, none if it's incomplete.
+/// Returns the frame and its length in bytes.
+pub fn parse_frame(bytes: &[u8], multi_axis: bool) -> eyre::Result<Option<(Frame, usize)>> {
+    let words: Vec<i16> = bytes
+        .chunks_exact(2)
+        .map(|word| i16::from_be_bytes([word[0], word[1]]))
+        .collect();
+    let deltas = |words: &[i16]| words.iter().copied().map(DeltaSteps).collect();
+    let (frame, len) = match words[..] {
+        [] => return Ok(None),
+        [COMMAND, code, argument, ref rest @ ..] => match code {
+            SET_MICROSTEPS => (Frame::Microsteps(argument), 3),
+            SET_PERIOD => (Frame::Period(argument), 3),
+            SET_PEN if argument == 0 => (Frame::Pen(Pen::Up), 3),
+            SET_PEN => (Frame::Pen(Pen::Down), 3),
+            STEP_BATCH => {
+                let count = usize::try_from(argument)
+                    .wrap_err_with(|| format!("invalid batch size {argument}"))?;
+                match rest.get(..count) {
+                    Some(batch) => (Frame::Batch(deltas(batch)), 3 + count),
+                    None => return Ok(None),
+                }
+            }
+            SEGMENT => match rest {
+                [beam, duration, ..] => {
+                    let units = u32::from(u16::from_be_bytes(duration.to_be_bytes()));
+                    let frame = Frame::Segment {
+                        central: TargetStep(argument),
+                        beam: TargetStep(*beam),
+                        duration: SEGMENT_TIME_UNIT * units,
+                    };
+                    (frame, 5)
+                }
+                _ => return Ok(None),
+            },
+            SEGMENT_STATUS => (Frame::SegmentStatus, 3),
+            CLEAR_SEGMENTS => (Frame::ClearSegments, 3),
+            _ => return Err(eyre!("unknown command {code}")),
+        },
+        [COMMAND, ..] => return Ok(None),
+        _ => {
+            let count = if multi_axis { 2 } else { 1 };
+            match words.get(..count) {
+                Some(steps) => (Frame::Steps(deltas(steps)), count),
+                None => return Ok(None),
+            }
+        }
+    };
+    Ok(Some((frame, 2 * len)))
+}
+
+#[track_caller]
+pub fn open(path: &str, baud: u32, timeout: Duration) -> eyre::Result<Box<dyn SerialPort>> {
+    serialport::new(path, baud)
+        .timeout(timeout)
+        .open()
+        .wrap_err_with(|| format!("failed to open serial port at {:?}", path))
+}
+
+pub fn write_steps<W: Write>(mut w: W, steps: DeltaSteps) -> eyre::Result<()> {
+    w.write_all(&steps.0.to_be_bytes())
+        .wrap_err_with(|| format!("failed to write {steps:?}"))
+}
+
+/// A controller driving both motors steps each of them by its delta
+/// and responds with [`read_multi_axis_steps`].
+/// It responds to commands the same way.
+pub fn write_multi_axis_steps<W: Write>(
+    mut w: W,
+    central: DeltaSteps,
+    beam: DeltaSteps,
+) -> eyre::Result<()> {
+    let mut frame = [0; 4];
+    frame[0..2].copy_from_slice(&central.0.to_be_bytes());
+    frame[2..4].copy_from_slice(&beam.0.to_be_bytes());
+    w.write_all(&frame)
+        .wrap_err_with(|| format!("failed to write central {central:?}, beam {beam:?}"))
+}
+
+/// A controller driving both motors moves them to the target steps in `duration`
+/// after the segments before, interpolating both linearly in steps.
+/// It responds with [`read_segment_status`].
+pub fn write_segment<W: Write>(
+    mut w: W,
+    central: TargetStep,
+    beam: TargetStep,
+    duration: Duration,
+) -> eyre::Result<()> {
+    let units = (duration.as_secs_f64() / SEGMENT_TIME_UNIT.as_secs_f64()).round();
+    let units = u16::try_from(units as u64)
+        .wrap_err_with(|| format!("segment duration {duration:?} is too long"))?;
+    let mut frame = [0; 10];
+    frame[0..2].copy_from_slice(&COMMAND.to_be_bytes());
+    frame[2..4].copy_from_slice(&SEGMENT.to_be_bytes());
+    frame[4..6].copy_from_slice(&central.0.to_be_bytes());
+    frame[6..8].copy_from_slice(&beam.0.to_be_bytes());
+    frame[8..10].copy_from_slice(&units.to_be_bytes());
+    w.write_all(&frame)
+        .wrap_err_with(|| format!("failed to write segment to central {central:?}, beam {beam:?}"))
+}
+
+/// The controller responds with [`read_segment_status`].
+pub fn write_segment_status<W: Write>(w: W) -> eyre::Result<()> {
+    write_command(w, SEGMENT_STATUS, 0).wrap_err("failed to write segment status request")
+}
+
+/// The controller stops both motors where they are and drops the buffered segments.
+/// It responds with [`read_segment_status`].
+pub fn write_clear_segments<W: Write>(w: W) -> eyre::Result<()> {
+    write_command(w, CLEAR_SEGMENTS, 0).wrap_err("failed to write segment clear request")
+}
+
+/// The microcontroller steps by the deltas in order
+/// and responds once with its current step after the last one.
+pub fn write_batch<W: Write>(mut w: W, deltas: &[DeltaSteps]) -> eyre::Result<()> {
+    let count = i16::try_from(deltas.len()).wrap_err("too many step deltas for one batch")?;
+    let mut frame = Vec::with_capacity(6 + 2 * deltas.len());
+    for value in [COMMAND, STEP_BATCH, count] {
+        frame.extend(value.to_be_bytes());
+    }
+    for delta in deltas {
+        frame.extend(delta.0.to_be_bytes());
+    }
+    w.write_all(&frame)
+        .wrap_err_with(|| format!("failed to write batch of {count} step deltas"))
+}
+
+/// The microcontroller responds with its current step converted to the new microsteps.
+pub fn write_microsteps<W: Write>(w: W, microsteps: Microsteps) -> eyre::Result<()> {
+    write_command(w, SET_MICROSTEPS, microsteps.factor())
+        .wrap_err_with(|| format!("failed to write {microsteps:?}"))
+}
+
+/// The microcontroller counts steps modulo `period`, a revolution of the driven output,
+/// and responds with its current step wrapped to it.
+pub fn write_period<W: Write>(w: W, period: i16) -> eyre::Result<()> {
+    write_command(w, SET_PERIOD, period)
+        .wrap_err_with(|| format!("failed to write period of {period} steps"))
+}
+
+/// The microcontroller responds with its current step after moving the pen.
+pub fn write_pen<W: Write>(w: W, pen: Pen) -> eyre::Result<()> {
+    let argument = match pen {
+        Pen::Up => 0,
+        Pen::Down => 1,
+    };
+    write_command(w, SET_PEN, argument).wrap_err_with(|| format!("failed to write pen {pen:?}"))
+}
+
+fn write_command<W: Write>(mut w: W, code: i16, argument: i16) -> eyre::Result<()> {
+    let mut frame = [0; 6];
+    frame[0..2].copy_from_slice(&COMMAND.to_be_bytes());
+    frame[2..4].copy_from_slice(&code.to_be_bytes());
+    frame[4..6].copy_from_slice(&argument.to_be_bytes());
+    w.write_all(&frame)
+        .wrap_err_with(|| format!("failed to write command {code} with argument {argument}"))
+}
+
+pub fn read_current_step<R: Read>(mut r: R) -> eyre::Result<CurrentStep> {
+    let mut bytes = [0; 2];
+    r.read_exact(&mut bytes)
+        .wrap_err("failed to read current step")?;
+    Ok(CurrentStep(i16::from_be_bytes(bytes)))
+}
+
+/// Fails if a link of `baud` can't carry `step_bits` every `step_interval`
+/// and warns if it is close to that.
+pub fn check_bandwidth(
+    name: &str,
+    baud: u32,
+    step_interval: Duration,
+    step_bits: f64,
+) -> eyre::Result<()> {
+    let required = step_bits / step_interval.as_secs_f64();
+    let load = required / f64::from(baud);
+    if load > 1.0 {
+        return Err(eyre!(
+            "{name} needs {required:.0} baud at its maximum step rate but the link has {baud}, \
+            raise the baud rate or lower the microsteps"
+        ));
+    }
+    if load > MAX_LOAD {
+        log::warn!(
+            "{name} needs {required:.0} of {baud} baud at its maximum step rate, \
+            steps may lag behind"
+        );
+    }
+    Ok(())
+}
+
+/// of the central and the beam motor
+pub fn read_multi_axis_steps<R: Read>(mut r: R) -> eyre::Result<[CurrentStep; 2]> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)
+        .wrap_err("failed to read current steps")?;
+    Ok([
+        CurrentStep(i16::from_be_bytes([bytes[0], bytes[1]])),
+        CurrentStep(i16::from_be_bytes([bytes[2], bytes[3]])),
+    ])
+}
+
+/// Current steps of the central and the beam motor and the free slots of the segment buffer.
+pub fn read_segment_status<R: Read>(mut r: R) -> eyre::Result<([CurrentStep; 2], u16)> {
+    let steps = read_multi_axis_steps(&mut r)?;
+    let mut bytes = [0; 2];
+    r.read_exact(&mut bytes)
+        .wrap_err("failed to read free segment slots")?;
+    Ok((steps, u16::from_be_bytes(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_negative_steps() {
+        let mut buffer = Vec::new();
+        write_steps(&mut buffer, DeltaSteps(-2)).unwrap();
+        assert_eq!(buffer, [0xff, 0xfe]);
+    }
+
+    #[test]
+    fn write_microsteps_command() {
+        let mut buffer = Vec::new();
+        write_microsteps(&mut buffer, Microsteps::Sixteenth).unwrap();
+        assert_eq!(buffer, [0x80, 0x00, 0x00, 0x01, 0x00, 0x10]);
+    }
+
+    #[test]
+    fn write_pen_down_command() {
+        let mut buffer = Vec::new();
+        write_pen(&mut buffer, Pen::Down).unwrap();
+        assert_eq!(buffer, [0x80, 0x00, 0x00, 0x02, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn bandwidth_of_step_rate() {
+        // 100 steps per second need 4000 baud
+        let interval = Duration::from_millis(10);
+        check_bandwidth("central", 9600, interval, MESSAGE_BITS).unwrap();
+        check_bandwidth("central", 4800, interval, MESSAGE_BITS).unwrap();
+        let e = check_bandwidth("central", 2400, interval, MESSAGE_BITS).unwrap_err();
+        assert!(e.to_string().contains("needs 4000 baud"), "{e}");
+        check_bandwidth("central", 2400, interval, BATCHED_STEP_BITS).unwrap();
+    }
+
+    #[test]
+    fn multi_axis_round_trip() {
+        let mut buffer = Vec::new();
+        write_multi_axis_steps(&mut buffer, DeltaSteps(3), DeltaSteps(-2)).unwrap();
+        assert_eq!(buffer, [0x00, 0x03, 0xff, 0xfe]);
+        let steps = read_multi_axis_steps(&buffer[..]).unwrap();
+        assert_eq!(steps, [CurrentStep(3), CurrentStep(-2)]);
+    }
+
+    #[test]
+    fn write_segment_frame() {
+        let mut buffer = Vec::new();
+        let duration = Duration::from_millis(25);
+        write_segment(&mut buffer, TargetStep(-3), TargetStep(512), duration).unwrap();
+        assert_eq!(
+            buffer,
+            [0x80, 0x00, 0x00, 0x04, 0xff, 0xfd, 0x02, 0x00, 0x00, 0xfa]
+        );
+        let too_long = Duration::from_secs(7);
+        let _ = write_segment(&mut buffer, TargetStep(0), TargetStep(0), too_long).unwrap_err();
+    }
+
+    #[test]
+    fn parse_written_frames() {
+        let mut buffer = Vec::new();
+        write_pen(&mut buffer, Pen::Down).unwrap();
+        write_period(&mut buffer, 1200).unwrap();
+        write_batch(&mut buffer, &[DeltaSteps(4), DeltaSteps(-1)]).unwrap();
+        write_segment(
+            &mut buffer,
+            TargetStep(7),
+            TargetStep(9),
+            Duration::from_millis(2),
+        )
+        .unwrap();
+        write_clear_segments(&mut buffer).unwrap();
+        write_steps(&mut buffer, DeltaSteps(-3)).unwrap();
+        let mut frames = Vec::new();
+        let mut rest = &buffer[..];
+        while let Some((frame, len)) = parse_frame(rest, false).unwrap() {
+            frames.push(frame);
+            rest = &rest[len..];
+        }
+        assert_eq!(
+            frames,
+            [
+                Frame::Pen(Pen::Down),
+                Frame::Period(1200),
+                Frame::Batch(vec![DeltaSteps(4), DeltaSteps(-1)]),
+                Frame::Segment {
+                    central: TargetStep(7),
+                    beam: TargetStep(9),
+                    duration: Duration::from_millis(2)
+                },
+                Frame::ClearSegments,
+                Frame::Steps(vec![DeltaSteps(-3)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_incomplete_frames() {
+        let mut buffer = Vec::new();
+        write_multi_axis_steps(&mut buffer, DeltaSteps(1), DeltaSteps(2)).unwrap();
+        assert_eq!(parse_frame(&buffer[..3], true).unwrap(), None);
+        assert_eq!(
+            parse_frame(&buffer, true).unwrap(),
+            Some((Frame::Steps(vec![DeltaSteps(1), DeltaSteps(2)]), 4))
+        );
+        buffer.clear();
+        write_batch(&mut buffer, &[DeltaSteps(1), DeltaSteps(2)]).unwrap();
+        assert_eq!(parse_frame(&buffer[..8], false).unwrap(), None);
+        let _ = parse_frame(&[0x80, 0x00, 0x00, 0x63, 0x00, 0x00], false).unwrap_err();
+    }
+
+    #[test]
+    fn write_batch_frame() {
+        let mut buffer = Vec::new();
+        write_batch(&mut buffer, &[DeltaSteps(1), DeltaSteps(-1)]).unwrap();
+        assert_eq!(
+            buffer,
+            [0x80, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x01, 0xff, 0xff]
+        );
+    }
+}
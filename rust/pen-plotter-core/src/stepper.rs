@@ -0,0 +1,177 @@
+pub mod backlash;
+pub mod beam;
+pub mod central;
+
+use self::backlash::Backlash;
+use crate::path::Point;
+use eyre::eyre;
+use std::{f64::consts::TAU, str::FromStr, time::Duration};
+
+pub trait Stepper {
+    fn min_step_interval(&self) -> Duration;
+
+    fn microsteps(&self) -> Microsteps;
+
+    fn target_step(&self, target: Point) -> eyre::Result<TargetStep>;
+
+    /// whether the pen is mounted on the axis and driven by its device
+    fn carries_pen(&self) -> bool {
+        false
+    }
+
+    fn backlash(&self) -> &Backlash;
+
+    fn backlash_mut(&mut self) -> &mut Backlash;
+
+    /// Includes take-up steps when the direction reverses.
+    fn delta_steps(
+        &mut self,
+        current: CurrentStep,
+        target: TargetStep,
+    ) -> eyre::Result<DeltaSteps> {
+        let delta = DeltaSteps(target.0 - current.0);
+        Ok(self.backlash_mut().compensate(delta))
+    }
+
+    /// the step number the microcontroller reports when the motor is at `position`
+    fn reported_step(&self, position: TargetStep) -> CurrentStep {
+        self.backlash().reported_step(position)
+    }
+
+    /// the position of the motor excluding backlash compensation
+    fn position(&self, reported: CurrentStep) -> CurrentStep {
+        self.backlash().position(reported)
+    }
+
+    /// steps after which the motor is back at the same position, `None` if it never is
+    fn period(&self) -> Option<i16> {
+        None
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CurrentStep(pub i16);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DeltaSteps(pub i16);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TargetStep(pub i16);
+
+/// Microstep mode of a stepper driver.
+/// Finer modes increase the resolution but also the step rate needed for a velocity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Microsteps {
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+}
+
+impl Microsteps {
+    /// number of microsteps per full step
+    pub fn factor(self) -> i16 {
+        match self {
+            Self::Full => 1,
+            Self::Half => 2,
+            Self::Quarter => 4,
+            Self::Eighth => 8,
+            Self::Sixteenth => 16,
+        }
+    }
+}
+
+impl TryFrom<i16> for Microsteps {
+    type Error = eyre::Report;
+
+    fn try_from(factor: i16) -> eyre::Result<Self> {
+        match factor {
+            1 => Ok(Self::Full),
+            2 => Ok(Self::Half),
+            4 => Ok(Self::Quarter),
+            8 => Ok(Self::Eighth),
+            16 => Ok(Self::Sixteenth),
+            _ => Err(eyre!(
+                "invalid microstep factor {factor}, expected 1, 2, 4, 8 or 16"
+            )),
+        }
+    }
+}
+
+impl FromStr for Microsteps {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let factor: i16 = s
+            .parse()
+            .map_err(|e| eyre!("failed to parse microstep factor {s:?}: {e}"))?;
+        Self::try_from(factor)
+    }
+}
+
+/// A stepper motor and its driver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motor {
+    pub full_steps_per_rev: i16,
+    pub microsteps: Microsteps,
+
+    /// motor revolutions per revolution of the driven output
+    pub gear_ratio: f64,
+
+    /// of the motor shaft in radians per second
+    pub max_velocity: f64,
+}
+
+impl Motor {
+    pub fn min_step_interval(self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.steps_per_sec())
+    }
+
+    /// at the maximum velocity
+    pub fn steps_per_sec(self) -> f64 {
+        let rev_per_sec = self.max_velocity / TAU;
+        self.motor_steps_per_rev() * rev_per_sec
+    }
+
+    /// microsteps per revolution of the driven output
+    pub fn steps_per_rev(self) -> f64 {
+        self.motor_steps_per_rev() * self.gear_ratio
+    }
+
+    fn motor_steps_per_rev(self) -> f64 {
+        f64::from(self.full_steps_per_rev) * f64::from(self.microsteps.factor())
+    }
+}
+
+/// Rounds `steps` to a positive number of steps that fits the `i16` the devices report.
+fn step_count(steps: f64) -> eyre::Result<i16> {
+    let rounded = i16::try_from(steps.round() as i32)
+        .map_err(|_| eyre!("{steps} steps do not fit in i16"))?;
+    if rounded <= 0 {
+        return Err(eyre!("{steps} steps are not positive"));
+    }
+    Ok(rounded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bad_microsteps() {
+        let _ = "3".parse::<Microsteps>().unwrap_err();
+    }
+
+    #[test]
+    fn gear_ratio_scales_steps_per_rev() {
+        let motor = Motor {
+            full_steps_per_rev: 200,
+            microsteps: Microsteps::Quarter,
+            gear_ratio: 3.0,
+            max_velocity: TAU,
+        };
+        assert_eq!(motor.steps_per_rev(), 2400.0);
+        assert_eq!(motor.steps_per_sec(), 800.0);
+    }
+}
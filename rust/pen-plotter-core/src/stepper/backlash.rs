@@ -0,0 +1,82 @@
+use super::{CurrentStep, DeltaSteps, TargetStep};
+
+/// Takes up the play of a drive train when its direction of motion reverses.
+///
+/// The microcontroller counts the take-up steps like any other steps,
+/// so its reported step number is offset by the accumulated compensation.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Backlash {
+    /// take-up steps per direction reversal
+    steps: i16,
+
+    /// sign of the last non-zero delta, zero before the first one
+    direction: i16,
+
+    /// accumulated take-up steps included in the reported step number
+    offset: i16,
+}
+
+impl Backlash {
+    pub const fn new(steps: i16) -> Self {
+        Self {
+            steps,
+            direction: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn compensate(&mut self, delta: DeltaSteps) -> DeltaSteps {
+        if delta.0 == 0 {
+            return delta;
+        }
+        let direction = delta.0.signum();
+        let take_up = if self.direction != 0 && self.direction != direction {
+            direction * self.steps
+        } else {
+            0
+        };
+        self.direction = direction;
+        self.offset += take_up;
+        DeltaSteps(delta.0 + take_up)
+    }
+
+    /// the step number the microcontroller reports when the motor is at `position`
+    pub fn reported_step(&self, position: TargetStep) -> CurrentStep {
+        CurrentStep(position.0 + self.offset)
+    }
+
+    /// the position of the motor excluding the compensation
+    pub fn position(&self, reported: CurrentStep) -> CurrentStep {
+        CurrentStep(reported.0 - self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_delta_is_not_compensated() {
+        let mut backlash = Backlash::new(5);
+        assert_eq!(backlash.compensate(DeltaSteps(-3)), DeltaSteps(-3));
+    }
+
+    #[test]
+    fn reversal_is_compensated_once() {
+        let mut backlash = Backlash::new(5);
+        let actual = [3, 0, -2, -2, 4].map(|d| backlash.compensate(DeltaSteps(d)).0);
+        assert_eq!(actual, [3, 0, -7, -2, 9]);
+    }
+
+    #[test]
+    fn position_excludes_compensation() {
+        let mut backlash = Backlash::new(5);
+        let mut reported = 0;
+        for delta in [3, -2] {
+            reported += backlash.compensate(DeltaSteps(delta)).0;
+        }
+        assert_eq!(reported, -4);
+        assert_eq!(backlash.position(CurrentStep(reported)), CurrentStep(1));
+        assert_eq!(backlash.reported_step(TargetStep(1)), CurrentStep(reported));
+    }
+}
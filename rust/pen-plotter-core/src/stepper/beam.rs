@@ -0,0 +1,294 @@
+use super::{backlash::Backlash, step_count, CurrentStep, Microsteps, Motor, Stepper, TargetStep};
+use crate::{angle::degrees_to_radians, named::Named, path::Point};
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{ops::RangeInclusive, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeamStepper {
+    total_steps: i16,
+    range: (f64, f64),
+    min_step_interval: Duration,
+    microsteps: Microsteps,
+    backlash: Backlash,
+}
+
+/// Measured properties of the beam, missing values keep those of the [`Builder`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BeamCalibration {
+    /// in millimeter, independent of the microsteps
+    pub mm_per_full_step: Option<f64>,
+
+    /// distance of the pen from the centre at step 0 in millimeter
+    pub offset: Option<f64>,
+}
+
+impl BeamStepper {
+    pub fn with_microsteps(microsteps: Microsteps) -> eyre::Result<Self> {
+        let mut builder = Builder::default();
+        builder.motor.microsteps = microsteps;
+        builder.build()
+    }
+
+    /// Compensates `steps` of backlash on each direction reversal.
+    pub fn with_backlash(self, steps: i16) -> Self {
+        Self {
+            backlash: Backlash::new(steps),
+            ..self
+        }
+    }
+
+    /// Keeps the travel of the beam but replaces the measured properties.
+    pub fn with_calibration(self, calibration: BeamCalibration) -> eyre::Result<Self> {
+        let travel = self.range.1 - self.range.0;
+        let total_steps = match calibration.mm_per_full_step {
+            Some(mm) => {
+                let mm_per_step = mm / f64::from(self.microsteps.factor());
+                step_count(travel / mm_per_step)
+                    .wrap_err_with(|| format!("invalid beam calibration of {mm} mm per step"))?
+            }
+            None => self.total_steps,
+        };
+        let offset = calibration.offset.unwrap_or(self.range.0);
+        Ok(Self {
+            total_steps,
+            range: (offset, offset + travel),
+            ..self
+        })
+    }
+
+    pub fn steps_per_mm(&self) -> f64 {
+        f64::from(self.total_steps) / (self.range.1 - self.range.0)
+    }
+
+    /// of the pen from the centre in millimeter at `step`
+    pub fn distance(&self, step: CurrentStep) -> f64 {
+        self.range.0 + f64::from(step.0) / self.steps_per_mm()
+    }
+
+    /// of the pen from the centre in millimeter
+    pub fn range(&self) -> RangeInclusive<f64> {
+        self.range.0..=self.range.1
+    }
+}
+
+impl Named for BeamStepper {
+    fn name(&self) -> &'static str {
+        "beam stepper"
+    }
+}
+
+impl Stepper for BeamStepper {
+    fn min_step_interval(&self) -> Duration {
+        self.min_step_interval
+    }
+
+    fn microsteps(&self) -> Microsteps {
+        self.microsteps
+    }
+
+    fn carries_pen(&self) -> bool {
+        true
+    }
+
+    fn backlash(&self) -> &Backlash {
+        &self.backlash
+    }
+
+    fn backlash_mut(&mut self) -> &mut Backlash {
+        &mut self.backlash
+    }
+
+    fn target_step(&self, target: Point) -> eyre::Result<TargetStep> {
+        let target_distance = target.location.coords.magnitude();
+        if !self.range().contains(&target_distance) {
+            return Err(eyre!("invalid target distance {target_distance}"));
+        }
+        let step_distance = target_distance - self.range.0;
+        let range_distance = self.range.1 - self.range.0;
+        let step_fraction = step_distance / range_distance;
+        let total_steps = f64::from(self.total_steps);
+        let target_step = (total_steps * step_fraction).round() as i32;
+        let target_step = i16::try_from(target_step).wrap_err_with(|| {
+            format!("failed to convert target step {target_step} to i16. target: {target:?}")
+        })?;
+        Ok(TargetStep(target_step))
+    }
+}
+
+impl Default for BeamStepper {
+    fn default() -> Self {
+        Builder::default()
+            .build()
+            .expect("the default builder fits i16")
+    }
+}
+
+/// Properties of the beam and its motor, the default ones are those of our plotter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Builder {
+    /// of the pen from the centre in millimeter
+    pub range: (i16, i16),
+
+    /// in millimeter per revolution of the lead screw
+    pub lead_screw_pitch: f64,
+    pub motor: Motor,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            range: (10, 80),
+            lead_screw_pitch: 10.0,
+            motor: Motor {
+                full_steps_per_rev: 200,
+                microsteps: Microsteps::Half,
+                gear_ratio: 1.0,
+                max_velocity: degrees_to_radians(60.0),
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Fails if the steps over the range do not fit in `i16`.
+    pub fn build(self) -> eyre::Result<BeamStepper> {
+        Ok(BeamStepper {
+            total_steps: self.total_steps()?,
+            range: self.range_f64(),
+            min_step_interval: self.min_step_interval(),
+            microsteps: self.motor.microsteps,
+            backlash: Backlash::default(),
+        })
+    }
+
+    fn total_steps(self) -> eyre::Result<i16> {
+        let range_distance = f64::from(self.range.1) - f64::from(self.range.0);
+        let revs = range_distance / self.lead_screw_pitch;
+        step_count(revs * self.motor.steps_per_rev())
+            .wrap_err_with(|| format!("invalid beam range {:?}", self.range))
+    }
+
+    fn range_f64(self) -> (f64, f64) {
+        map_array_pair(self.range, Into::into)
+    }
+
+    fn min_step_interval(self) -> Duration {
+        self.motor.min_step_interval()
+    }
+}
+
+fn map_array_pair<T, U>((a, b): (T, T), mut f: impl FnMut(T) -> U) -> (U, U) {
+    (f(a), f(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point2;
+
+    #[test]
+    fn default() {
+        let expected = BeamStepper {
+            total_steps: 2800,
+            range: (10.0, 80.0),
+            min_step_interval: Duration::from_millis(15),
+            microsteps: Microsteps::Half,
+            backlash: Backlash::default(),
+        };
+        assert_eq!(BeamStepper::default(), expected);
+    }
+
+    #[test]
+    fn sixteenth_microsteps() {
+        let stepper = BeamStepper::with_microsteps(Microsteps::Sixteenth).unwrap();
+        assert_eq!(stepper.total_steps, 22400);
+        assert_eq!(stepper.min_step_interval, Duration::from_micros(1875));
+    }
+
+    #[test]
+    fn built_with_finer_lead_screw() {
+        let stepper = Builder {
+            range: (10, 40),
+            lead_screw_pitch: 2.0,
+            ..Builder::default()
+        }
+        .build()
+        .unwrap();
+        assert_eq!(stepper.total_steps, 6000);
+        assert_eq!(stepper.range(), 10.0..=40.0);
+    }
+
+    #[test]
+    fn steps_beyond_i16_fail() {
+        let builder = Builder {
+            range: (10, 400),
+            lead_screw_pitch: 1.0,
+            ..Builder::default()
+        };
+        assert!(builder.build().is_err());
+        let calibration = BeamCalibration {
+            mm_per_full_step: Some(0.001),
+            offset: None,
+        };
+        assert!(BeamStepper::default()
+            .with_calibration(calibration)
+            .is_err());
+    }
+
+    #[test]
+    fn calibrated() {
+        let calibration = BeamCalibration {
+            mm_per_full_step: Some(0.035),
+            offset: Some(12.0),
+        };
+        let stepper = BeamStepper::default()
+            .with_calibration(calibration)
+            .unwrap();
+        assert_eq!(stepper.total_steps, 4000);
+        assert_eq!(stepper.range, (12.0, 82.0));
+    }
+
+    #[test]
+    fn default_bit_rate() {
+        let message_size = 32.0;
+        let bit_rate = Builder::default().motor.steps_per_sec() * message_size;
+        assert!(bit_rate <= 9600.0, "actual = {bit_rate}");
+        assert_eq!(bit_rate, 2133.333333333333);
+    }
+
+    /// Use this instead of `BeamStepper::default()`
+    /// so your test doesn't need to be adjusted when the default changes.
+    const TEST_STEPPER: BeamStepper = BeamStepper {
+        total_steps: 1000,
+        range: (20.0, 80.0),
+        min_step_interval: Duration::from_millis(0),
+        microsteps: Microsteps::Half,
+        backlash: Backlash::new(0),
+    };
+
+    #[test]
+    fn target_step() {
+        let target = Point {
+            location: Point2::new(30.0, 40.0),
+            ..Default::default()
+        };
+        let actual = TEST_STEPPER.target_step(target).unwrap().0;
+        assert_eq!(actual, 500);
+    }
+
+    #[test]
+    fn distance_inverts_target_step() {
+        assert_eq!(TEST_STEPPER.distance(CurrentStep(500)), 50.0);
+    }
+
+    #[test]
+    fn target_out_of_reach() {
+        let target = Point {
+            location: Point2::new(100.0, 100.0),
+            ..Default::default()
+        };
+        let _ = TEST_STEPPER.target_step(target).unwrap_err();
+    }
+}
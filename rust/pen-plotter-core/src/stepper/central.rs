@@ -0,0 +1,257 @@
+use super::{
+    backlash::Backlash, step_count, CurrentStep, DeltaSteps, Microsteps, Motor, Stepper, TargetStep,
+};
+use crate::{angle::degrees_to_radians, named::Named, path::Point, vector_2};
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    f64::consts::{PI, TAU},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CentralStepper {
+    steps_per_rev: i16,
+    min_step_interval: Duration,
+    microsteps: Microsteps,
+    backlash: Backlash,
+
+    /// angle of step 0 from forward towards the right in radians
+    zero_offset: f64,
+}
+
+/// Measured properties of the central motor.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CentralCalibration {
+    /// angle of step 0 from forward towards the right in degrees
+    pub zero_offset: Option<f64>,
+}
+
+impl CentralStepper {
+    pub fn with_microsteps(microsteps: Microsteps) -> eyre::Result<Self> {
+        let mut builder = Builder::default();
+        builder.motor.microsteps = microsteps;
+        builder.build()
+    }
+
+    /// Compensates `steps` of backlash on each direction reversal.
+    pub fn with_backlash(self, steps: i16) -> Self {
+        Self {
+            backlash: Backlash::new(steps),
+            ..self
+        }
+    }
+
+    pub fn with_calibration(self, calibration: CentralCalibration) -> Self {
+        Self {
+            zero_offset: degrees_to_radians(calibration.zero_offset.unwrap_or(0.0)),
+            ..self
+        }
+    }
+
+    pub fn steps_per_rev(&self) -> i16 {
+        self.steps_per_rev
+    }
+
+    /// of the beam from forward towards the right in radians at `step`
+    pub fn orientation(&self, step: CurrentStep) -> f64 {
+        f64::from(step.0) * TAU / f64::from(self.steps_per_rev) + self.zero_offset
+    }
+}
+
+impl Named for CentralStepper {
+    fn name(&self) -> &'static str {
+        "central stepper"
+    }
+}
+
+impl Stepper for CentralStepper {
+    fn min_step_interval(&self) -> Duration {
+        self.min_step_interval
+    }
+
+    fn microsteps(&self) -> Microsteps {
+        self.microsteps
+    }
+
+    fn backlash(&self) -> &Backlash {
+        &self.backlash
+    }
+
+    /// a revolution
+    fn period(&self) -> Option<i16> {
+        Some(self.steps_per_rev)
+    }
+
+    fn backlash_mut(&mut self) -> &mut Backlash {
+        &mut self.backlash
+    }
+
+    fn target_step(&self, target: Point) -> eyre::Result<TargetStep> {
+        let steps_per_rev = f64::from(self.steps_per_rev);
+        let target_vec = target.location.coords;
+        let angle = target_vec.angle(&vector_2::forward());
+        let orientation = if target_vec.x >= 0.0 { angle } else { -angle };
+        let orientation = (orientation - self.zero_offset + PI).rem_euclid(TAU) - PI;
+        let target_step = orientation * steps_per_rev / TAU;
+        let target_step = i16::try_from(target_step.round() as i32).wrap_err_with(|| {
+            format!("failed to convert target step {target_step} to i16. target: {target:?}")
+        })?;
+        Ok(TargetStep(target_step))
+    }
+
+    fn delta_steps(
+        &mut self,
+        current: CurrentStep,
+        target: super::TargetStep,
+    ) -> eyre::Result<DeltaSteps> {
+        let delta = DeltaSteps((target.0 - current.0) % self.steps_per_rev);
+        Ok(self.backlash.compensate(delta))
+    }
+}
+
+impl Default for CentralStepper {
+    fn default() -> Self {
+        Builder::default()
+            .build()
+            .expect("the default builder fits i16")
+    }
+}
+
+/// Properties of the central motor, the default ones are those of our plotter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Builder {
+    pub motor: Motor,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            motor: Motor {
+                full_steps_per_rev: 200,
+                microsteps: Microsteps::Half,
+                gear_ratio: 1.0,
+                max_velocity: degrees_to_radians(90.0),
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Fails if the steps per revolution do not fit in `i16`.
+    pub fn build(self) -> eyre::Result<CentralStepper> {
+        Ok(CentralStepper {
+            steps_per_rev: self.steps_per_rev()?,
+            min_step_interval: self.min_step_interval(),
+            microsteps: self.motor.microsteps,
+            backlash: Backlash::default(),
+            zero_offset: 0.0,
+        })
+    }
+
+    fn steps_per_rev(self) -> eyre::Result<i16> {
+        step_count(self.motor.steps_per_rev()).wrap_err_with(|| {
+            format!(
+                "invalid central motor with a gear ratio of {}",
+                self.motor.gear_ratio
+            )
+        })
+    }
+
+    fn min_step_interval(self) -> Duration {
+        self.motor.min_step_interval()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Point2;
+
+    #[test]
+    fn nalgebra_angle_is_in_radians() {
+        let actual = vector_2::forward().angle(&vector_2::right());
+        assert_eq!(actual, TAU * 0.25);
+    }
+
+    #[test]
+    fn default_bit_rate() {
+        let message_size = 32.0;
+        let bit_rate = Builder::default().motor.steps_per_sec() * message_size;
+        assert!(bit_rate <= 9600.0, "actual = {bit_rate}");
+        assert_eq!(bit_rate, 3200.0);
+    }
+
+    #[test]
+    fn geared_steps_per_rev() {
+        let mut builder = Builder::default();
+        builder.motor.gear_ratio = 4.5;
+        builder.motor.microsteps = Microsteps::Eighth;
+        assert_eq!(builder.build().unwrap().steps_per_rev, 7200);
+        builder.motor.gear_ratio = 45.0;
+        assert!(builder.build().is_err());
+    }
+
+    const TEST_STEPPER: CentralStepper = CentralStepper {
+        steps_per_rev: 1200,
+        min_step_interval: Duration::from_millis(0),
+        microsteps: Microsteps::Half,
+        backlash: Backlash::new(0),
+        zero_offset: 0.0,
+    };
+
+    #[test]
+    fn target_step_front_right() {
+        let target = Point {
+            location: Point2::new(10.0, -10.0),
+            ..Default::default()
+        };
+        let actual = TEST_STEPPER.target_step(target).unwrap().0;
+        assert_eq!(actual, 150);
+    }
+
+    #[test]
+    fn target_step_back_right() {
+        let target = Point {
+            location: Point2::new(10.0, 10.0),
+            ..Default::default()
+        };
+        let actual = TEST_STEPPER.target_step(target).unwrap().0;
+        assert_eq!(actual, 450);
+    }
+
+    #[test]
+    fn target_step_back_left() {
+        let target = Point {
+            location: Point2::new(-10.0, 10.0),
+            ..Default::default()
+        };
+        let actual = TEST_STEPPER.target_step(target).unwrap().0;
+        assert_eq!(actual, -450);
+    }
+
+    #[test]
+    fn target_step_front_left() {
+        let target = Point {
+            location: Point2::new(-10.0, -10.0),
+            ..Default::default()
+        };
+        let actual = TEST_STEPPER.target_step(target).unwrap().0;
+        assert_eq!(actual, -150);
+    }
+
+    #[test]
+    fn target_step_with_zero_offset() {
+        let stepper = CentralStepper {
+            zero_offset: degrees_to_radians(-170.0),
+            ..TEST_STEPPER
+        };
+        let target = Point {
+            location: Point2::new(-10.0, 10.0),
+            ..Default::default()
+        };
+        let actual = stepper.target_step(target).unwrap().0;
+        assert_eq!(actual, 117);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::control::Control;
+use eyre::eyre;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalTimer {
+    /// on the plot clock of the control
+    pub start: Instant,
+    pub duration: Duration,
+    pub interval: Duration,
+
+    /// of a tick before failing, unbounded if `None`
+    pub max_lateness: Option<Duration>,
+}
+
+/// One call of [`IntervalTimer::for_each_tick`].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Tick {
+    /// elapsed of the duration on the plot clock
+    pub fraction: f64,
+
+    /// of the call after its deadline on the wall clock
+    pub lateness: Duration,
+}
+
+/// Statistics of the ticks of interval timers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub ticks: u64,
+
+    /// deadlines passed while a tick overran
+    pub missed: u64,
+    pub max_lateness: Duration,
+    pub total_lateness: Duration,
+}
+
+impl IntervalTimer {
+    /// Calls `f` every interval with the elapsed fraction of the duration on the plot clock,
+    /// which stands still while `control` is paused.
+    pub fn for_each_fraction<F>(self, control: &Control, mut f: F) -> eyre::Result<()>
+    where
+        F: FnMut(f64) -> eyre::Result<()>,
+    {
+        self.for_each_tick(control, &mut Timing::default(), |tick| f(tick.fraction))
+    }
+
+    /// Like [`IntervalTimer::for_each_fraction`] but also tells `f` how late it is called
+    /// and adds each tick to `timing`.
+    ///
+    /// The deadlines follow the clock of `control`, a fixed interval apart, starting at the first tick
+    /// and shifted by the time `control` holds the motions.
+    /// When a tick overruns, the deadlines it passed are skipped and counted as missed
+    /// so the following ticks stay on schedule.
+    pub fn for_each_tick<F>(
+        self,
+        control: &Control,
+        timing: &mut Timing,
+        mut f: F,
+    ) -> eyre::Result<()>
+    where
+        F: FnMut(Tick) -> eyre::Result<()>,
+    {
+        let clock = control.clock();
+        let mut deadline = clock.now();
+        let mut elapsed = Duration::ZERO;
+        let mut lateness = Duration::ZERO;
+        loop {
+            timing.add(lateness);
+            if let Some(max_lateness) = self.max_lateness.filter(|max| lateness > *max) {
+                return Err(eyre!(
+                    "tick {:.3} ms late, more than {:.3} ms; {timing}",
+                    millis(lateness),
+                    millis(max_lateness)
+                ));
+            }
+            let fraction = fraction(elapsed, self.duration);
+            f(Tick { fraction, lateness })?;
+            if elapsed > self.duration {
+                return Ok(());
+            }
+            deadline += self.interval + control.wait_while_held()?;
+            clock.sleep_until(deadline);
+            lateness = clock.now().saturating_duration_since(deadline);
+            let missed = lateness
+                .as_nanos()
+                .checked_div(self.interval.as_nanos())
+                .unwrap_or(0);
+            let missed = u32::try_from(missed).unwrap_or(u32::MAX);
+            timing.missed += u64::from(missed);
+            deadline += self.interval * missed;
+            elapsed = control.now().saturating_duration_since(self.start);
+        }
+    }
+}
+
+impl Timing {
+    pub fn mean_lateness(&self) -> Duration {
+        let ticks = u32::try_from(self.ticks).unwrap_or(u32::MAX);
+        self.total_lateness.checked_div(ticks).unwrap_or_default()
+    }
+
+    fn add(&mut self, lateness: Duration) {
+        self.ticks += 1;
+        self.max_lateness = self.max_lateness.max(lateness);
+        self.total_lateness += lateness;
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ticks, {} missed, lateness max {:.3} ms, mean {:.3} ms",
+            self.ticks,
+            self.missed,
+            millis(self.max_lateness),
+            millis(self.mean_lateness())
+        )
+    }
+}
+
+fn fraction(left: Duration, right: Duration) -> f64 {
+    left.as_secs_f64() / right.as_secs_f64()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+    use std::{sync::Arc, thread};
+
+    fn timer(max_lateness: Option<Duration>) -> IntervalTimer {
+        IntervalTimer {
+            start: Instant::now(),
+            duration: Duration::from_millis(50),
+            interval: Duration::from_millis(5),
+            max_lateness,
+        }
+    }
+
+    #[test]
+    fn skip_deadlines_of_overrun() {
+        let mut timing = Timing::default();
+        let mut fractions = Vec::new();
+        timer(None)
+            .for_each_tick(&Control::default(), &mut timing, |tick| {
+                if fractions.is_empty() {
+                    thread::sleep(Duration::from_millis(17));
+                }
+                fractions.push(tick.fraction);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(timing.ticks, fractions.len() as u64);
+        assert!(timing.missed >= 2, "{timing}");
+        assert!(timing.max_lateness >= Duration::from_millis(10), "{timing}");
+        assert!(timing.mean_lateness() <= timing.max_lateness);
+        assert!(*fractions.last().unwrap() > 1.0);
+    }
+
+    #[test]
+    fn fail_when_too_late() {
+        let mut timing = Timing::default();
+        let mut ticks = 0;
+        let e = timer(Some(Duration::from_millis(5)))
+            .for_each_tick(&Control::default(), &mut timing, |_| {
+                ticks += 1;
+                thread::sleep(Duration::from_millis(15));
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(ticks, 1);
+        assert!(e.to_string().contains("late"), "{e}");
+    }
+
+    #[test]
+    fn virtual_ticks_are_on_time() {
+        let start = Instant::now();
+        let control = Control::new(Arc::new(VirtualClock::default()));
+        let timer = IntervalTimer {
+            start: control.now(),
+            duration: Duration::from_secs(60),
+            ..timer(Some(Duration::ZERO))
+        };
+        let mut timing = Timing::default();
+        timer
+            .for_each_tick(&control, &mut timing, |_| Ok(()))
+            .unwrap();
+        assert_eq!(timing.ticks, 12_002);
+        assert_eq!(timing.max_lateness, Duration::ZERO);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn mean_lateness() {
+        let timing = Timing {
+            ticks: 4,
+            missed: 0,
+            max_lateness: Duration::from_millis(3),
+            total_lateness: Duration::from_millis(4),
+        };
+        assert_eq!(timing.mean_lateness(), Duration::from_millis(1));
+        assert_eq!(Timing::default().mean_lateness(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::serial;
+use eyre::{eyre, WrapErr};
+use serialport::SerialPort;
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+/// of serial ports without a baud rate in their endpoint
+pub const DEFAULT_BAUD: u32 = 9600;
+
+/// of connecting, reading and writing
+pub const TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How to open an endpoint and how long to wait for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    /// of serial ports without a baud rate in their endpoint,
+    /// and of the serial side of TCP bridges if known
+    pub baud: Option<u32>,
+
+    /// of connecting, reading and writing
+    pub timeout: Duration,
+
+    /// attempts after the first one failed
+    pub retries: u32,
+
+    /// before the first retry, doubled before each further one
+    pub backoff: Duration,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self {
+            baud: None,
+            timeout: TIMEOUT,
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Link {
+    /// Calls `f` until it succeeds or the retries are used up.
+    /// Only retry what is safe to repeat, like opening a device and setting its microsteps,
+    /// not step deltas.
+    pub fn retry<T>(&self, f: impl FnMut() -> eyre::Result<T>) -> eyre::Result<T> {
+        self.retry_when(f, |_| true)
+    }
+
+    fn retry_when<T, E: fmt::Display>(
+        &self,
+        mut f: impl FnMut() -> Result<T, E>,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.retries && retryable(&e) => {
+                    attempt += 1;
+                    log::warn!("{e:#}, retry {attempt} of {} in {backoff:?}", self.retries);
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A byte stream to the microcontroller of a motor.
+pub trait Transport: Read + Write + Send {
+    /// Reads and writes fail with [`io::ErrorKind::TimedOut`] after `timeout`.
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()> {
+        SerialPort::set_timeout(&mut **self, timeout).wrap_err("failed to set serial timeout")
+    }
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()> {
+        self.set_read_timeout(Some(timeout))
+            .and_then(|()| self.set_write_timeout(Some(timeout)))
+            .wrap_err("failed to set TCP timeout")
+    }
+}
+
+/// Where the microcontroller of a motor is connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// `serial:///dev/ttyUSB0?baud=115200` or just the path
+    Serial { path: String, baud: Option<u32> },
+
+    /// `tcp://host:port`, for example a ser2net or ESP serial bridge
+    Tcp { address: String },
+
+    /// `pty:///dev/pts/3`, a pseudoterminal like one of socat, without a baud rate
+    Pty { path: String },
+}
+
+impl FromStr for Endpoint {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let (scheme, rest) = s.split_once("://").unwrap_or(("serial", s));
+        let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if location.is_empty() {
+            return Err(eyre!("missing path or address in device {s:?}"));
+        }
+        let mut baud = None;
+        for parameter in query.split('&').filter(|p| !p.is_empty()) {
+            match parameter.split_once('=') {
+                Some(("baud", value)) if scheme == "serial" => {
+                    let value = value
+                        .parse()
+                        .wrap_err_with(|| format!("failed to parse baud rate {value:?}"))?;
+                    baud = Some(value);
+                }
+                _ => return Err(eyre!("unknown parameter {parameter:?} of device {s:?}")),
+            }
+        }
+        let location = location.to_string();
+        match scheme {
+            "serial" => Ok(Self::Serial {
+                path: location,
+                baud,
+            }),
+            "tcp" => Ok(Self::Tcp { address: location }),
+            "pty" => Ok(Self::Pty { path: location }),
+            _ => Err(eyre!(
+                "unknown scheme {scheme:?} of device {s:?}, expected serial, tcp or pty"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serial {
+                path,
+                baud: Some(baud),
+            } => write!(f, "serial://{path}?baud={baud}"),
+            Self::Serial { path, baud: None } => write!(f, "serial://{path}"),
+            Self::Tcp { address } => write!(f, "tcp://{address}"),
+            Self::Pty { path } => write!(f, "pty://{path}"),
+        }
+    }
+}
+
+impl Endpoint {
+    /// Of the serial line to the microcontroller, none if it's unknown.
+    pub fn baud(&self, link: &Link) -> Option<u32> {
+        match self {
+            Self::Serial { baud, .. } => Some(baud.or(link.baud).unwrap_or(DEFAULT_BAUD)),
+            Self::Tcp { .. } => link.baud,
+            Self::Pty { .. } => None,
+        }
+    }
+
+    /// Opens the endpoint once, see [`Link::retry`].
+    /// Its reads are retried as [`RetriedReads`].
+    pub fn open(&self, link: &Link) -> eyre::Result<Box<dyn Transport>> {
+        let baud = self.baud(link).unwrap_or(DEFAULT_BAUD);
+        Ok(match self {
+            Self::Serial { path, .. } | Self::Pty { path } => {
+                let port = serial::open(path, baud, link.timeout)?;
+                Box::new(RetriedReads::new(port, *link))
+            }
+            Self::Tcp { address } => {
+                let stream = connect(address, link.timeout)?;
+                Box::new(RetriedReads::new(stream, *link))
+            }
+        })
+    }
+}
+
+/// Repeats reads that timed out as often as the link retries them.
+///
+/// A repeated read waits longer for the same reply instead of asking again,
+/// so it is safe for step deltas too and bytes read so far are kept.
+#[derive(Debug)]
+pub struct RetriedReads<T> {
+    transport: T,
+    link: Link,
+}
+
+impl<T: Transport> RetriedReads<T> {
+    pub fn new(transport: T, link: Link) -> Self {
+        Self { transport, link }
+    }
+}
+
+impl<T: Transport> Read for RetriedReads<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let transport = &mut self.transport;
+        self.link.retry_when(
+            || transport.read(buf),
+            |e| e.kind() == io::ErrorKind::TimedOut,
+        )
+    }
+}
+
+impl<T: Transport> Write for RetriedReads<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.transport.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.transport.flush()
+    }
+}
+
+impl<T: Transport> Transport for RetriedReads<T> {
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()> {
+        self.transport.set_timeout(timeout)
+    }
+}
+
+fn connect(address: &str, timeout: Duration) -> eyre::Result<TcpStream> {
+    let mut last_error = None;
+    let addresses = address
+        .to_socket_addrs()
+        .wrap_err_with(|| format!("failed to resolve {address:?}"))?;
+    for socket_address in addresses {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(mut stream) => {
+                stream
+                    .set_nodelay(true)
+                    .wrap_err("failed to disable Nagle's algorithm")?;
+                stream.set_timeout(timeout)?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(e).wrap_err_with(|| format!("failed to connect to {address:?}")),
+        None => Err(eyre!("no address for {address:?}")),
+    }
+}
+
+/// One end of an in-memory connection, what one end writes the other one reads.
+#[derive(Debug)]
+pub struct Memory {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+    received: VecDeque<u8>,
+    timeout: Duration,
+}
+
+/// Connects two [`Memory`] transports.
+pub fn pair() -> (Memory, Memory) {
+    let (a_sender, b_receiver) = mpsc::channel();
+    let (b_sender, a_receiver) = mpsc::channel();
+    let end = |sender, receiver| Memory {
+        sender,
+        receiver,
+        received: VecDeque::new(),
+        timeout: TIMEOUT,
+    };
+    (end(a_sender, a_receiver), end(b_sender, b_receiver))
+}
+
+impl Read for Memory {
+    /// Returns 0 bytes after the other end was dropped.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.received.is_empty() {
+            match self.receiver.recv_timeout(self.timeout) {
+                Ok(bytes) => self.received.extend(bytes),
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        self.received.read(buf)
+    }
+}
+
+impl Write for Memory {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Memory {
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stepper::{CurrentStep, DeltaSteps};
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn parse_endpoints() {
+        let parse = |s: &str| s.parse::<Endpoint>().unwrap();
+        assert_eq!(
+            parse("serial:///dev/ttyUSB0?baud=115200"),
+            Endpoint::Serial {
+                path: "/dev/ttyUSB0".into(),
+                baud: Some(115200)
+            }
+        );
+        assert_eq!(
+            parse("/dev/ttyUSB1"),
+            Endpoint::Serial {
+                path: "/dev/ttyUSB1".into(),
+                baud: None
+            }
+        );
+        assert_eq!(
+            parse("tcp://127.0.0.1:5000"),
+            Endpoint::Tcp {
+                address: "127.0.0.1:5000".into()
+            }
+        );
+        assert_eq!(
+            parse("pty:///dev/pts/3"),
+            Endpoint::Pty {
+                path: "/dev/pts/3".into()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_bad_endpoints() {
+        for s in [
+            "usb:///dev/ttyUSB0",
+            "tcp://",
+            "tcp://a:1?baud=9600",
+            "/dev/x?speed=1",
+        ] {
+            let _ = s.parse::<Endpoint>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for s in ["/dev/ttyACM0", "/dev/ttyACM0?baud=57600"] {
+            let endpoint: Endpoint = s.parse().unwrap();
+            assert_eq!(endpoint.to_string().parse::<Endpoint>().unwrap(), endpoint);
+        }
+    }
+
+    #[test]
+    fn baud_of_endpoint_then_link() {
+        let link = Link {
+            baud: Some(19200),
+            ..Link::default()
+        };
+        let baud = |s: &str, link: &Link| s.parse::<Endpoint>().unwrap().baud(link);
+        assert_eq!(baud("/dev/ttyUSB0?baud=57600", &link), Some(57600));
+        assert_eq!(baud("/dev/ttyUSB0", &link), Some(19200));
+        assert_eq!(baud("/dev/ttyUSB0", &Link::default()), Some(DEFAULT_BAUD));
+        assert_eq!(baud("tcp://a:1", &link), Some(19200));
+        assert_eq!(baud("tcp://a:1", &Link::default()), None);
+        assert_eq!(baud("pty:///dev/pts/1", &link), None);
+    }
+
+    #[test]
+    fn retry_with_backoff() {
+        let link = Link {
+            retries: 2,
+            backoff: Duration::from_millis(1),
+            ..Link::default()
+        };
+        let mut attempts = 0;
+        let result = link.retry(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(eyre!("busy"))
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        attempts = 0;
+        let _ = link
+            .retry(|| -> eyre::Result<()> {
+                attempts += 1;
+                Err(eyre!("gone"))
+            })
+            .unwrap_err();
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn timed_out_reads_are_retried() {
+        let (mut host, device) = pair();
+        let link = Link {
+            retries: 1,
+            backoff: Duration::ZERO,
+            ..Link::default()
+        };
+        let mut device = RetriedReads::new(device, link);
+        device.set_timeout(Duration::from_millis(1)).unwrap();
+        let mut bytes = [0; 2];
+        let e = device.read_exact(&mut bytes).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        serial::write_steps(&mut host, DeltaSteps(7)).unwrap();
+        device.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, [0x00, 0x07]);
+    }
+
+    #[test]
+    fn memory_pair_carries_protocol() {
+        let (mut host, mut device) = pair();
+        serial::write_steps(&mut host, DeltaSteps(-3)).unwrap();
+        serial::write_steps(&mut host, DeltaSteps(5)).unwrap();
+        let mut bytes = [0; 4];
+        device.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, [0xff, 0xfd, 0x00, 0x05]);
+        device.write_all(&7_i16.to_be_bytes()).unwrap();
+        assert_eq!(
+            serial::read_current_step(&mut host).unwrap(),
+            CurrentStep(7)
+        );
+    }
+
+    #[test]
+    fn memory_read_times_out() {
+        let (mut host, _device) = pair();
+        host.set_timeout(Duration::from_millis(10)).unwrap();
+        let e = host.read(&mut [0; 2]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pty_round_trip() {
+        let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+        let endpoint = Endpoint::Pty {
+            path: slave.name().unwrap(),
+        };
+        let mut transport = endpoint.open(&Link::default()).unwrap();
+        serial::write_steps(&mut transport, DeltaSteps(-1)).unwrap();
+        let mut bytes = [0; 2];
+        master.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, [0xff, 0xff]);
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp {
+            address: listener.local_addr().unwrap().to_string(),
+        };
+        let echo = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut bytes = [0; 2];
+            stream.read_exact(&mut bytes).unwrap();
+            stream.write_all(&bytes).unwrap();
+        });
+        let mut transport = endpoint.open(&Link::default()).unwrap();
+        serial::write_steps(&mut transport, DeltaSteps(300)).unwrap();
+        let step = serial::read_current_step(&mut transport).unwrap();
+        assert_eq!(step, CurrentStep(300));
+        echo.join().unwrap();
+    }
+}
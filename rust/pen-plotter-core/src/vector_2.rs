@@ -0,0 +1,34 @@
+use nalgebra::Vector2;
+
+pub fn right() -> Vector2<f64> {
+    Vector2::new(1.0, 0.0)
+}
+
+pub fn left() -> Vector2<f64> {
+    -right()
+}
+
+pub fn back() -> Vector2<f64> {
+    Vector2::new(0.0, 1.0)
+}
+
+pub fn forward() -> Vector2<f64> {
+    -back()
+}
+
+/// `orientation` from forward towards the right in radians
+pub fn polar(length: f64, orientation: f64) -> Vector2<f64> {
+    (forward() * orientation.cos() + right() * orientation.sin()) * length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn polar_right() {
+        let actual = polar(2.0, FRAC_PI_2);
+        assert!((actual - Vector2::new(2.0, 0.0)).magnitude() < 1e-12);
+    }
+}
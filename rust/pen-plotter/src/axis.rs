@@ -0,0 +1,70 @@
+use crate::{
+    device::Device,
+    named::Named,
+    pen::Pen,
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep, Stepper},
+    vector_2,
+};
+use nalgebra::Point2;
+
+/// A stepper with the device driving it and the last reported position.
+pub struct Axis<S> {
+    pub stepper: S,
+    pub device: Box<dyn Device>,
+
+    /// excluding backlash compensation
+    pub step: CurrentStep,
+
+    /// `None` until the pen is set the first time
+    pub pen: Option<Pen>,
+}
+
+impl<S: Stepper> Axis<S> {
+    /// Starts at the step number the device reports.
+    pub fn new(stepper: S, mut device: Box<dyn Device>) -> eyre::Result<Self> {
+        let step = stepper.position(device.flush()?);
+        Ok(Self {
+            stepper,
+            device,
+            step,
+            pen: None,
+        })
+    }
+
+    /// Asks the device for its step number once it took all steps,
+    /// for example after an interrupted move.
+    pub fn read_back(&mut self) -> eyre::Result<CurrentStep> {
+        self.step = self.stepper.position(self.device.flush()?);
+        Ok(self.step)
+    }
+}
+
+impl<S: Stepper + Named> Axis<S> {
+    pub fn set_pen(&mut self, pen: Pen) -> eyre::Result<()> {
+        let reported_step = self.device.set_pen(pen)?;
+        self.step = self.stepper.position(reported_step);
+        self.pen = Some(pen);
+        log::info!("{}: pen {pen:?}", self.stepper.name());
+        Ok(())
+    }
+
+    /// Raises the pen if this axis lowered it.
+    pub fn lift_pen(&mut self) -> eyre::Result<()> {
+        if self.pen == Some(Pen::Down) {
+            self.set_pen(Pen::Up)?;
+        }
+        Ok(())
+    }
+}
+
+/// of the pen in millimeter at the given positions of the axes
+pub fn location(
+    central: &CentralStepper,
+    central_step: CurrentStep,
+    beam: &BeamStepper,
+    beam_step: CurrentStep,
+) -> Point2<f64> {
+    let distance = beam.distance(beam_step);
+    let orientation = central.orientation(central_step);
+    vector_2::polar(distance, orientation).into()
+}
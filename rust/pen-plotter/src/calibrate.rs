@@ -0,0 +1,222 @@
+use crate::{
+    config::{BeamCalibration, CentralCalibration, Config},
+    control::Control,
+    device::Device,
+    stepper::{CurrentStep, DeltaSteps, Microsteps},
+};
+use eyre::{eyre, WrapErr};
+use std::{
+    io::{BufRead, Write},
+    sync::Arc,
+};
+
+/// Guides the operator through measuring the beam and the central motor.
+/// Each measurement jogs a motor by the steps entered in `input`.
+pub struct Wizard<R, W> {
+    pub input: R,
+    pub output: W,
+    pub microsteps: Microsteps,
+    pub central_steps_per_rev: i16,
+
+    /// checked before jogging and after every input line
+    pub control: Arc<Control>,
+}
+
+impl<R: BufRead, W: Write> Wizard<R, W> {
+    pub fn run(
+        &mut self,
+        config: Config,
+        central: &mut dyn Device,
+        beam: &mut dyn Device,
+    ) -> eyre::Result<Config> {
+        writeln!(
+            self.output,
+            "Enter steps to jog a motor, for example 10 or -25, and an empty line when done."
+        )?;
+        writeln!(
+            self.output,
+            "Jog the beam until the pen is above the first reference point."
+        )?;
+        let first = self.jog(beam)?;
+        writeln!(self.output, "Mark the first reference point.")?;
+        writeln!(
+            self.output,
+            "Jog the beam outwards to the second reference point."
+        )?;
+        let second = self.jog(beam)?;
+        writeln!(self.output, "Mark the second reference point.")?;
+        let distance = self.ask_mm("Distance between the reference points in mm: ")?;
+        let radius =
+            self.ask_mm("Distance of the first reference point from the centre in mm: ")?;
+        writeln!(
+            self.output,
+            "Jog the central motor until the beam points forward."
+        )?;
+        let forward = self.jog(central)?;
+        let mut config = config;
+        config.beam = beam_calibration([first, second], distance, radius, self.microsteps)?;
+        config.central = central_calibration(forward, self.central_steps_per_rev);
+        Ok(config)
+    }
+
+    fn jog(&mut self, device: &mut dyn Device) -> eyre::Result<CurrentStep> {
+        let mut step = device.step(DeltaSteps(0))?;
+        loop {
+            write!(self.output, "at step {} > ", step.0)?;
+            self.output.flush()?;
+            let line = self.read_line()?;
+            if line.is_empty() {
+                return Ok(step);
+            }
+            match line.parse() {
+                Ok(delta) => {
+                    self.control.wait_while_held()?;
+                    step = device.step(DeltaSteps(delta))?
+                }
+                Err(e) => writeln!(self.output, "invalid steps {line:?}: {e}")?,
+            }
+        }
+    }
+
+    fn ask_mm(&mut self, question: &str) -> eyre::Result<f64> {
+        loop {
+            write!(self.output, "{question}")?;
+            self.output.flush()?;
+            let line = self.read_line()?;
+            match line.parse::<f64>() {
+                Ok(mm) if mm.is_finite() => return Ok(mm),
+                Ok(_) => writeln!(self.output, "{line:?} is not finite")?,
+                Err(e) => writeln!(self.output, "invalid millimeter {line:?}: {e}")?,
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> eyre::Result<String> {
+        let mut line = String::new();
+        let n = self
+            .input
+            .read_line(&mut line)
+            .wrap_err("failed to read input")?;
+        if n == 0 {
+            return Err(eyre!("input ended before the calibration was done"));
+        }
+        self.control.wait_while_held()?;
+        Ok(line.trim().to_owned())
+    }
+}
+
+/// `steps` of the reference points, the second one further outwards.
+fn beam_calibration(
+    steps: [CurrentStep; 2],
+    distance: f64,
+    first_radius: f64,
+    microsteps: Microsteps,
+) -> eyre::Result<BeamCalibration> {
+    let [first, second] = steps.map(|s| f64::from(s.0));
+    if second <= first || distance <= 0.0 {
+        return Err(eyre!(
+            "the second reference point must be outwards of the first one, \
+            steps: {first} -> {second}, distance: {distance} mm"
+        ));
+    }
+    let mm_per_step = distance / (second - first);
+    Ok(BeamCalibration {
+        mm_per_full_step: Some(mm_per_step * f64::from(microsteps.factor())),
+        offset: Some(first_radius - first * mm_per_step),
+    })
+}
+
+/// `forward` is the step at which the beam points forward.
+fn central_calibration(forward: CurrentStep, steps_per_rev: i16) -> CentralCalibration {
+    let degrees_per_step = 360.0 / f64::from(steps_per_rev);
+    CentralCalibration {
+        zero_offset: Some(-f64::from(forward.0) * degrees_per_step),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{control::Aborted, device::SimulatedDevice};
+
+    #[test]
+    fn beam_offset_and_scale() {
+        let steps = [CurrentStep(200), CurrentStep(1000)];
+        let actual = beam_calibration(steps, 20.0, 15.0, Microsteps::Half).unwrap();
+        let expected = BeamCalibration {
+            mm_per_full_step: Some(0.05),
+            offset: Some(10.0),
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn beam_reference_points_reversed() {
+        let steps = [CurrentStep(1000), CurrentStep(200)];
+        let _ = beam_calibration(steps, 20.0, 15.0, Microsteps::Half).unwrap_err();
+    }
+
+    #[test]
+    fn central_zero_offset() {
+        let actual = central_calibration(CurrentStep(-10), 400);
+        assert_eq!(actual.zero_offset, Some(9.0));
+    }
+
+    #[test]
+    fn wizard() {
+        let input = "200\n\nx\n800\n\n20\n15\n-10\n\n";
+        let mut output = Vec::new();
+        let mut wizard = Wizard {
+            input: input.as_bytes(),
+            output: &mut output,
+            microsteps: Microsteps::Half,
+            central_steps_per_rev: 400,
+            control: Arc::default(),
+        };
+        let mut central = SimulatedDevice::default();
+        let mut beam = SimulatedDevice::default();
+        let config = wizard
+            .run(Config::default(), &mut central, &mut beam)
+            .unwrap();
+        assert_eq!(config.beam.offset, Some(10.0));
+        assert_eq!(config.central.zero_offset, Some(9.0));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("invalid steps \"x\""), "{output}");
+    }
+
+    #[test]
+    fn wizard_stops_after_abort() {
+        let control = Arc::new(Control::default());
+        control.abort();
+        let mut wizard = Wizard {
+            input: "200\n".as_bytes(),
+            output: Vec::new(),
+            microsteps: Microsteps::Half,
+            central_steps_per_rev: 400,
+            control,
+        };
+        let mut central = SimulatedDevice::default();
+        let mut beam = SimulatedDevice::default();
+        let e = wizard
+            .run(Config::default(), &mut central, &mut beam)
+            .unwrap_err();
+        assert!(e.is::<Aborted>(), "{e:?}");
+        assert_eq!(beam.step, CurrentStep(0));
+    }
+
+    #[test]
+    fn wizard_input_ends_early() {
+        let mut wizard = Wizard {
+            input: "200\n".as_bytes(),
+            output: Vec::new(),
+            microsteps: Microsteps::Half,
+            central_steps_per_rev: 400,
+            control: Arc::default(),
+        };
+        let mut central = SimulatedDevice::default();
+        let mut beam = SimulatedDevice::default();
+        let _ = wizard
+            .run(Config::default(), &mut central, &mut beam)
+            .unwrap_err();
+    }
+}
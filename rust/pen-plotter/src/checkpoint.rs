@@ -0,0 +1,345 @@
+use crate::{
+    executor::Progress,
+    job::{Job, Layer, MIN_SEGMENT_LENGTH},
+    motion::Segment,
+    path::Path,
+    pen::{Pen, PenNumber},
+    stepper::CurrentStep,
+    tool::Tools,
+};
+use eyre::WrapErr;
+use nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{self, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// between writes while plotting
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// Progress of a job, saved while plotting so it can be resumed after a failure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// of the job, after translating to the work origin
+    pub layers: Vec<SavedLayer>,
+
+    /// where the job started, the first segment travels from there
+    pub start: [f64; 2],
+
+    pub tools: Tools,
+
+    /// index of the segment in progress
+    pub segment: usize,
+
+    /// of the segment in progress, from 0 to 1
+    pub fraction: f64,
+
+    /// last reported by the devices
+    pub central_step: i16,
+    pub beam_step: i16,
+}
+
+/// Strokes of a pen in millimeter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedLayer {
+    pub pen: PenNumber,
+    pub strokes: Vec<Vec<[f64; 2]>>,
+}
+
+/// Segments to execute with the position in the job of each of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub checkpoint: Checkpoint,
+    pub segments: Vec<Segment>,
+
+    /// index and fraction in the job of the start of the first segment
+    first: (usize, f64),
+
+    /// whether the first segment is a travel to the resumed position
+    travel: bool,
+}
+
+impl Checkpoint {
+    pub fn new(job: &Job, start: Point2<f64>, tools: Tools) -> Self {
+        let layers = job
+            .layers
+            .iter()
+            .map(|l| SavedLayer {
+                pen: l.pen,
+                strokes: l
+                    .strokes
+                    .iter()
+                    .map(|s| s.iter().map(|p| [p.x, p.y]).collect())
+                    .collect(),
+            })
+            .collect();
+        Self {
+            layers,
+            start: [start.x, start.y],
+            tools,
+            segment: 0,
+            fraction: 0.0,
+            central_step: 0,
+            beam_step: 0,
+        }
+    }
+
+    pub fn load(path: &path::Path) -> eyre::Result<Self> {
+        let text = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read checkpoint {path:?}"))?;
+        let checkpoint: Self = serde_json::from_str(&text)
+            .wrap_err_with(|| format!("failed to parse checkpoint {path:?}"))?;
+        checkpoint
+            .tools
+            .validate()
+            .wrap_err_with(|| format!("invalid checkpoint {path:?}"))?;
+        Ok(checkpoint)
+    }
+
+    /// Replaces the file at once so a crash leaves either the old or the new checkpoint.
+    pub fn save(&self, path: &path::Path) -> eyre::Result<()> {
+        let text = serde_json::to_string(self).wrap_err("failed to serialize checkpoint")?;
+        let mut temporary = PathBuf::from(path);
+        temporary.set_extension("tmp");
+        fs::write(&temporary, text)
+            .and_then(|()| fs::rename(&temporary, path))
+            .wrap_err_with(|| format!("failed to write checkpoint {path:?}"))
+    }
+
+    /// Ignores a missing file.
+    pub fn remove(path: &path::Path) -> eyre::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).wrap_err_with(|| format!("failed to remove checkpoint {path:?}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn job(&self) -> Job {
+        let layers = self
+            .layers
+            .iter()
+            .map(|l| Layer {
+                pen: l.pen,
+                strokes: l
+                    .strokes
+                    .iter()
+                    .map(|s| s.iter().map(|&p| Point2::from(p)).collect())
+                    .collect(),
+            })
+            .collect();
+        Job { layers }
+    }
+
+    /// All segments of the job.
+    pub fn segments(&self) -> Vec<Segment> {
+        self.job().segments(self.start.into(), &self.tools)
+    }
+
+    /// Plots the whole job.
+    pub fn plan(self) -> Plan {
+        Plan {
+            segments: self.segments(),
+            checkpoint: self,
+            first: (0, 0.0),
+            travel: false,
+        }
+    }
+
+    /// Travels with the pen up from `location` to the checkpoint and plots the rest of the job.
+    /// A tool change of the segment in progress is repeated unless it had started to move.
+    pub fn resume(self, location: Point2<f64>) -> Plan {
+        let all = self.segments();
+        let mut segments = Vec::new();
+        let mut first = (self.segment, self.fraction);
+        let mut travel = false;
+        if let Some(current) = all.get(self.segment) {
+            let resumed = current.path.interpolate(self.fraction).location;
+            let path = Path {
+                start: location,
+                end: resumed,
+                velocity: current.path.velocity,
+            };
+            travel = push(&mut segments, Segment::new(path, Pen::Up));
+            let rest = Segment {
+                path: Path {
+                    start: resumed,
+                    ..current.path
+                },
+                tool_change: current.tool_change.filter(|_| self.fraction == 0.0),
+                ..*current
+            };
+            if !push(&mut segments, rest) {
+                first = (self.segment + 1, 0.0);
+            }
+            segments.extend_from_slice(&all[self.segment + 1..]);
+        }
+        Plan {
+            segments,
+            checkpoint: self,
+            first,
+            travel,
+        }
+    }
+}
+
+/// Skips paths too short to time, returns whether `segment` was pushed.
+fn push(segments: &mut Vec<Segment>, segment: Segment) -> bool {
+    let path = &segment.path;
+    let long_enough = nalgebra::distance(&path.start, &path.end) >= MIN_SEGMENT_LENGTH;
+    if long_enough {
+        segments.push(segment);
+    }
+    long_enough
+}
+
+impl Plan {
+    /// Converts the index and fraction of an executed segment to the segment of the job.
+    pub fn position(&self, index: usize, fraction: f64) -> (usize, f64) {
+        let (segment, start) = self.first;
+        match index.checked_sub(usize::from(self.travel)) {
+            None => (segment, start),
+            Some(0) => (segment, start + fraction * (1.0 - start)),
+            Some(i) => (segment + i, fraction),
+        }
+    }
+
+    /// Updates the checkpoint from the progress of the executed segments.
+    pub fn update(&mut self, progress: &Progress) {
+        let (index, fraction) = progress.motion();
+        let (segment, fraction) = self.position(index, fraction);
+        let (central, beam) = progress.steps();
+        self.checkpoint.segment = segment;
+        self.checkpoint.fraction = fraction;
+        self.checkpoint.central_step = central.0;
+        self.checkpoint.beam_step = beam.0;
+    }
+
+    /// Saves the checkpoint every [`INTERVAL`] until `done`.
+    pub fn save_periodically(&mut self, progress: &Progress, path: &path::Path, done: &AtomicBool) {
+        while !done.load(Ordering::SeqCst) {
+            thread::park_timeout(INTERVAL);
+            self.update(progress);
+            if let Err(e) = self.checkpoint.save(path) {
+                log::warn!("{e:?}");
+            }
+        }
+    }
+
+    /// Removes the checkpoint after finishing or saves it with the final steps otherwise.
+    pub fn finish(
+        &mut self,
+        result: &eyre::Result<()>,
+        progress: &Progress,
+        steps: (CurrentStep, CurrentStep),
+        path: &path::Path,
+    ) -> eyre::Result<()> {
+        if result.is_ok() {
+            return Checkpoint::remove(path);
+        }
+        self.update(progress);
+        self.checkpoint.central_step = steps.0 .0;
+        self.checkpoint.beam_step = steps.1 .0;
+        self.checkpoint.save(path)?;
+        log::info!(
+            "saved checkpoint at segment {} of {} to {path:?}, continue with the resume subcommand",
+            self.checkpoint.segment,
+            self.checkpoint.segments().len(),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    fn checkpoint() -> Checkpoint {
+        let job = Job::new(vec![vec![
+            Point2::new(0.0, -10.0),
+            Point2::new(0.0, -20.0),
+            Point2::new(10.0, -20.0),
+        ]]);
+        Checkpoint::new(&job, Point2::new(0.0, -10.0), Tools::new(1.0))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!(
+            "pen-plotter-checkpoint-test-{}.json",
+            process::id()
+        ));
+        let checkpoint = Checkpoint {
+            segment: 1,
+            fraction: 0.25,
+            ..checkpoint()
+        };
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+        Checkpoint::remove(&path).unwrap();
+        Checkpoint::remove(&path).unwrap();
+    }
+
+    #[test]
+    fn resume_travels_to_checkpoint() {
+        let checkpoint = Checkpoint {
+            segment: 1,
+            fraction: 0.25,
+            ..checkpoint()
+        };
+        let plan = checkpoint.resume(Point2::new(0.0, -15.0));
+        let paths: Vec<_> = plan
+            .segments
+            .iter()
+            .map(|s| (s.path.start, s.path.end, s.pen))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (Point2::new(0.0, -15.0), Point2::new(2.5, -20.0), Pen::Up),
+                (Point2::new(2.5, -20.0), Point2::new(10.0, -20.0), Pen::Down),
+            ]
+        );
+        assert_eq!(plan.position(0, 0.5), (1, 0.25));
+        assert_eq!(plan.position(1, 0.0), (1, 0.25));
+        assert_eq!(plan.position(1, 1.0), (1, 1.0));
+    }
+
+    #[test]
+    fn resume_at_checkpoint_skips_travel() {
+        let checkpoint = Checkpoint {
+            segment: 0,
+            fraction: 0.5,
+            ..checkpoint()
+        };
+        let plan = checkpoint.resume(Point2::new(0.0, -15.0));
+        assert_eq!(plan.segments.len(), 2);
+        assert_eq!(plan.position(0, 0.5), (0, 0.75));
+        assert_eq!(plan.position(1, 0.5), (1, 0.5));
+    }
+
+    #[test]
+    fn resume_after_end_of_segment() {
+        let checkpoint = Checkpoint {
+            segment: 0,
+            fraction: 1.0,
+            ..checkpoint()
+        };
+        let plan = checkpoint.resume(Point2::new(0.0, -20.0));
+        assert_eq!(plan.segments.len(), 1);
+        assert_eq!(plan.position(0, 0.5), (1, 0.5));
+    }
+
+    #[test]
+    fn plan_maps_segments_one_to_one() {
+        let plan = checkpoint().plan();
+        assert_eq!(plan.segments.len(), 2);
+        assert_eq!(plan.position(1, 0.5), (1, 0.5));
+    }
+}
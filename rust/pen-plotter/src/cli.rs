@@ -1,36 +1,409 @@
-use clap::Parser;
+use crate::{executor::OnMismatch, fill::Pattern, stepper::Microsteps, transport::Endpoint};
+use clap::{Parser, Subcommand};
 use eyre::{eyre, WrapErr};
 use nalgebra::Point2;
+use std::path::PathBuf;
+
+const OPTIMIZE_HELP: &str = "reorder, reverse and merge strokes to shorten the pen-up travel";
+
+const DEVICE_HELP: &str = "of the stepper motor: a serial port path, serial://PATH?baud=BAUD, \
+    tcp://HOST:PORT or pty://PATH";
 
 const POINT_HELP: &str = "2D point in millimeter, for example (3,7) or (3.0,7.0)";
 
 #[derive(Debug, Parser)]
+#[clap(subcommand_negates_reqs = true)]
 pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(short, long, help = POINT_HELP, parse(try_from_str = parse_point), default_value = "(0,-10)")]
     pub start: Point2<f64>,
 
-    #[clap(short, long, help = POINT_HELP, parse(try_from_str = parse_point))]
-    pub end: Point2<f64>,
+    #[clap(short, long, help = POINT_HELP, parse(try_from_str = parse_point), required = true)]
+    pub end: Option<Point2<f64>>,
 
-    #[clap(long, help = "in mm/s", default_value = "1.2")]
+    #[clap(long, global = true, help = "in mm/s", default_value = "1.2")]
     pub velocity: f64,
 
-    #[clap(long, help = "use this flag to perform I/O to the motors")]
+    #[clap(
+        long,
+        global = true,
+        help = "largest deviation in mm when flattening curves and simplifying strokes, \
+            defaults to the distance of one motor step at each point",
+        parse(try_from_str = parse_tolerance)
+    )]
+    pub tolerance: Option<f64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "fills closed SVG shapes unless their fill is none: \
+            hatch, cross-hatch, concentric or polar"
+    )]
+    pub fill: Option<Pattern>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "between fill lines in mm",
+        parse(try_from_str = parse_spacing),
+        default_value = "0.5"
+    )]
+    pub fill_spacing: f64,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of hatch lines in degrees counterclockwise from the x axis",
+        default_value = "45"
+    )]
+    pub fill_angle: f64,
+
+    #[clap(
+        long,
+        global = true,
+        help = "microstep factor of the motor drivers: 1, 2, 4, 8 or 16",
+        default_value = "2"
+    )]
+    pub microsteps: Microsteps,
+
+    #[clap(
+        long,
+        global = true,
+        help = "steps added when the central stepper motor reverses",
+        default_value = "0"
+    )]
+    pub central_backlash: i16,
+
+    #[clap(
+        long,
+        global = true,
+        help = "steps added when the beam stepper motor reverses",
+        default_value = "0"
+    )]
+    pub beam_backlash: i16,
+
+    #[clap(
+        long,
+        global = true,
+        help = "calibration file written by the calibrate subcommand",
+        default_value = "pen-plotter.toml"
+    )]
+    pub config: PathBuf,
+
+    #[clap(
+        long,
+        global = true,
+        help = "where the motors stopped, written after plotting",
+        default_value = "pen-plotter-position.toml"
+    )]
+    pub position: PathBuf,
+
+    #[clap(
+        long,
+        global = true,
+        help = "progress of the running job, read by the resume subcommand",
+        default_value = "pen-plotter-checkpoint.json"
+    )]
+    pub checkpoint: PathBuf,
+
+    #[clap(
+        long,
+        global = true,
+        help = "use this flag to perform I/O to the motors"
+    )]
     pub io: bool,
 
     #[clap(
         long,
-        help = "device path for the central stepper motor",
+        global = true,
+        conflicts_with = "io",
+        help = "simulate plots on a virtual clock, as fast as the steps can be computed"
+    )]
+    pub virtual_clock: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = DEVICE_HELP,
         default_value = "/dev/ttyUSB0"
     )]
-    pub central: String,
+    pub central: Endpoint,
 
     #[clap(
         long,
-        help = "device path for the beam stepper motor",
+        global = true,
+        help = DEVICE_HELP,
         default_value = "/dev/ttyUSB1"
     )]
-    pub beam: String,
+    pub beam: Endpoint,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of one microcontroller driving both stepper motors, in place of --central and --beam",
+        conflicts_with_all = &["central", "beam", "batch"],
+        requires = "io"
+    )]
+    pub device: Option<Endpoint>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of serial devices without one, and of the serial side of TCP bridges; \
+            overrides the config, defaults to 9600"
+    )]
+    pub baud: Option<u32>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of connecting, reading and writing in seconds; overrides the config, defaults to 4",
+        parse(try_from_str = parse_timeout)
+    )]
+    pub timeout: Option<f64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of opening a device and of reads that timed out; overrides the config, \
+            defaults to 2"
+    )]
+    pub retries: Option<u32>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "before the first retry in seconds, doubled for each further one; \
+            overrides the config, defaults to 0.5",
+        parse(try_from_str = parse_backoff)
+    )]
+    pub backoff: Option<f64>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "send step deltas in batch frames without waiting for each reply, \
+            needs firmware support"
+    )]
+    pub batch: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "send polar line segments the controller interpolates instead of step deltas, \
+            needs --device and firmware support",
+        requires_all = &["device", "io"]
+    )]
+    pub segments: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "largest deviation in mm of the segments from the path",
+        parse(try_from_str = parse_chord_error),
+        default_value = "0.05"
+    )]
+    pub chord_error: f64,
+
+    #[clap(
+        long,
+        global = true,
+        help = "JSON lines file to record every byte sent to and received from the devices to",
+        parse(from_os_str)
+    )]
+    pub record: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "file to write a sample of every interval of both axes to while plotting or serving, \
+            as CSV if it ends with .csv and as JSON lines otherwise",
+        parse(from_os_str)
+    )]
+    pub telemetry: Option<PathBuf>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "of an interval after its deadline in seconds before the plot fails; unbounded by default",
+        parse(try_from_str = parse_max_lateness)
+    )]
+    pub max_lateness: Option<f64>,
+
+    #[clap(
+        long,
+        global = true,
+        default_value = "halt",
+        help = "when a device reports another step than it was sent to: \
+            warn, correct by sending the difference again, or halt"
+    )]
+    pub on_mismatch: OnMismatch,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Draws strokes reversing each motor to measure their backlash
+    ///
+    /// Measure the gaps between the ends of outgoing and returning strokes
+    /// without backlash compensation.
+    /// The radial strokes measure the beam, the tangential strokes the central motor.
+    BacklashPattern,
+
+    /// Measures the beam and the central motor interactively
+    ///
+    /// Jog the motors to reference points and enter their measured distances.
+    /// The results are written to the config file.
+    Calibrate,
+
+    /// Moves the pen with the keyboard
+    ///
+    /// Arrow keys move along the axes, a/d rotate the beam and w/s extend or retract it.
+    /// t toggles the step size, o saves the current location as work origin
+    /// to the config file and q quits.
+    Jog,
+
+    /// Plots a job file
+    ///
+    /// The format is chosen by the file extension: .json for an array of strokes
+    /// of [x, y] points, .gcode, .nc or .g for G-code, .hpgl, .hpg or .plt for HP-GL
+    /// and .svg for SVG.
+    Plot {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+
+        #[clap(long, help = OPTIMIZE_HELP)]
+        optimize: bool,
+    },
+
+    /// Reports the lengths, pen lifts, duration and motor step rates of a job file
+    ///
+    /// Nothing is sent to the motors. The job starts where the motors are at step 0
+    /// and is timed like the plot subcommand would plot it.
+    Estimate {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+
+        #[clap(long, help = OPTIMIZE_HELP)]
+        optimize: bool,
+    },
+
+    /// Writes text in a single-stroke font
+    ///
+    /// The text starts at --at and is rotated by --angle.
+    /// With --radius it runs clockwise along a circle about the centre of the plotter
+    /// from --angle instead, the letters standing on the circle.
+    Text {
+        text: String,
+
+        #[clap(long, help = "of capitals in mm", parse(try_from_str = parse_size), default_value = "5")]
+        size: f64,
+
+        #[clap(long, help = POINT_HELP, parse(try_from_str = parse_point), default_value = "(0,-20)")]
+        at: Point2<f64>,
+
+        #[clap(
+            long,
+            help = "in degrees counterclockwise from the x axis",
+            default_value = "0"
+        )]
+        angle: f64,
+
+        #[clap(
+            long,
+            help = "of the circle about the centre of the plotter in mm",
+            parse(try_from_str = parse_radius)
+        )]
+        radius: Option<f64>,
+
+        #[clap(long, help = OPTIMIZE_HELP)]
+        optimize: bool,
+    },
+
+    /// Replays a recording of --record through simulated devices
+    ///
+    /// Compares the responses of the recorded devices with those of the simulated firmware
+    /// and prints where the devices reported the axes as CSV:
+    /// time, central step, beam step, pen and the x and y location in mm.
+    Replay {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Continues an interrupted job from its checkpoint
+    ///
+    /// Travels with the pen up to where the job stopped and plots the rest of it
+    /// with the velocity it was started with.
+    /// The checkpoint is removed when the job finishes.
+    Resume,
+
+    /// Plots jobs submitted over HTTP one after another
+    ///
+    /// POST /jobs queues a job, GET /jobs lists them, GET and DELETE /jobs/{id}
+    /// query and cancel one. GET /status reports the pen location and progress,
+    /// POST /pause and /resume hold and release the queue.
+    Serve {
+        #[clap(
+            long,
+            help = "address of the HTTP server",
+            default_value = "127.0.0.1:8080"
+        )]
+        listen: String,
+
+        #[clap(long, help = OPTIMIZE_HELP)]
+        optimize: bool,
+    },
+}
+
+fn parse_tolerance(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "tolerance")
+}
+
+fn parse_timeout(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "timeout")
+}
+
+fn parse_backoff(s: &str) -> eyre::Result<f64> {
+    let value: f64 = s
+        .parse()
+        .wrap_err_with(|| format!("failed to parse backoff {s:?}"))?;
+    if value >= 0.0 {
+        Ok(value)
+    } else {
+        Err(eyre!("backoff {s:?} must not be negative"))
+    }
+}
+
+fn parse_max_lateness(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "max lateness")
+}
+
+fn parse_chord_error(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "chord error")
+}
+
+fn parse_size(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "size")
+}
+
+fn parse_radius(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "radius")
+}
+
+fn parse_spacing(s: &str) -> eyre::Result<f64> {
+    parse_positive(s, "spacing")
+}
+
+fn parse_positive(s: &str, name: &str) -> eyre::Result<f64> {
+    let value: f64 = s
+        .parse()
+        .wrap_err_with(|| format!("failed to parse {name} {s:?}"))?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(eyre!("{name} {s:?} must be positive"))
+    }
 }
 
 fn parse_point(s: &str) -> eyre::Result<Point2<f64>> {
@@ -72,6 +445,192 @@ mod tests {
         let _ = parse_point("(1, 2)").unwrap_err();
     }
 
+    #[test]
+    fn parse_microsteps() {
+        let cli = Cli::try_parse_from(["bin-name", "--end", "(1,0)", "--microsteps", "8"]).unwrap();
+        assert_eq!(cli.microsteps, Microsteps::Eighth);
+    }
+
+    #[test]
+    fn parse_subcommand_without_end() {
+        let cli =
+            Cli::try_parse_from(["bin-name", "backlash-pattern", "--beam-backlash", "3"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::BacklashPattern)));
+        assert_eq!(cli.beam_backlash, 3);
+    }
+
+    #[test]
+    fn tolerance_must_be_positive() {
+        let cli =
+            Cli::try_parse_from(["bin-name", "plot", "a.svg", "--tolerance", "0.05"]).unwrap();
+        assert_eq!(cli.tolerance, Some(0.05));
+        let _ = Cli::try_parse_from(["bin-name", "plot", "a.svg", "--tolerance", "0"]).unwrap_err();
+    }
+
+    #[test]
+    fn parse_fill() {
+        let args = [
+            "bin-name",
+            "plot",
+            "a.svg",
+            "--fill",
+            "polar",
+            "--fill-spacing",
+            "2",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert_eq!(cli.fill, Some(Pattern::Polar));
+        assert_eq!(cli.fill_spacing, 2.0);
+        assert_eq!(cli.fill_angle, 45.0);
+    }
+
+    #[test]
+    fn parse_text_on_arc() {
+        let args = [
+            "bin-name", "text", "Hello", "--radius", "80", "--angle", "90",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Text { text, size, radius: Some(radius), .. })
+                if text == "Hello" && size == 5.0 && radius == 80.0
+        ));
+    }
+
+    #[test]
+    fn radius_must_be_positive() {
+        let args = ["bin-name", "text", "Hello", "--radius", "-80"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn parse_serve() {
+        let cli = Cli::try_parse_from(["bin-name", "serve", "--io"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(Command::Serve { listen, optimize: false }) if listen == "127.0.0.1:8080")
+        );
+        assert!(cli.io);
+    }
+
+    #[test]
+    fn parse_link_settings() {
+        let args = [
+            "bin-name",
+            "jog",
+            "--central",
+            "tcp://bridge:2000",
+            "--baud",
+            "115200",
+            "--retries",
+            "0",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(matches!(cli.central, Endpoint::Tcp { .. }));
+        assert_eq!(cli.baud, Some(115200));
+        assert_eq!(cli.retries, Some(0));
+        assert_eq!(cli.timeout, None);
+        let _ = Cli::try_parse_from(["bin-name", "jog", "--timeout", "0"]).unwrap_err();
+        let cli = Cli::try_parse_from(["bin-name", "jog", "--backoff", "0"]).unwrap();
+        assert_eq!(cli.backoff, Some(0.0));
+        let _ = Cli::try_parse_from(["bin-name", "jog", "--backoff", "-1"]).unwrap_err();
+    }
+
+    #[test]
+    fn device_replaces_central_and_beam() {
+        let args = ["bin-name", "jog", "--device", "/dev/ttyACM0", "--io"];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(
+            matches!(cli.device, Some(Endpoint::Serial { path, .. }) if path == "/dev/ttyACM0")
+        );
+        let _ = Cli::try_parse_from(&args[..4]).unwrap_err();
+        let args = [
+            "bin-name",
+            "jog",
+            "--io",
+            "--device",
+            "/dev/ttyACM0",
+            "--beam",
+            "/dev/ttyACM1",
+        ];
+        let _ = Cli::try_parse_from(args).unwrap_err();
+    }
+
+    #[test]
+    fn segments_need_device() {
+        let _ = Cli::try_parse_from(["bin-name", "plot", "a.svg", "--segments"]).unwrap_err();
+        let args = [
+            "bin-name",
+            "plot",
+            "a.svg",
+            "--segments",
+            "--device",
+            "/dev/ttyACM0",
+            "--io",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(cli.segments);
+        assert_eq!(cli.chord_error, 0.05);
+    }
+
+    #[test]
+    fn parse_record_and_replay() {
+        let cli =
+            Cli::try_parse_from(["bin-name", "plot", "a.svg", "--record", "a.jsonl"]).unwrap();
+        assert_eq!(cli.record, Some(PathBuf::from("a.jsonl")));
+        let cli = Cli::try_parse_from(["bin-name", "replay", "a.jsonl"]).unwrap();
+        assert!(
+            matches!(cli.command, Some(Command::Replay { file }) if file.as_os_str() == "a.jsonl")
+        );
+    }
+
+    #[test]
+    fn virtual_clock_conflicts_with_io() {
+        let cli = Cli::try_parse_from(["bin-name", "plot", "a.svg", "--virtual-clock"]).unwrap();
+        assert!(cli.virtual_clock);
+        let args = ["bin-name", "plot", "a.svg", "--virtual-clock", "--io"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn parse_on_mismatch() {
+        let cli = Cli::try_parse_from(["bin-name", "plot", "a.svg"]).unwrap();
+        assert_eq!(cli.on_mismatch, OnMismatch::Halt);
+        let cli =
+            Cli::try_parse_from(["bin-name", "plot", "a.svg", "--on-mismatch", "correct"]).unwrap();
+        assert_eq!(cli.on_mismatch, OnMismatch::Correct);
+        let args = ["bin-name", "plot", "a.svg", "--on-mismatch", "ignore"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn max_lateness_is_optional() {
+        let cli =
+            Cli::try_parse_from(["bin-name", "plot", "a.svg", "--max-lateness", "0.005"]).unwrap();
+        assert_eq!(cli.max_lateness, Some(0.005));
+        let cli = Cli::try_parse_from(["bin-name", "plot", "a.svg"]).unwrap();
+        assert_eq!(cli.max_lateness, None);
+    }
+
+    #[test]
+    fn max_lateness_must_be_positive() {
+        let args = ["bin-name", "plot", "a.svg", "--max-lateness", "0"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn parse_telemetry() {
+        let cli =
+            Cli::try_parse_from(["bin-name", "plot", "a.svg", "--telemetry", "a.csv"]).unwrap();
+        assert_eq!(cli.telemetry, Some(PathBuf::from("a.csv")));
+        let cli = Cli::try_parse_from(["bin-name", "serve", "--io"]).unwrap();
+        assert_eq!(cli.telemetry, None);
+    }
+
+    #[test]
+    fn end_is_required_without_subcommand() {
+        let _ = Cli::try_parse_from(["bin-name", "--io"]).unwrap_err();
+    }
+
     #[test]
     fn parse_negative_x_coordinate() {
         Cli::try_parse_from(["bin-name", "--end", "(-1,0)"]).unwrap();
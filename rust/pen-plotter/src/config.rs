@@ -0,0 +1,163 @@
+pub use crate::stepper::{beam::BeamCalibration, central::CentralCalibration};
+use crate::tool::PenSettings;
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path, time::Duration};
+
+/// Measured properties of the plotter, written by the `calibrate` subcommand.
+/// Missing values keep the defaults of the stepper builders.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// in millimeter, paths are drawn relative to it
+    pub work_origin: Option<[f64; 2]>,
+
+    /// where pens are changed in millimeter, none changes them where the job started
+    pub park: Option<[f64; 2]>,
+
+    pub beam: BeamCalibration,
+    pub central: CentralCalibration,
+
+    /// of multi-pen jobs, as `[[pens]]` tables
+    pub pens: Vec<PenSettings>,
+
+    pub central_link: LinkConfig,
+    pub beam_link: LinkConfig,
+
+    /// of the microcontroller driving both motors
+    pub device_link: LinkConfig,
+}
+
+/// How to talk to the microcontroller of a motor.
+/// Missing values are taken from the command line or its defaults.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkConfig {
+    /// of the serial port unless its device has one
+    pub baud: Option<u32>,
+
+    /// of connecting, reading and writing in seconds
+    pub timeout: Option<f64>,
+
+    /// of opening the device
+    pub retries: Option<u32>,
+
+    /// before the first retry in seconds
+    pub backoff: Option<f64>,
+}
+
+impl Config {
+    /// Returns the default config if there is no file at `path`.
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).wrap_err_with(|| format!("failed to read config {path:?}")),
+        };
+        let config: Self =
+            toml::from_str(&text).wrap_err_with(|| format!("failed to parse config {path:?}"))?;
+        config
+            .validate()
+            .wrap_err_with(|| format!("invalid config {path:?}"))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> eyre::Result<()> {
+        for pen in &self.pens {
+            pen.validate()?;
+        }
+        let links = [
+            ("central_link", self.central_link),
+            ("beam_link", self.beam_link),
+            ("device_link", self.device_link),
+        ];
+        for (name, link) in links {
+            link.validate()
+                .wrap_err_with(|| format!("invalid {name}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let text = toml::to_string(self).wrap_err("failed to serialize config")?;
+        fs::write(path, text).wrap_err_with(|| format!("failed to write config {path:?}"))
+    }
+}
+
+impl LinkConfig {
+    /// Fails on a timeout that is not positive like the command line does,
+    /// and on a negative backoff.
+    pub fn validate(&self) -> eyre::Result<()> {
+        let seconds = |value: f64| Duration::try_from_secs_f64(value).ok();
+        if let Some(timeout) = self.timeout {
+            if seconds(timeout).is_none_or(|timeout| timeout.is_zero()) {
+                return Err(eyre!("timeout {timeout} must be positive"));
+            }
+        }
+        if let Some(backoff) = self.backoff {
+            if seconds(backoff).is_none() {
+                return Err(eyre!("backoff {backoff} must not be negative"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial_config() {
+        let actual: Config = toml::from_str("[beam]\noffset = 9.5\n").unwrap();
+        let expected = Config {
+            beam: BeamCalibration {
+                offset: Some(9.5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn zero_timeout_fails() {
+        let config: Config = toml::from_str("[beam_link]\ntimeout = 0.0\n").unwrap();
+        let e = config.validate().unwrap_err();
+        assert_eq!(e.to_string(), "invalid beam_link");
+        let config: Config = toml::from_str("[device_link]\ntimeout = 0.5\n").unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let config = Config {
+            work_origin: Some([1.0, -20.5]),
+            park: Some([0.0, -60.0]),
+            beam: BeamCalibration {
+                mm_per_full_step: Some(0.05),
+                offset: Some(10.2),
+            },
+            central: CentralCalibration {
+                zero_offset: Some(-3.6),
+            },
+            pens: vec![PenSettings {
+                number: 2,
+                velocity: Some(5.0),
+                pen_delay: None,
+            }],
+            central_link: LinkConfig {
+                baud: Some(115200),
+                retries: Some(5),
+                ..Default::default()
+            },
+            beam_link: LinkConfig::default(),
+            device_link: LinkConfig {
+                timeout: Some(1.5),
+                ..Default::default()
+            },
+        };
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(toml::from_str::<Config>(&text).unwrap(), config);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    geometry,
+    motion::{Motion, Segment},
+    pen::Pen,
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep, Stepper},
+};
+use nalgebra::Point2;
+use std::{
+    fmt,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+/// of step intervals to average step rates over
+const WINDOW: usize = 10;
+
+/// Statistics of the segments of a job, computed without moving the motors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    /// with the pen down in millimeter
+    pub draw_length: f64,
+
+    /// with the pen up in millimeter
+    pub travel_length: f64,
+    pub pen_lifts: usize,
+    pub tool_changes: usize,
+
+    /// including pen delays but not the time the operator takes for tool changes
+    pub duration: Duration,
+
+    pub central_rate: StepRate,
+    pub beam_rate: StepRate,
+
+    /// of the pen from the centre in millimeter
+    pub radius: RangeInclusive<f64>,
+
+    /// of the beam in millimeter
+    pub reach: RangeInclusive<f64>,
+}
+
+/// in steps per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepRate {
+    /// of the motions
+    pub peak: f64,
+
+    /// of the motor
+    pub max: f64,
+}
+
+impl Estimate {
+    /// Times the segments like [`Motion::sequence`].
+    pub fn new(
+        segments: &[Segment],
+        central: &CentralStepper,
+        beam: &BeamStepper,
+    ) -> eyre::Result<Self> {
+        let mut draw_length = 0.0;
+        let mut travel_length = 0.0;
+        let mut pen_lifts = 0;
+        let mut pen = None;
+        let mut radius = f64::INFINITY..=0.0;
+        for segment in segments {
+            let (start, end) = (segment.path.start, segment.path.end);
+            let length = nalgebra::distance(&start, &end);
+            match segment.pen {
+                Pen::Down => draw_length += length,
+                Pen::Up => travel_length += length,
+            }
+            if pen.replace(segment.pen) == Some(Pen::Down) && segment.pen == Pen::Up {
+                pen_lifts += 1;
+            }
+            let nearest = geometry::segment_distance(Point2::origin(), start, end);
+            let farthest = start.coords.norm().max(end.coords.norm());
+            radius = radius.start().min(nearest)..=radius.end().max(farthest);
+        }
+        let start = Instant::now();
+        let motions = Motion::sequence(segments.iter().copied(), start)?;
+        let duration = match motions.last() {
+            Some(last) => last.end()?.duration_since(start),
+            None => Duration::ZERO,
+        };
+        Ok(Self {
+            draw_length,
+            travel_length,
+            pen_lifts,
+            tool_changes: segments.iter().filter(|s| s.tool_change.is_some()).count(),
+            duration,
+            central_rate: step_rate(*central, &motions)?,
+            beam_rate: step_rate(*beam, &motions)?,
+            radius,
+            reach: beam.range(),
+        })
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        self.radius.is_empty()
+            || (self.reach.contains(self.radius.start()) && self.reach.contains(self.radius.end()))
+    }
+}
+
+impl StepRate {
+    pub fn is_exceeded(&self) -> bool {
+        self.peak > self.max
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "draw length:   {:.1} mm", self.draw_length)?;
+        writeln!(f, "travel length: {:.1} mm", self.travel_length)?;
+        writeln!(f, "pen lifts:     {}", self.pen_lifts)?;
+        writeln!(f, "tool changes:  {}", self.tool_changes)?;
+        let seconds = self.duration.as_secs();
+        writeln!(
+            f,
+            "duration:      {}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+        writeln!(f, "central motor: {}", self.central_rate)?;
+        writeln!(f, "beam motor:    {}", self.beam_rate)?;
+        if self.radius.is_empty() {
+            return writeln!(f, "radius:        no motions");
+        }
+        write!(
+            f,
+            "radius:        {:.1} to {:.1} mm, the beam reaches {:.1} to {:.1} mm",
+            self.radius.start(),
+            self.radius.end(),
+            self.reach.start(),
+            self.reach.end()
+        )?;
+        if self.is_reachable() {
+            writeln!(f)
+        } else {
+            writeln!(f, ", out of reach")
+        }
+    }
+}
+
+impl fmt::Display for StepRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "peak {:.0} of {:.0} steps/s",
+            self.peak.ceil(),
+            self.max.floor()
+        )?;
+        if self.is_exceeded() {
+            write!(f, ", too fast")?;
+        }
+        Ok(())
+    }
+}
+
+/// Samples the location every step interval of the motor like the executor
+/// and averages the steps over [`WINDOW`] intervals.
+/// Skips points out of reach of the stepper.
+fn step_rate<S: Stepper>(mut stepper: S, motions: &[Motion]) -> eyre::Result<StepRate> {
+    let interval = stepper.min_step_interval();
+    let max = 1.0 / interval.as_secs_f64();
+    let (first, last) = match (motions.first(), motions.last()) {
+        (Some(first), Some(last)) => (first.start, last.end()?),
+        _ => return Ok(StepRate { peak: 0.0, max }),
+    };
+    let mut deltas = Vec::new();
+    let mut current: Option<CurrentStep> = None;
+    let mut index = 0;
+    let mut time = first;
+    while time <= last {
+        while index + 1 < motions.len() && motions[index + 1].start <= time {
+            index += 1;
+        }
+        let motion = &motions[index];
+        let fraction = time.saturating_duration_since(motion.start).as_secs_f64()
+            / motion.path.duration()?.as_secs_f64();
+        time += interval;
+        let target = match stepper.target_step(motion.path.interpolate(fraction)) {
+            Ok(target) => target,
+            Err(_) => {
+                current = None;
+                deltas.push(0);
+                continue;
+            }
+        };
+        let delta = match current {
+            Some(current) => stepper.delta_steps(current, target)?.0,
+            None => 0,
+        };
+        deltas.push(i32::from(delta).abs());
+        current = Some(CurrentStep(target.0));
+    }
+    let window = WINDOW.min(deltas.len()).max(1);
+    let steps = deltas.windows(window).map(|w| w.iter().sum::<i32>()).max();
+    let seconds = interval.as_secs_f64() * window as f64;
+    Ok(StepRate {
+        peak: f64::from(steps.unwrap_or(0)) / seconds,
+        max,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{job::Job, motion::PEN_DELAY, stepper::Microsteps, tool::Tools};
+
+    fn estimate(job: &Job, velocity: f64) -> Estimate {
+        let segments = job.segments(Point2::new(0.0, -10.0), &Tools::new(velocity));
+        let central = CentralStepper::default();
+        let beam = BeamStepper::with_microsteps(Microsteps::Half).unwrap();
+        Estimate::new(&segments, &central, &beam).unwrap()
+    }
+
+    #[test]
+    fn lengths_lifts_and_duration() {
+        let job = Job::new(vec![
+            vec![Point2::new(0.0, -10.0), Point2::new(0.0, -20.0)],
+            vec![Point2::new(5.0, -20.0), Point2::new(5.0, -30.0)],
+        ]);
+        let estimate = estimate(&job, 5.0);
+        assert_eq!(estimate.draw_length, 20.0);
+        assert_eq!(estimate.travel_length, 5.0);
+        assert_eq!(estimate.pen_lifts, 1);
+        assert_eq!(estimate.tool_changes, 0);
+        let expected = Duration::from_secs(5) + PEN_DELAY * 3;
+        assert!((estimate.duration.as_secs_f64() - expected.as_secs_f64()).abs() < 1e-6);
+        assert_eq!(estimate.radius, 10.0..=f64::hypot(5.0, 30.0));
+        assert!(estimate.is_reachable());
+    }
+
+    #[test]
+    fn beam_step_rate_grows_with_velocity() {
+        let job = Job::new(vec![vec![Point2::new(0.0, -10.0), Point2::new(0.0, -20.0)]]);
+        // 40 steps per mm
+        let fast = estimate(&job, 5.0).beam_rate;
+        assert!((190.0..=215.0).contains(&fast.peak), "{fast:?}");
+        assert!(fast.is_exceeded());
+        let slow = estimate(&job, 1.0).beam_rate;
+        assert!(!slow.is_exceeded(), "{slow:?}");
+    }
+
+    #[test]
+    fn out_of_reach() {
+        let job = Job::new(vec![vec![Point2::new(0.0, -10.0), Point2::new(0.0, -90.0)]]);
+        let estimate = estimate(&job, 1.0);
+        assert!(!estimate.is_reachable());
+        assert!(estimate.to_string().contains("out of reach"));
+    }
+}
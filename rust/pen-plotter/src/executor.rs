@@ -0,0 +1,996 @@
+use crate::{
+    axis::Axis,
+    clock::Entered,
+    control::{Aborted, Control},
+    controller::{Finished, Streamer},
+    motion::Motion,
+    named::Named,
+    path::Point,
+    polar::PolarSegment,
+    record::{self, Context},
+    stepper::{CurrentStep, DeltaSteps, Stepper, TargetStep},
+    telemetry::{Sample, Telemetry},
+    timer::{IntervalTimer, Timing},
+};
+use eyre::eyre;
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Shared between the axis threads and observers of running motions.
+#[derive(Debug, Default)]
+pub struct Progress {
+    pub central: Mutex<AxisProgress>,
+    pub beam: Mutex<AxisProgress>,
+
+    /// receives a sample of every interval of both axes, shared by the jobs of a daemon
+    pub telemetry: Option<Arc<Telemetry>>,
+
+    /// of an interval before its axis fails, unbounded if `None`
+    pub max_lateness: Option<Duration>,
+
+    /// when a device reports another step than it was sent to
+    pub on_mismatch: OnMismatch,
+}
+
+/// What an axis does when its device reports another step than it was sent to,
+/// for example after lost bytes or a reset of the microcontroller.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnMismatch {
+    /// logs a warning and continues from the reported step
+    Warn,
+
+    /// sends the difference again, at most [`MAX_CORRECTIONS`] times
+    Correct,
+
+    /// fails with the last intervals of the axis
+    #[default]
+    Halt,
+}
+
+/// per interval
+pub const MAX_CORRECTIONS: usize = 3;
+
+/// intervals kept per axis to diagnose a mismatch
+const HISTORY: usize = 32;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AxisProgress {
+    pub step: CurrentStep,
+
+    /// of the duration of all motions, from 0 to 1
+    pub fraction: f64,
+
+    /// index of the current motion
+    pub motion: usize,
+
+    /// of the current motion, from 0 to 1
+    pub motion_fraction: f64,
+
+    /// of the intervals of the finished motions
+    pub timing: Timing,
+}
+
+impl Progress {
+    /// Reports the given steps until the axes start.
+    pub fn at(central: CurrentStep, beam: CurrentStep) -> Self {
+        let progress = Self::default();
+        *progress.central.lock().unwrap_or_else(|e| e.into_inner()) = AxisProgress {
+            step: central,
+            ..Default::default()
+        };
+        *progress.beam.lock().unwrap_or_else(|e| e.into_inner()) = AxisProgress {
+            step: beam,
+            ..Default::default()
+        };
+        progress
+    }
+
+    /// of the central and the beam axis
+    pub fn steps(&self) -> (CurrentStep, CurrentStep) {
+        (lock(&self.central).step, lock(&self.beam).step)
+    }
+
+    /// index and fraction of the motion of the axis lagging behind
+    pub fn motion(&self) -> (usize, f64) {
+        let central = lock(&self.central);
+        let beam = lock(&self.beam);
+        let central = (central.motion, central.motion_fraction);
+        let beam = (beam.motion, beam.motion_fraction);
+        if central <= beam {
+            central
+        } else {
+            beam
+        }
+    }
+
+    /// of the finished motions of both axes
+    pub fn timing(&self) -> Timing {
+        let central = lock(&self.central).timing;
+        let beam = lock(&self.beam).timing;
+        Timing {
+            ticks: central.ticks + beam.ticks,
+            missed: central.missed + beam.missed,
+            max_lateness: central.max_lateness.max(beam.max_lateness),
+            total_lateness: central.total_lateness + beam.total_lateness,
+        }
+    }
+
+    /// of the axis lagging behind
+    pub fn fraction(&self) -> f64 {
+        let central = lock(&self.central).fraction;
+        let beam = lock(&self.beam).fraction;
+        central.min(beam)
+    }
+}
+
+fn lock(progress: &Mutex<AxisProgress>) -> AxisProgress {
+    *progress.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Steps both axes through the motions in a thread each.
+/// An error of one axis aborts the other one.
+/// The pen is lifted when the motions are aborted.
+pub fn execute<C, B>(
+    motions: &[Motion],
+    central: &mut Axis<C>,
+    beam: &mut Axis<B>,
+    progress: &Progress,
+    control: &Control,
+) -> eyre::Result<()>
+where
+    C: Stepper + Named + Send,
+    B: Stepper + Named + Send,
+{
+    let monitor = Monitor {
+        telemetry: progress.telemetry.as_deref(),
+        max_lateness: progress.max_lateness,
+        on_mismatch: progress.on_mismatch,
+    };
+    // both axes enter before either one sleeps so a virtual clock waits for both
+    let entered = [Entered::new(control.clock()), Entered::new(control.clock())];
+    let results = thread::scope(|s| {
+        let [central_entered, beam_entered] = entered;
+        let central = s.spawn(move || {
+            let _entered = central_entered;
+            step_through_motions(motions, central, &progress.central, monitor, control)
+        });
+        let beam = s.spawn(move || {
+            let _entered = beam_entered;
+            step_through_motions(motions, beam, &progress.beam, monitor, control)
+        });
+        [central.join(), beam.join()]
+    });
+    let mut aborted = Ok(());
+    for result in results {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) if e.is::<Aborted>() => aborted = Err(e),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(eyre!("axis thread panicked")),
+        }
+    }
+    aborted.and(flush(progress))
+}
+
+fn flush(progress: &Progress) -> eyre::Result<()> {
+    match &progress.telemetry {
+        Some(telemetry) => telemetry.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Streams the motions as polar segments the controller of both axes interpolates.
+/// Reports the progress of the segments the controller finished
+/// and writes a telemetry sample of both axes for each of them, without latency and lateness.
+/// Whenever the controller finished all segments sent, its steps are verified
+/// against the targets of the last one, segments can't be corrected by sending them again.
+pub fn stream(
+    motions: &[Motion],
+    segments: &[PolarSegment],
+    (central_name, beam_name): (&'static str, &'static str),
+    streamer: &mut Streamer,
+    progress: &Progress,
+    control: &Control,
+) -> eyre::Result<()> {
+    if progress.on_mismatch == OnMismatch::Correct {
+        return Err(eyre!(
+            "segments can't be sent again to correct mismatches, use warn or halt"
+        ));
+    }
+    let (first, last) = match (motions.first(), motions.last()) {
+        (Some(first), Some(last)) => (first.start, last.end()?),
+        _ => return Ok(()),
+    };
+    let total = last.duration_since(first).as_secs_f64();
+    let durations = motions
+        .iter()
+        .map(|motion| motion.path.duration())
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let (central_start, beam_start) = progress.steps();
+    let mut previous = [central_start.0, beam_start.0];
+    streamer.stream(motions, segments, control, |finished| {
+        let Finished { segment, steps, .. } = finished;
+        let motion = &motions[segment.motion];
+        let elapsed = motion.start.duration_since(first)
+            + durations[segment.motion].mul_f64(segment.fraction);
+        let target = motion.path.interpolate(segment.fraction).location;
+        let axes = [
+            (central_name, &progress.central, segment.central, steps[0]),
+            (beam_name, &progress.beam, segment.beam, steps[1]),
+        ];
+        for (previous, (stepper, axis_progress, target_step, step)) in previous.iter_mut().zip(axes)
+        {
+            *axis_progress.lock().unwrap_or_else(|e| e.into_inner()) = AxisProgress {
+                step,
+                fraction: elapsed.as_secs_f64() / total,
+                motion: segment.motion,
+                motion_fraction: segment.fraction,
+                ..Default::default()
+            };
+            if let Some(telemetry) = &progress.telemetry {
+                let sample = Sample {
+                    stepper,
+                    fraction: segment.fraction,
+                    target: [target.x, target.y],
+                    target_step: target_step.0,
+                    delta_steps: target_step.0.wrapping_sub(*previous),
+                    reported_step: Some(step.0),
+                    latency: None,
+                    lateness: 0.0,
+                };
+                telemetry.write(segment.motion, &sample);
+            }
+            *previous = target_step.0;
+        }
+        verify_segment(finished, (central_name, beam_name), progress.on_mismatch)
+    })?;
+    flush(progress)
+}
+
+/// Compares the steps of a settled controller with the targets of its last segment.
+fn verify_segment(
+    finished: Finished,
+    (central_name, beam_name): (&'static str, &'static str),
+    on_mismatch: OnMismatch,
+) -> eyre::Result<()> {
+    if !finished.settled {
+        return Ok(());
+    }
+    let segment = finished.segment;
+    let axes = [
+        (central_name, segment.central, finished.steps[0]),
+        (beam_name, segment.beam, finished.steps[1]),
+    ];
+    for (stepper, target_step, step) in axes {
+        if step.0 == target_step.0 {
+            continue;
+        }
+        let mismatch = format!(
+            "{stepper}: controller reported step {} instead of {} after segment {segment:?}",
+            step.0, target_step.0
+        );
+        match on_mismatch {
+            OnMismatch::Warn => log::warn!("{mismatch}"),
+            OnMismatch::Correct | OnMismatch::Halt => return Err(eyre!("{mismatch}")),
+        }
+    }
+    Ok(())
+}
+
+/// How both axes watch their intervals.
+#[derive(Debug, Clone, Copy)]
+struct Monitor<'a> {
+    telemetry: Option<&'a Telemetry>,
+    max_lateness: Option<Duration>,
+    on_mismatch: OnMismatch,
+}
+
+/// Of the intervals of an axis so far.
+#[derive(Debug, Default)]
+struct History {
+    timing: Timing,
+
+    /// the last [`HISTORY`] ones with steps to send
+    intervals: VecDeque<IntervalContext>,
+}
+
+fn step_through_motions<S>(
+    motions: &[Motion],
+    axis: &mut Axis<S>,
+    axis_progress: &Mutex<AxisProgress>,
+    monitor: Monitor,
+    control: &Control,
+) -> eyre::Result<()>
+where
+    S: Stepper + Named,
+{
+    let result = try_step_through_motions(motions, axis, axis_progress, monitor, control);
+    match &result {
+        Err(e) if e.is::<Aborted>() => axis.lift_pen()?,
+        Err(_) => control.abort(),
+        Ok(()) => {}
+    }
+    result
+}
+
+fn try_step_through_motions<S>(
+    motions: &[Motion],
+    axis: &mut Axis<S>,
+    axis_progress: &Mutex<AxisProgress>,
+    monitor: Monitor,
+    control: &Control,
+) -> eyre::Result<()>
+where
+    S: Stepper + Named,
+{
+    let mut history = History::default();
+    let (first, last) = match (motions.first(), motions.last()) {
+        (Some(first), Some(last)) => (first.start, last.end()?),
+        _ => return Ok(()),
+    };
+    let total = last.duration_since(first).as_secs_f64();
+    for (index, motion) in motions.iter().enumerate() {
+        if let (true, Some(pen)) = (axis.stepper.carries_pen(), motion.tool_change) {
+            axis.lift_pen()?;
+            log::warn!("insert pen {pen}, then type p and Enter or send SIGUSR2 to continue");
+            control.pause();
+        }
+        control.wait_while_held()?;
+        if axis.stepper.carries_pen() && axis.pen != Some(motion.pen) {
+            axis.set_pen(motion.pen)?;
+        }
+        let duration = motion.path.duration()?;
+        let offset = motion.start.duration_since(first);
+        step_through_motion(
+            *motion,
+            axis,
+            control,
+            monitor,
+            &mut history,
+            |sample, step| {
+                if let Some(telemetry) = monitor.telemetry {
+                    telemetry.write(index, sample);
+                }
+                let motion_fraction = sample.fraction.min(1.0);
+                let elapsed = offset + duration.mul_f64(motion_fraction);
+                let mut axis_progress = axis_progress.lock().unwrap_or_else(|e| e.into_inner());
+                *axis_progress = AxisProgress {
+                    step,
+                    fraction: elapsed.as_secs_f64() / total,
+                    motion: index,
+                    motion_fraction,
+                    ..*axis_progress
+                };
+            },
+        )?;
+        if !axis.device.reports_steps() {
+            // a batched device reports the steps of the motion once flushed
+            axis.read_back()?;
+            if let Some(interval) = history.intervals.back().copied() {
+                verify(axis, interval, &mut history.intervals, monitor.on_mismatch)?;
+            }
+        }
+        axis_progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .timing = history.timing;
+    }
+    let timing = history.timing;
+    match timing.missed {
+        0 => log::info!("{}: {timing}", axis.stepper.name()),
+        _ => log::warn!("{}: {timing}", axis.stepper.name()),
+    }
+    axis.read_back()?;
+    Ok(())
+}
+
+fn step_through_motion<S, F>(
+    motion: Motion,
+    axis: &mut Axis<S>,
+    control: &Control,
+    monitor: Monitor,
+    History { timing, intervals }: &mut History,
+    mut on_interval: F,
+) -> eyre::Result<()>
+where
+    S: Stepper + Named,
+    F: FnMut(&Sample, CurrentStep),
+{
+    let Motion { path, start, .. } = motion;
+    let interval_timer = IntervalTimer {
+        start,
+        duration: path.duration()?,
+        interval: axis.stepper.min_step_interval(),
+        max_lateness: monitor.max_lateness,
+    };
+    // a batched device returns the step it expects, not the one it reported
+    let reports_steps = axis.device.reports_steps();
+    interval_timer.for_each_tick(control, timing, |tick| {
+        let target = path.interpolate(tick.fraction);
+        let target_step = axis.stepper.target_step(target)?;
+        // before the delta adds take-up steps to the offset of the reported step
+        let previous = axis.stepper.reported_step(TargetStep(axis.step.0));
+        let delta_steps = axis.stepper.delta_steps(axis.step, target_step)?;
+        let mut latency = None;
+        if delta_steps.0 != 0 {
+            let interval = IntervalContext {
+                stepper_name: axis.stepper.name(),
+                point: target,
+                current_step: axis.step,
+                delta_steps,
+                target_step,
+                expected_step: CurrentStep(previous.0.wrapping_add(delta_steps.0)),
+            };
+            let clock = control.clock();
+            let sent = clock.now();
+            send(axis, interval, intervals)?;
+            if reports_steps {
+                latency = Some(clock.now().saturating_duration_since(sent).as_secs_f64());
+                verify(axis, interval, intervals, monitor.on_mismatch)?;
+            }
+        }
+        let sample = Sample {
+            stepper: axis.stepper.name(),
+            fraction: tick.fraction,
+            target: [target.location.x, target.location.y],
+            target_step: target_step.0,
+            delta_steps: delta_steps.0,
+            reported_step: reports_steps.then_some(axis.step.0),
+            latency,
+            lateness: tick.lateness.as_secs_f64(),
+        };
+        on_interval(&sample, axis.step);
+        Ok(())
+    })
+}
+
+/// Sends the delta of the interval and keeps it in the history.
+fn send<S>(
+    axis: &mut Axis<S>,
+    interval: IntervalContext,
+    intervals: &mut VecDeque<IntervalContext>,
+) -> eyre::Result<()>
+where
+    S: Stepper,
+{
+    log::info!("{interval}");
+    if intervals.len() == HISTORY {
+        intervals.pop_front();
+    }
+    intervals.push_back(interval);
+    let delta_steps = interval.delta_steps;
+    let reported_step =
+        record::with_context(Context::from(&interval), || axis.device.step(delta_steps))?;
+    axis.step = axis.stepper.position(reported_step);
+    Ok(())
+}
+
+/// Compares the step the device reported after the interval with the one it was expected to,
+/// the previous reported step plus the delta, wrapping like the firmware
+/// and up to whole periods of the stepper.
+/// A batched device is flushed to report its step after a correction.
+fn verify<S>(
+    axis: &mut Axis<S>,
+    mut interval: IntervalContext,
+    intervals: &mut VecDeque<IntervalContext>,
+    on_mismatch: OnMismatch,
+) -> eyre::Result<()>
+where
+    S: Stepper + Named,
+{
+    let mut corrections = 0;
+    loop {
+        let reported = axis.stepper.reported_step(TargetStep(axis.step.0));
+        let missing = difference(axis.stepper.period(), reported, interval.expected_step);
+        if missing == 0 {
+            return Ok(());
+        }
+        let mismatch = format!(
+            "{}: device reported step {} instead of {}",
+            interval.stepper_name, reported.0, interval.expected_step.0
+        );
+        match on_mismatch {
+            OnMismatch::Warn => {
+                log::warn!("{mismatch}");
+                return Ok(());
+            }
+            OnMismatch::Correct if corrections < MAX_CORRECTIONS => {
+                let target_step = TargetStep(axis.step.0.wrapping_add(missing));
+                let delta_steps = axis.stepper.delta_steps(axis.step, target_step)?;
+                log::warn!("{mismatch}, sending {} steps again", delta_steps.0);
+                interval = IntervalContext {
+                    current_step: axis.step,
+                    delta_steps,
+                    expected_step: CurrentStep(reported.0.wrapping_add(delta_steps.0)),
+                    ..interval
+                };
+                send(axis, interval, intervals)?;
+                if !axis.device.reports_steps() {
+                    axis.read_back()?;
+                }
+                corrections += 1;
+            }
+            OnMismatch::Correct | OnMismatch::Halt => {
+                let intervals: Vec<_> = intervals.iter().map(ToString::to_string).collect();
+                return Err(eyre!(
+                    "{mismatch} after {corrections} corrections, the last intervals were\n{}",
+                    intervals.join("\n")
+                ));
+            }
+        }
+    }
+}
+
+/// from one step number to another, wrapping around i16
+/// and within half a period either way for a stepper with one
+fn difference(period: Option<i16>, from: CurrentStep, to: CurrentStep) -> i16 {
+    let difference = to.0.wrapping_sub(from.0);
+    match period {
+        Some(period) => {
+            let (difference, period) = (i32::from(difference), i32::from(period));
+            let reduced = (difference + period / 2).rem_euclid(period) - period / 2;
+            reduced as i16
+        }
+        None => difference,
+    }
+}
+
+impl FromStr for OnMismatch {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "warn" => Ok(Self::Warn),
+            "correct" => Ok(Self::Correct),
+            "halt" => Ok(Self::Halt),
+            _ => Err(eyre!(
+                "invalid mismatch handling {s:?}, expected warn, correct or halt"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct IntervalContext {
+    pub stepper_name: &'static str,
+    pub point: Point,
+    pub current_step: CurrentStep,
+    pub delta_steps: DeltaSteps,
+    pub target_step: TargetStep,
+
+    /// the step number the microcontroller should report after stepping
+    pub expected_step: CurrentStep,
+}
+
+impl From<&IntervalContext> for Context {
+    fn from(interval: &IntervalContext) -> Self {
+        let location = interval.point.location;
+        Self {
+            stepper: interval.stepper_name.to_string(),
+            location: [location.x, location.y],
+            current_step: interval.current_step.0,
+            delta_steps: interval.delta_steps.0,
+            target_step: interval.target_step.0,
+        }
+    }
+}
+
+impl fmt::Display for IntervalContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: current = {}, delta = {}, target = {}, expected = {}, location = {}",
+            self.stepper_name,
+            self.current_step.0,
+            self.delta_steps.0,
+            self.target_step.0,
+            self.expected_step.0,
+            self.point.location
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{Clock, VirtualClock},
+        device::{Device, SimulatedDevice},
+        job::{Job, Layer},
+        pen::Pen,
+        stepper::{beam::BeamStepper, central::CentralStepper},
+        tool::Tools,
+    };
+    use nalgebra::Point2;
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    fn axes() -> (Axis<CentralStepper>, Axis<BeamStepper>) {
+        let central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        );
+        let beam = Axis::new(BeamStepper::default(), Box::new(SimulatedDevice::default()));
+        (central.unwrap(), beam.unwrap())
+    }
+
+    /// starting now on the clock of `control`
+    fn motions(end: Point2<f64>, velocity: f64, control: &Control) -> Vec<Motion> {
+        let job = Job::new(vec![vec![Point2::new(0.0, -10.0), end]]);
+        let segments = job.segments(Point2::new(0.0, -10.0), &Tools::new(velocity));
+        Motion::sequence(segments, control.clock().now()).unwrap()
+    }
+
+    fn virtual_control() -> Control {
+        Control::new(Arc::new(VirtualClock::default()))
+    }
+
+    #[test]
+    fn execute_to_end() {
+        let (mut central, mut beam) = axes();
+        let progress = Progress::default();
+        let control = Control::default();
+        let motions = motions(Point2::new(0.0, -20.0), 1000.0, &control);
+        execute(&motions, &mut central, &mut beam, &progress, &control).unwrap();
+        assert_eq!(beam.step, CurrentStep(400));
+        assert_eq!(beam.pen, Some(Pen::Down));
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(progress.steps().1, CurrentStep(400));
+        assert_eq!(progress.motion(), (motions.len() - 1, 1.0));
+    }
+
+    #[test]
+    fn execute_on_virtual_clock() {
+        let clock = Arc::new(VirtualClock::default());
+        let control = Control::new(clock.clone());
+        let (mut central, mut beam) = axes();
+        let progress = Progress::default();
+        let job = Job::new(vec![vec![Point2::new(0.0, -10.0), Point2::new(0.0, -20.0)]]);
+        let segments = job.segments(Point2::new(0.0, -10.0), &Tools::new(1.0));
+        let motions = Motion::sequence(segments, clock.now()).unwrap();
+        let wall_start = Instant::now();
+        execute(&motions, &mut central, &mut beam, &progress, &control).unwrap();
+        assert!(wall_start.elapsed() < Duration::from_secs(5));
+        assert!(
+            clock.elapsed() >= Duration::from_secs(10),
+            "{:?}",
+            clock.elapsed()
+        );
+        assert_eq!(beam.step, CurrentStep(400));
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(lock(&progress.beam).timing.max_lateness, Duration::ZERO);
+    }
+
+    #[test]
+    fn execute_with_telemetry() {
+        let path = std::env::temp_dir().join(format!(
+            "pen-plotter-executor-telemetry-{}.jsonl",
+            std::process::id()
+        ));
+        let (mut central, mut beam) = axes();
+        let progress = Progress {
+            telemetry: Some(Arc::new(Telemetry::create(&path).unwrap())),
+            ..Progress::default()
+        };
+        let control = Control::default();
+        let motions = motions(Point2::new(0.0, -20.0), 1000.0, &control);
+        execute(&motions, &mut central, &mut beam, &progress, &control).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let samples: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let beam_samples: Vec<_> = samples
+            .iter()
+            .filter(|sample| sample["stepper"] == beam.stepper.name())
+            .collect();
+        assert!(samples.len() > beam_samples.len());
+        let last = beam_samples.last().unwrap();
+        assert_eq!(last["reported_step"], 400);
+        let sent: i64 = beam_samples
+            .iter()
+            .map(|sample| sample["delta_steps"].as_i64().unwrap())
+            .sum();
+        assert_eq!(sent, 400);
+    }
+
+    /// Loses the first delta, like a dropped frame.
+    /// Batched, it returns the expected step until flushed.
+    struct LossyDevice {
+        device: SimulatedDevice,
+        lost: bool,
+        expected: Option<CurrentStep>,
+    }
+
+    impl Device for LossyDevice {
+        fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+            let reported = if delta.0 != 0 && !self.lost {
+                self.lost = true;
+                self.device.flush()?
+            } else {
+                self.device.step(delta)?
+            };
+            match &mut self.expected {
+                Some(expected) => {
+                    expected.0 += delta.0;
+                    Ok(*expected)
+                }
+                None => Ok(reported),
+            }
+        }
+
+        fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+            self.device.set_pen(pen)
+        }
+
+        fn flush(&mut self) -> eyre::Result<CurrentStep> {
+            let reported = self.device.flush()?;
+            if let Some(expected) = &mut self.expected {
+                *expected = reported;
+            }
+            Ok(reported)
+        }
+
+        fn reports_steps(&self) -> bool {
+            self.expected.is_none()
+        }
+    }
+
+    fn execute_lossy(on_mismatch: OnMismatch) -> (eyre::Result<()>, Axis<BeamStepper>) {
+        execute_lossy_device(on_mismatch, None)
+    }
+
+    fn execute_lossy_device(
+        on_mismatch: OnMismatch,
+        expected: Option<CurrentStep>,
+    ) -> (eyre::Result<()>, Axis<BeamStepper>) {
+        let control = virtual_control();
+        let (mut central, _) = axes();
+        let lossy = LossyDevice {
+            device: SimulatedDevice::default(),
+            lost: false,
+            expected,
+        };
+        let mut beam = Axis::new(BeamStepper::default(), Box::new(lossy)).unwrap();
+        let progress = Progress {
+            on_mismatch,
+            ..Progress::default()
+        };
+        let motions = motions(Point2::new(0.0, -20.0), 10.0, &control);
+        let result = execute(&motions, &mut central, &mut beam, &progress, &control);
+        (result, beam)
+    }
+
+    #[test]
+    fn halt_on_mismatch() {
+        let (result, beam) = execute_lossy(OnMismatch::Halt);
+        let e = result.unwrap_err().to_string();
+        assert!(e.contains("device reported step 0 instead of"), "{e}");
+        assert!(e.contains("after 0 corrections"), "{e}");
+        assert!(e.contains("beam stepper: current = 0, delta = "), "{e}");
+        assert_eq!(beam.step, CurrentStep(0));
+    }
+
+    #[test]
+    fn correct_mismatch() {
+        let (result, beam) = execute_lossy(OnMismatch::Correct);
+        result.unwrap();
+        assert_eq!(beam.step, CurrentStep(400));
+    }
+
+    #[test]
+    fn warn_on_mismatch() {
+        let (result, beam) = execute_lossy(OnMismatch::Warn);
+        result.unwrap();
+        assert_eq!(beam.step, CurrentStep(400));
+    }
+
+    #[test]
+    fn batched_mismatch_is_verified_on_flush() {
+        let batched = Some(CurrentStep(0));
+        let (result, beam) = execute_lossy_device(OnMismatch::Halt, batched);
+        let e = result.unwrap_err().to_string();
+        assert!(e.contains("device reported step 398 instead of 400"), "{e}");
+        assert_eq!(beam.step, CurrentStep(398));
+        let (result, beam) = execute_lossy_device(OnMismatch::Correct, batched);
+        result.unwrap();
+        assert_eq!(beam.step, CurrentStep(400));
+    }
+
+    #[test]
+    fn central_steps_match_a_revolution_apart() {
+        let control = virtual_control();
+        let stepper = CentralStepper::default();
+        let revolution = SimulatedDevice {
+            step: CurrentStep(stepper.steps_per_rev()),
+            ..Default::default()
+        };
+        let mut central = Axis::new(stepper, Box::new(revolution)).unwrap();
+        let (_, mut beam) = axes();
+        let motions = motions(Point2::new(5.0, -20.0), 1000.0, &control);
+        let progress = Progress::default();
+        execute(&motions, &mut central, &mut beam, &progress, &control).unwrap();
+        assert_ne!(central.step.0, stepper.steps_per_rev());
+    }
+
+    #[test]
+    fn difference_wraps() {
+        let step = CurrentStep;
+        assert_eq!(difference(Some(600), step(610), step(10)), 0);
+        assert_eq!(difference(Some(600), step(0), step(590)), -10);
+        assert_eq!(difference(Some(600), step(-290), step(290)), -20);
+        assert_eq!(difference(None, step(i16::MAX), step(i16::MIN)), 1);
+        assert_eq!(difference(None, step(10), step(-5)), -15);
+    }
+
+    #[test]
+    fn parse_on_mismatch() {
+        assert_eq!(
+            "correct".parse::<OnMismatch>().unwrap(),
+            OnMismatch::Correct
+        );
+        assert!("retry".parse::<OnMismatch>().is_err());
+    }
+
+    /// Aborts the plot once its steps reach `at`.
+    struct AbortingDevice {
+        device: SimulatedDevice,
+        control: Arc<Control>,
+        at: i16,
+    }
+
+    impl Device for AbortingDevice {
+        fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+            let step = self.device.step(delta)?;
+            if step.0 >= self.at {
+                self.control.abort();
+            }
+            Ok(step)
+        }
+
+        fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+            self.device.set_pen(pen)
+        }
+    }
+
+    #[test]
+    fn execute_aborted() {
+        let (mut central, mut beam) = axes();
+        let control = virtual_control();
+        control.abort();
+        let motions = motions(Point2::new(0.0, -20.0), 1000.0, &control);
+        let e = execute(
+            &motions,
+            &mut central,
+            &mut beam,
+            &Progress::default(),
+            &control,
+        );
+        assert!(e.unwrap_err().is::<Aborted>());
+        assert_eq!(beam.step, CurrentStep(0));
+    }
+
+    #[test]
+    fn abort_lifts_pen() {
+        let control = Arc::new(virtual_control());
+        let (mut central, _) = axes();
+        let aborting = AbortingDevice {
+            device: SimulatedDevice::default(),
+            control: Arc::clone(&control),
+            at: 100,
+        };
+        let mut beam = Axis::new(BeamStepper::default(), Box::new(aborting)).unwrap();
+        let motions = motions(Point2::new(0.0, -20.0), 10.0, &control);
+        let result = execute(
+            &motions,
+            &mut central,
+            &mut beam,
+            &Progress::default(),
+            &control,
+        );
+        assert!(result.unwrap_err().is::<Aborted>());
+        assert_eq!(beam.pen, Some(Pen::Up));
+        assert!(100 <= beam.step.0 && beam.step.0 < 400, "{:?}", beam.step);
+    }
+
+    #[test]
+    fn pause_holds_until_resume() {
+        let (mut central, mut beam) = axes();
+        let progress = Progress::default();
+        let control = virtual_control();
+        control.pause();
+        let motions = motions(Point2::new(0.0, -20.0), 1000.0, &control);
+        thread::scope(|s| {
+            let plot = s.spawn(|| execute(&motions, &mut central, &mut beam, &progress, &control));
+            // on a virtual clock the motions would finish right away without the hold
+            for _ in 0..1000 {
+                thread::yield_now();
+            }
+            assert!(!plot.is_finished());
+            assert_eq!(progress.steps().1, CurrentStep(0));
+            control.resume();
+            plot.join().unwrap().unwrap();
+        });
+        assert_eq!(beam.step, CurrentStep(400));
+    }
+
+    #[test]
+    fn settled_segments_are_verified() {
+        let segment = PolarSegment {
+            central: TargetStep(-5),
+            beam: TargetStep(40),
+            duration: Duration::from_millis(10),
+            motion: 0,
+            fraction: 1.0,
+        };
+        let finished = |beam, settled| Finished {
+            segment: &segment,
+            steps: [CurrentStep(-5), CurrentStep(beam)],
+            settled,
+        };
+        let names = ("central stepper", "beam stepper");
+        verify_segment(finished(40, true), names, OnMismatch::Halt).unwrap();
+        verify_segment(finished(30, false), names, OnMismatch::Halt).unwrap();
+        verify_segment(finished(30, true), names, OnMismatch::Warn).unwrap();
+        let e = verify_segment(finished(30, true), names, OnMismatch::Halt).unwrap_err();
+        assert!(e
+            .to_string()
+            .starts_with("beam stepper: controller reported step 30"));
+    }
+
+    #[test]
+    fn tool_change_pauses_with_pen_up() {
+        let clock = Arc::new(VirtualClock::default());
+        let control = Control::new(clock.clone());
+        let (mut central, mut beam) = axes();
+        let progress = Progress::default();
+        let layer = |pen, x| Layer {
+            pen,
+            strokes: vec![vec![Point2::new(x, -10.0), Point2::new(x, -20.0)]],
+        };
+        let job = Job {
+            layers: vec![layer(1, 0.0), layer(2, 0.0)],
+        };
+        let segments = job.segments(Point2::new(0.0, -10.0), &Tools::new(1000.0));
+        let motions = Motion::sequence(segments, clock.now()).unwrap();
+        let tool_change = motions.iter().position(|m| m.tool_change == Some(2));
+        thread::scope(|s| {
+            let plot = s.spawn(|| execute(&motions, &mut central, &mut beam, &progress, &control));
+            // the virtual clock stands still once both axes hold
+            while !control.is_held() && !plot.is_finished() {
+                thread::yield_now();
+            }
+            let paused = (control.is_paused(), progress.motion().0);
+            control.resume();
+            assert_eq!(Some(paused), tool_change.map(|i| (true, i)));
+            plot.join().unwrap().unwrap();
+        });
+        assert_eq!(beam.step, CurrentStep(400));
+        assert_eq!(beam.pen, Some(Pen::Down));
+    }
+
+    #[test]
+    fn axis_error_is_returned() {
+        let (mut central, mut beam) = axes();
+        let control = virtual_control();
+        let motions = motions(Point2::new(0.0, -90.0), 1000.0, &control);
+        let e = execute(
+            &motions,
+            &mut central,
+            &mut beam,
+            &Progress::default(),
+            &control,
+        )
+        .unwrap_err();
+        assert!(!e.is::<Aborted>(), "{e:?}");
+    }
+}
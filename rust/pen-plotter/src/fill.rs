@@ -0,0 +1,578 @@
+use crate::{
+    geometry::{self, Tolerance},
+    job::Stroke,
+};
+use eyre::eyre;
+use nalgebra::{Point2, Rotation2, Vector2};
+use std::{
+    collections::{HashMap, HashSet},
+    f64::consts::{FRAC_PI_2, TAU},
+    str::FromStr,
+};
+
+/// of the distance grid for concentric fills, relative to the spacing
+const CELLS_PER_SPACING: f64 = 4.0;
+
+/// of the distance grid, each cell measures its distance to every edge
+const MAX_CELLS: f64 = 250_000.0;
+
+/// Lines drawn inside closed shapes, because the pen can only draw lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    pub pattern: Pattern,
+
+    /// between neighbouring lines in millimeter
+    pub spacing: f64,
+
+    /// of hatch lines in radians counterclockwise from the x axis
+    pub angle: f64,
+
+    /// of the plotter in job coordinates, polar fills draw arcs about it
+    pub center: Point2<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// parallel lines
+    Hatch,
+
+    /// parallel lines and lines perpendicular to them
+    CrossHatch,
+
+    /// outlines shrunk by the spacing again and again
+    Concentric,
+
+    /// arcs about the centre of the plotter, which mostly turn the central motor
+    Polar,
+}
+
+/// Which points are inside rings that overlap or are nested, like SVG `fill-rule`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FillRule {
+    /// where the rings wind around a point at all, holes must run the other way
+    #[default]
+    NonZero,
+
+    /// inside an odd number of rings
+    EvenOdd,
+}
+
+impl FillRule {
+    /// from the sum of the directions of the edges crossing a ray from the point
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            Self::NonZero => winding != 0,
+            Self::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "hatch" => Ok(Self::Hatch),
+            "cross-hatch" => Ok(Self::CrossHatch),
+            "concentric" => Ok(Self::Concentric),
+            "polar" => Ok(Self::Polar),
+            _ => Err(eyre!(
+                "unknown fill {s:?}, use hatch, cross-hatch, concentric or polar"
+            )),
+        }
+    }
+}
+
+/// Fills the area inside `rings` by the fill rule.
+/// Rings are closed implicitly.
+pub fn fill(
+    rings: &[Stroke],
+    fill: &Fill,
+    rule: FillRule,
+    tolerance: &Tolerance,
+) -> eyre::Result<Vec<Stroke>> {
+    let rings: Vec<&Stroke> = rings.iter().filter(|r| r.len() >= 3).collect();
+    if rings.is_empty() {
+        return Ok(Vec::new());
+    }
+    let strokes = match fill.pattern {
+        Pattern::Hatch => hatch(&rings, rule, fill.angle, fill.spacing),
+        Pattern::CrossHatch => {
+            let mut strokes = hatch(&rings, rule, fill.angle, fill.spacing);
+            strokes.extend(hatch(&rings, rule, fill.angle + FRAC_PI_2, fill.spacing));
+            strokes
+        }
+        Pattern::Concentric => concentric(&rings, rule, fill.spacing)?,
+        Pattern::Polar => polar(&rings, rule, fill.center, fill.spacing, tolerance),
+    };
+    Ok(strokes)
+}
+
+/// of all rings, each closed
+fn edges<'a>(rings: &'a [&Stroke]) -> impl Iterator<Item = (Point2<f64>, Point2<f64>)> + 'a {
+    rings.iter().flat_map(|ring| {
+        ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    })
+}
+
+/// +1 for edges upwards and -1 for edges downwards across the horizontal line at `y`
+fn crossings<'a>(rings: &'a [&Stroke], y: f64) -> impl Iterator<Item = (f64, i32)> + 'a {
+    edges(rings)
+        .filter(move |(a, b)| (a.y <= y) != (b.y <= y))
+        .map(move |(a, b)| {
+            let x = a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y);
+            (x, if b.y > a.y { 1 } else { -1 })
+        })
+}
+
+fn contains(rings: &[&Stroke], rule: FillRule, point: Point2<f64>) -> bool {
+    let winding = crossings(rings, point.y)
+        .filter(|&(x, _)| point.x < x)
+        .map(|(_, direction)| direction)
+        .sum();
+    rule.is_inside(winding)
+}
+
+/// of the horizontal line at `y` inside the rings, from left to right
+fn spans(rings: &[&Stroke], rule: FillRule, y: f64) -> Vec<(f64, f64)> {
+    let mut crossings: Vec<(f64, i32)> = crossings(rings, y).collect();
+    crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut spans = Vec::new();
+    let (mut winding, mut start) = (0, None);
+    for (x, direction) in crossings {
+        winding += direction;
+        match (rule.is_inside(winding), start) {
+            (true, None) => start = Some(x),
+            (false, Some(from)) => {
+                spans.push((from, x));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// Alternates the direction of the lines to keep the travel between them short.
+fn hatch(rings: &[&Stroke], rule: FillRule, angle: f64, spacing: f64) -> Vec<Stroke> {
+    let to_lines = Rotation2::new(-angle);
+    let rotated: Vec<Stroke> = rings
+        .iter()
+        .map(|ring| ring.iter().map(|&p| to_lines * p).collect())
+        .collect();
+    let rotated: Vec<&Stroke> = rotated.iter().collect();
+    let (min, max) = rotated
+        .iter()
+        .flat_map(|r| r.iter())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+            (min.min(p.y), max.max(p.y))
+        });
+    let from_lines = to_lines.inverse();
+    let mut strokes = Vec::new();
+    let mut y = min + spacing / 2.0;
+    let mut reverse = false;
+    while y < max {
+        let mut spans = spans(&rotated, rule, y);
+        if reverse {
+            spans.reverse();
+            spans
+                .iter_mut()
+                .for_each(|(start, end)| std::mem::swap(start, end));
+        }
+        reverse = !reverse;
+        for (start, end) in spans {
+            strokes.push(vec![
+                from_lines * Point2::new(start, y),
+                from_lines * Point2::new(end, y),
+            ]);
+        }
+        y += spacing;
+    }
+    strokes
+}
+
+/// Draws arcs about `center` where they are inside the rings, alternating their direction.
+fn polar(
+    rings: &[&Stroke],
+    rule: FillRule,
+    center: Point2<f64>,
+    spacing: f64,
+    tolerance: &Tolerance,
+) -> Vec<Stroke> {
+    let max_radius = rings
+        .iter()
+        .flat_map(|r| r.iter())
+        .map(|p| nalgebra::distance(p, &center))
+        .fold(0.0, f64::max);
+    let at = |radius: f64, angle: f64| center + Vector2::new(angle.cos(), angle.sin()) * radius;
+    let mut strokes = Vec::new();
+    let mut radius = spacing;
+    let mut reverse = false;
+    while radius < max_radius {
+        let mut angles: Vec<f64> = edges(rings)
+            .flat_map(|(a, b)| circle_crossings(a, b, center, radius))
+            .map(|p| (p.y - center.y).atan2(p.x - center.x).rem_euclid(TAU))
+            .collect();
+        angles.sort_by(f64::total_cmp);
+        angles.dedup_by(|a, b| (*a - *b).abs() < 1e-12);
+        let arcs: Vec<(f64, f64)> = match angles.len() {
+            0 if contains(rings, rule, at(radius, 0.0)) => vec![(0.0, TAU)],
+            0 => Vec::new(),
+            n => (0..n)
+                .map(|i| {
+                    let start = angles[i];
+                    let end = angles.get(i + 1).copied().unwrap_or(angles[0] + TAU);
+                    (start, end - start)
+                })
+                .filter(|&(start, sweep)| contains(rings, rule, at(radius, start + sweep / 2.0)))
+                .collect(),
+        };
+        let mut ring: Vec<Stroke> = arcs
+            .into_iter()
+            .map(|(start, sweep)| {
+                let mut stroke = vec![at(radius, start)];
+                geometry::flatten_arc(center, radius, start, sweep, tolerance, &mut stroke);
+                if reverse {
+                    stroke.reverse();
+                }
+                stroke
+            })
+            .collect();
+        if reverse {
+            ring.reverse();
+        }
+        strokes.extend(ring);
+        reverse = !reverse;
+        radius += spacing;
+    }
+    strokes
+}
+
+/// of the segment from `a` to `b`, excluding `b`
+fn circle_crossings(
+    a: Point2<f64>,
+    b: Point2<f64>,
+    center: Point2<f64>,
+    radius: f64,
+) -> Vec<Point2<f64>> {
+    let d = b - a;
+    let f = a - center;
+    let (qa, qb, qc) = (d.dot(&d), 2.0 * f.dot(&d), f.dot(&f) - radius * radius);
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if qa == 0.0 || discriminant < 0.0 {
+        return Vec::new();
+    }
+    let root = discriminant.sqrt();
+    [(-qb - root) / (2.0 * qa), (-qb + root) / (2.0 * qa)]
+        .into_iter()
+        .filter(|t| (0.0..1.0).contains(t))
+        .map(|t| a + d * t)
+        .collect()
+}
+
+/// Traces lines at multiples of `spacing` inside the rings from the outline
+/// on a grid of distances (marching squares).
+fn concentric(rings: &[&Stroke], rule: FillRule, spacing: f64) -> eyre::Result<Vec<Stroke>> {
+    let grid = Grid::new(rings, rule, spacing / CELLS_PER_SPACING)?;
+    let max = grid.values.iter().copied().fold(0.0, f64::max);
+    let mut strokes = Vec::new();
+    let mut level = spacing;
+    while level < max {
+        strokes.extend(grid.contours(level));
+        level += spacing;
+    }
+    Ok(strokes)
+}
+
+/// Signed distances to the outline, positive inside.
+#[derive(Debug)]
+struct Grid {
+    min: Point2<f64>,
+    cell: f64,
+    columns: usize,
+    values: Vec<f64>,
+}
+
+/// Between two neighbouring grid points, the one at `i`, `j` and the next
+/// to the right or above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GridEdge {
+    i: usize,
+    j: usize,
+    vertical: bool,
+}
+
+impl Grid {
+    /// The margin keeps all contours inside the grid, so they are closed.
+    /// Fails above [`MAX_CELLS`].
+    fn new(rings: &[&Stroke], rule: FillRule, cell: f64) -> eyre::Result<Self> {
+        let (min, max) = rings.iter().flat_map(|r| r.iter()).fold(
+            (
+                Point2::new(f64::INFINITY, f64::INFINITY),
+                Point2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
+            ),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        );
+        let min = min - Vector2::repeat(cell);
+        let columns = ((max.x - min.x) / cell).ceil() + 2.0;
+        let rows = ((max.y - min.y) / cell).ceil() + 2.0;
+        let cells = columns * rows;
+        if !cells.is_finite() || cells > MAX_CELLS {
+            return Err(eyre!(
+                "concentric fill needs {cells} grid cells, more than {MAX_CELLS}, \
+                increase the fill spacing"
+            ));
+        }
+        let (columns, rows) = (columns as usize, rows as usize);
+        let mut grid = Self {
+            min,
+            cell,
+            columns,
+            values: Vec::with_capacity(columns * rows),
+        };
+        for j in 0..rows {
+            for i in 0..columns {
+                let point = grid.point(i, j);
+                let distance = edges(rings)
+                    .map(|(a, b)| geometry::segment_distance(point, a, b))
+                    .fold(f64::INFINITY, f64::min);
+                let inside = contains(rings, rule, point);
+                grid.values.push(if inside { distance } else { -distance });
+            }
+        }
+        Ok(grid)
+    }
+
+    fn rows(&self) -> usize {
+        self.values.len() / self.columns
+    }
+
+    fn point(&self, i: usize, j: usize) -> Point2<f64> {
+        self.min + Vector2::new(i as f64, j as f64) * self.cell
+    }
+
+    fn value(&self, i: usize, j: usize) -> f64 {
+        self.values[j * self.columns + i]
+    }
+
+    fn crossing(&self, edge: GridEdge, level: f64) -> Point2<f64> {
+        let (i, j) = (edge.i, edge.j);
+        let (k, l) = if edge.vertical {
+            (i, j + 1)
+        } else {
+            (i + 1, j)
+        };
+        let (a, b) = (self.value(i, j), self.value(k, l));
+        let t = (level - a) / (b - a);
+        self.point(i, j)
+            .coords
+            .lerp(&self.point(k, l).coords, t)
+            .into()
+    }
+
+    fn contours(&self, level: f64) -> Vec<Stroke> {
+        let mut neighbours: HashMap<GridEdge, Vec<GridEdge>> = HashMap::new();
+        let mut order = Vec::new();
+        for j in 0..self.rows() - 1 {
+            for i in 0..self.columns - 1 {
+                for (a, b) in self.cell_segments(i, j, level) {
+                    neighbours.entry(a).or_default().push(b);
+                    neighbours.entry(b).or_default().push(a);
+                    order.push(a);
+                }
+            }
+        }
+        let mut visited = HashSet::new();
+        let mut strokes = Vec::new();
+        for start in order {
+            if visited.contains(&start) {
+                continue;
+            }
+            let mut stroke = Vec::new();
+            let (mut previous, mut current) = (None, start);
+            loop {
+                visited.insert(current);
+                stroke.push(self.crossing(current, level));
+                let next = neighbours[&current]
+                    .iter()
+                    .copied()
+                    .find(|&n| Some(n) != previous && !visited.contains(&n));
+                match next {
+                    Some(next) => (previous, current) = (Some(current), next),
+                    None => break,
+                }
+            }
+            stroke.push(stroke[0]);
+            strokes.push(stroke);
+        }
+        strokes
+    }
+
+    /// Saddles are split by the value at the centre of the cell.
+    fn cell_segments(&self, i: usize, j: usize, level: f64) -> Vec<(GridEdge, GridEdge)> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let inside = corners.map(|(i, j)| self.value(i, j) > level);
+        let sides = [
+            GridEdge {
+                i,
+                j,
+                vertical: false,
+            },
+            GridEdge {
+                i: i + 1,
+                j,
+                vertical: true,
+            },
+            GridEdge {
+                i,
+                j: j + 1,
+                vertical: false,
+            },
+            GridEdge {
+                i,
+                j,
+                vertical: true,
+            },
+        ];
+        let crossed: Vec<GridEdge> = (0..4)
+            .filter(|&k| inside[k] != inside[(k + 1) % 4])
+            .map(|k| sides[k])
+            .collect();
+        match crossed.len() {
+            2 => vec![(crossed[0], crossed[1])],
+            4 => {
+                let center = corners.iter().map(|&(i, j)| self.value(i, j)).sum::<f64>() / 4.0;
+                let [bottom, right, top, left] = sides;
+                if (center > level) == inside[0] {
+                    vec![(bottom, right), (top, left)]
+                } else {
+                    vec![(left, bottom), (right, top)]
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Tolerance = Tolerance::Fixed(0.01);
+
+    fn square(min: f64, max: f64) -> Stroke {
+        vec![
+            Point2::new(min, min),
+            Point2::new(max, min),
+            Point2::new(max, max),
+            Point2::new(min, max),
+        ]
+    }
+
+    fn fill_with(pattern: Pattern, angle: f64) -> Fill {
+        Fill {
+            pattern,
+            spacing: 1.0,
+            angle,
+            center: Point2::new(0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn hatch_square_with_hole() {
+        let rings = [square(0.0, 10.0), square(4.0, 6.0)];
+        let fill_with = fill_with(Pattern::Hatch, 0.0);
+        let strokes = fill(&rings, &fill_with, FillRule::EvenOdd, &TOLERANCE).unwrap();
+        // 10 lines, the 2 through the hole are split
+        assert_eq!(strokes.len(), 12);
+        assert_eq!(strokes[0], [Point2::new(0.0, 0.5), Point2::new(10.0, 0.5)]);
+        assert_eq!(strokes[1][0], Point2::new(10.0, 1.5));
+        let through_hole: Vec<_> = strokes.iter().filter(|s| s[0].y == 4.5).collect();
+        assert_eq!(through_hole.len(), 2);
+    }
+
+    #[test]
+    fn cross_hatch_adds_perpendicular_lines() {
+        let rings = [square(0.0, 10.0)];
+        let rule = FillRule::NonZero;
+        let hatch = fill(&rings, &fill_with(Pattern::Hatch, 0.3), rule, &TOLERANCE).unwrap();
+        let cross = fill(
+            &rings,
+            &fill_with(Pattern::CrossHatch, 0.3),
+            rule,
+            &TOLERANCE,
+        )
+        .unwrap();
+        assert!(cross.len() > hatch.len());
+        let direction = |s: &Stroke| (s[1] - s[0]).normalize();
+        let last = cross.last().unwrap();
+        assert!(direction(&cross[0]).dot(&direction(last)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn concentric_rings_shrink() {
+        let rings = [square(0.0, 9.0)];
+        let fill_with = fill_with(Pattern::Concentric, 0.0);
+        let strokes = fill(&rings, &fill_with, FillRule::NonZero, &TOLERANCE).unwrap();
+        assert_eq!(strokes.len(), 4);
+        for (k, stroke) in strokes.iter().enumerate() {
+            let level = (k + 1) as f64;
+            assert_eq!(stroke.first(), stroke.last());
+            let min_x = stroke.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+            assert!((min_x - level).abs() < 0.1, "ring {k} min x {min_x}");
+        }
+    }
+
+    #[test]
+    fn polar_arcs_about_center() {
+        let rings = [square(-10.5, 10.5), square(-3.5, 3.5)];
+        let fill = fill_with(Pattern::Polar, 0.0);
+        let strokes = super::fill(&rings, &fill, FillRule::EvenOdd, &TOLERANCE).unwrap();
+        for stroke in &strokes {
+            let radius = nalgebra::distance(&stroke[0], &fill.center);
+            for p in stroke {
+                assert!((nalgebra::distance(p, &fill.center) - radius).abs() < 1e-9);
+                assert!(p.x.abs().max(p.y.abs()) > 3.5 - 1e-9);
+            }
+        }
+        // full circles from radius 5 to 10, 4 arcs around the hole at radius 4
+        // and in the corners from radius 11 to 14
+        let full = strokes
+            .iter()
+            .filter(|s| nalgebra::distance(&s[0], &s[s.len() - 1]) < 1e-9)
+            .count();
+        assert_eq!(full, 6);
+        assert_eq!(strokes.len(), 6 + 4 + 4 * 4);
+    }
+
+    #[test]
+    fn nonzero_fills_rings_in_the_same_direction() {
+        let mut hole = square(4.0, 6.0);
+        hole.reverse();
+        let fill_with = fill_with(Pattern::Hatch, 0.0);
+        for (inner, expected) in [(square(4.0, 6.0), 10), (hole, 12)] {
+            let rings = [square(0.0, 10.0), inner];
+            let strokes = fill(&rings, &fill_with, FillRule::NonZero, &TOLERANCE).unwrap();
+            assert_eq!(strokes.len(), expected);
+        }
+    }
+
+    #[test]
+    fn concentric_fails_above_max_cells() {
+        let rings = [square(0.0, 1000.0)];
+        let fill_with = fill_with(Pattern::Concentric, 0.0);
+        let e = fill(&rings, &fill_with, FillRule::NonZero, &TOLERANCE).unwrap_err();
+        assert!(e.to_string().contains("grid cells"), "{e}");
+    }
+
+    #[test]
+    fn parse_pattern() {
+        assert_eq!(
+            "cross-hatch".parse::<Pattern>().unwrap(),
+            Pattern::CrossHatch
+        );
+        let _ = "solid".parse::<Pattern>().unwrap_err();
+    }
+}
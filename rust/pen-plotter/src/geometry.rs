@@ -0,0 +1,281 @@
+use crate::stepper::{beam::BeamStepper, central::CentralStepper};
+use nalgebra::{Point2, Vector2};
+use std::f64::consts::{PI, TAU};
+
+/// of halving a Bézier curve, limits a curve to 2^16 lines
+const MAX_DEPTH: u32 = 16;
+
+/// Largest distance in millimeter of approximating lines from the exact geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    Fixed(f64),
+
+    /// Distance a single step of the coarser motor moves the pen at each point.
+    /// Finer details can not be plotted, so the tolerance grows with the radius.
+    StepResolution {
+        /// in millimeter per beam step
+        beam: f64,
+
+        /// in radians per central step
+        central: f64,
+
+        /// of the job coordinates from the centre
+        origin: Vector2<f64>,
+    },
+}
+
+impl Tolerance {
+    pub fn step_resolution(
+        central: &CentralStepper,
+        beam: &BeamStepper,
+        origin: Vector2<f64>,
+    ) -> Self {
+        Self::StepResolution {
+            beam: 1.0 / beam.steps_per_mm(),
+            central: TAU / f64::from(central.steps_per_rev()),
+            origin,
+        }
+    }
+
+    /// at `point` in job coordinates
+    pub fn at(&self, point: Point2<f64>) -> f64 {
+        match *self {
+            Self::Fixed(tolerance) => tolerance,
+            Self::StepResolution {
+                beam,
+                central,
+                origin,
+            } => {
+                let radius = (point.coords + origin).norm();
+                beam.max(central * radius)
+            }
+        }
+    }
+
+    /// the smallest at `points`
+    pub fn min(&self, points: &[Point2<f64>]) -> f64 {
+        points
+            .iter()
+            .map(|&p| self.at(p))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// Drops points closer to the line through the kept points around them than the tolerance
+/// (Ramer–Douglas–Peucker).
+/// The first and the last point are always kept.
+pub fn simplify(points: &[Point2<f64>], tolerance: &Tolerance) -> Vec<Point2<f64>> {
+    if points.len() <= 2 {
+        return points.to_vec();
+    }
+    let last = points.len() - 1;
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[last] = true;
+    let mut ranges = vec![(0, last)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| (i, segment_distance(points[i], points[start], points[end])))
+            .fold(
+                None,
+                |farthest: Option<(usize, f64)>, next| match farthest {
+                    Some(f) if f.1 >= next.1 => Some(f),
+                    _ => Some(next),
+                },
+            );
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance.at(points[i]) {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(&p, keep)| keep.then_some(p))
+        .collect()
+}
+
+/// of `point` from the segment between `start` and `end`
+pub fn segment_distance(point: Point2<f64>, start: Point2<f64>, end: Point2<f64>) -> f64 {
+    let direction = end - start;
+    let length_squared = direction.norm_squared();
+    if length_squared == 0.0 {
+        return nalgebra::distance(&point, &start);
+    }
+    let t = ((point - start).dot(&direction) / length_squared).clamp(0.0, 1.0);
+    nalgebra::distance(&point, &(start + direction * t))
+}
+
+/// Appends lines approximating the quadratic Bézier curve to `points`,
+/// which end at the start of the curve.
+pub fn flatten_quadratic(
+    [p0, p1, p2]: [Point2<f64>; 3],
+    tolerance: &Tolerance,
+    points: &mut Vec<Point2<f64>>,
+) {
+    let c1 = p0 + (p1 - p0) * (2.0 / 3.0);
+    let c2 = p2 + (p1 - p2) * (2.0 / 3.0);
+    flatten_cubic([p0, c1, c2, p2], tolerance, points);
+}
+
+/// Appends lines approximating the cubic Bézier curve to `points`,
+/// which end at the start of the curve.
+///
+/// Halves the curve until its control points are within the tolerance of its chord,
+/// the curve lies within their convex hull.
+pub fn flatten_cubic(
+    control: [Point2<f64>; 4],
+    tolerance: &Tolerance,
+    points: &mut Vec<Point2<f64>>,
+) {
+    flatten_cubic_to_depth(control, tolerance, points, 0);
+}
+
+fn flatten_cubic_to_depth(
+    control: [Point2<f64>; 4],
+    tolerance: &Tolerance,
+    points: &mut Vec<Point2<f64>>,
+    depth: u32,
+) {
+    let [p0, p1, p2, p3] = control;
+    let flatness = segment_distance(p1, p0, p3).max(segment_distance(p2, p0, p3));
+    if depth >= MAX_DEPTH || flatness <= tolerance.min(&[p0, p3]) {
+        points.push(p3);
+        return;
+    }
+    let p01 = p0.coords.lerp(&p1.coords, 0.5);
+    let p12 = p1.coords.lerp(&p2.coords, 0.5);
+    let p23 = p2.coords.lerp(&p3.coords, 0.5);
+    let p012 = p01.lerp(&p12, 0.5);
+    let p123 = p12.lerp(&p23, 0.5);
+    let middle = p012.lerp(&p123, 0.5).into();
+    let first = [p0, p01.into(), p012.into(), middle];
+    let second = [middle, p123.into(), p23.into(), p3];
+    flatten_cubic_to_depth(first, tolerance, points, depth + 1);
+    flatten_cubic_to_depth(second, tolerance, points, depth + 1);
+}
+
+/// Appends lines approximating the circular arc to `points`, which end at the start of the arc.
+/// Angles are in radians, a positive `sweep` turns counterclockwise.
+pub fn flatten_arc(
+    center: Point2<f64>,
+    radius: f64,
+    start: f64,
+    sweep: f64,
+    tolerance: &Tolerance,
+    points: &mut Vec<Point2<f64>>,
+) {
+    let at = |angle: f64| center + Vector2::new(angle.cos(), angle.sin()) * radius;
+    let tolerance = tolerance.min(&[at(start), at(start + sweep / 2.0), at(start + sweep)]);
+    // the sagitta of a line spanning `max_angle` equals the tolerance
+    let max_angle = if tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        PI
+    };
+    let lines = (sweep.abs() / max_angle).ceil().max(1.0) as usize;
+    points.extend((1..=lines).map(|i| at(start + sweep * i as f64 / lines as f64)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_distance(points: &[Point2<f64>], center: Point2<f64>, radius: f64) -> f64 {
+        points
+            .windows(2)
+            .map(|w| {
+                let middle = Point2::from(w[0].coords.lerp(&w[1].coords, 0.5));
+                (radius - nalgebra::distance(&middle, &center)).abs()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn simplify_drops_points_within_tolerance() {
+        let points = [
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.01),
+            Point2::new(2.0, -0.01),
+            Point2::new(3.0, 0.0),
+            Point2::new(3.0, 2.0),
+        ];
+        let simplified = simplify(&points, &Tolerance::Fixed(0.05));
+        assert_eq!(
+            simplified,
+            [points[0], Point2::new(3.0, 0.0), Point2::new(3.0, 2.0)]
+        );
+        assert_eq!(simplify(&points, &Tolerance::Fixed(0.001)), points);
+    }
+
+    #[test]
+    fn simplify_keeps_closed_stroke() {
+        let square = [
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(0.0, 1.0),
+            Point2::new(0.0, 0.0),
+        ];
+        assert_eq!(simplify(&square, &Tolerance::Fixed(0.1)), square);
+    }
+
+    #[test]
+    fn flatten_cubic_within_tolerance() {
+        // approximates a quarter circle within 0.003 mm
+        let k = 0.552_284_749_8;
+        let control = [
+            Point2::new(10.0, 0.0),
+            Point2::new(10.0, 10.0 * k),
+            Point2::new(10.0 * k, 10.0),
+            Point2::new(0.0, 10.0),
+        ];
+        let coarse = {
+            let mut points = vec![control[0]];
+            flatten_cubic(control, &Tolerance::Fixed(0.1), &mut points);
+            points
+        };
+        let mut fine = vec![control[0]];
+        flatten_cubic(control, &Tolerance::Fixed(0.005), &mut fine);
+        assert!(coarse.len() < fine.len());
+        assert_eq!(fine.last(), Some(&control[3]));
+        assert!(max_distance(&coarse, Point2::origin(), 10.0) < 0.1);
+        assert!(max_distance(&fine, Point2::origin(), 10.0) < 0.01);
+    }
+
+    #[test]
+    fn flatten_straight_quadratic_to_one_line() {
+        let mut points = vec![Point2::new(0.0, 0.0)];
+        let control = [points[0], Point2::new(1.0, 1.0), Point2::new(2.0, 2.0)];
+        flatten_quadratic(control, &Tolerance::Fixed(0.01), &mut points);
+        assert_eq!(points, [control[0], control[2]]);
+    }
+
+    #[test]
+    fn flatten_arc_within_tolerance() {
+        let center = Point2::new(5.0, -20.0);
+        let mut points = vec![Point2::new(15.0, -20.0)];
+        flatten_arc(center, 10.0, 0.0, -PI, &Tolerance::Fixed(0.01), &mut points);
+        assert!(max_distance(&points, center, 10.0) <= 0.01);
+        let end = points.last().unwrap();
+        assert!(nalgebra::distance(end, &Point2::new(-5.0, -20.0)) < 1e-9);
+        assert!(points[1].y < -20.0);
+    }
+
+    #[test]
+    fn step_resolution_grows_with_radius() {
+        let tolerance = Tolerance::step_resolution(
+            &CentralStepper::default(),
+            &BeamStepper::default(),
+            Vector2::new(0.0, -10.0),
+        );
+        let beam = 1.0 / BeamStepper::default().steps_per_mm();
+        assert_eq!(tolerance.at(Point2::new(0.0, 10.0)), beam);
+        let far = tolerance.at(Point2::new(0.0, -1000.0));
+        let expected = 1010.0 * TAU / f64::from(CentralStepper::default().steps_per_rev());
+        assert!((far - expected).abs() < 1e-9);
+    }
+}
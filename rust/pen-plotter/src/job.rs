@@ -0,0 +1,275 @@
+mod gcode;
+mod hpgl;
+mod points;
+mod svg;
+
+use crate::{
+    fill::Fill,
+    geometry::{self, Tolerance},
+    motion::Segment,
+    path::Path,
+    pen::{Pen, PenNumber, DEFAULT_PEN},
+    tool::Tools,
+};
+use eyre::eyre;
+use nalgebra::{Point2, Vector2};
+use std::cell::Cell;
+
+/// Connected points drawn without lifting the pen, in millimeter.
+pub type Stroke = Vec<Point2<f64>>;
+
+/// Shorter segments are skipped.
+pub const MIN_SEGMENT_LENGTH: f64 = 1e-6;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Job {
+    /// plotted one after another with a tool change between different pens
+    pub layers: Vec<Layer>,
+}
+
+/// Strokes drawn with the same pen.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Layer {
+    pub pen: PenNumber,
+    pub strokes: Vec<Stroke>,
+}
+
+/// How the geometry of a file becomes strokes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub tolerance: Tolerance,
+
+    /// of closed SVG shapes which are filled, none draws their outlines only
+    pub fill: Option<Fill>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// JSON array of strokes, each an array of `[x, y]` points
+    Points,
+
+    /// G0 travels, G1 draws lines and G2 and G3 arcs, other commands are ignored
+    Gcode,
+
+    /// HP-GL with pens selected by SP, pen up and down by PU and PD
+    Hpgl,
+
+    /// lines, polylines, polygons, rectangles, circles, ellipses and paths without transforms
+    Svg,
+}
+
+impl Format {
+    pub fn from_extension(path: &std::path::Path) -> eyre::Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Points),
+            "gcode" | "nc" | "g" => Ok(Self::Gcode),
+            "hpgl" | "hpg" | "plt" => Ok(Self::Hpgl),
+            "svg" => Ok(Self::Svg),
+            _ => Err(eyre!("unknown job format of {path:?}")),
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> eyre::Result<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime {
+            "application/json" => Ok(Self::Points),
+            "text/x-gcode" | "text/plain" => Ok(Self::Gcode),
+            "application/vnd.hp-hpgl" => Ok(Self::Hpgl),
+            "image/svg+xml" => Ok(Self::Svg),
+            _ => Err(eyre!("unknown job content type {content_type:?}")),
+        }
+    }
+}
+
+impl Job {
+    /// Draws all strokes with the [`DEFAULT_PEN`].
+    pub fn new(strokes: Vec<Stroke>) -> Self {
+        Self {
+            layers: vec![Layer {
+                pen: DEFAULT_PEN,
+                strokes,
+            }],
+        }
+    }
+
+    /// Joins the layers of each pen, ordered by pen number.
+    pub fn from_layers(layers: Vec<Layer>) -> Self {
+        let mut joined: Vec<Layer> = Vec::new();
+        for layer in layers {
+            match joined.iter_mut().find(|l| l.pen == layer.pen) {
+                Some(same_pen) => same_pen.strokes.extend(layer.strokes),
+                None => joined.push(layer),
+            }
+        }
+        joined.sort_by_key(|l| l.pen);
+        Self { layers: joined }
+    }
+
+    /// Flattens curves and drops points within the tolerance.
+    pub fn parse(format: Format, text: &str, options: &Options) -> eyre::Result<Self> {
+        let tolerance = &options.tolerance;
+        let job = match format {
+            Format::Points => Self::new(points::parse(text)?),
+            Format::Gcode => Self::new(gcode::parse(text, tolerance)?),
+            Format::Hpgl => Self::from_layers(hpgl::parse(text)?),
+            Format::Svg => Self::from_layers(svg::parse(text, options)?),
+        };
+        Ok(job.simplify(tolerance))
+    }
+
+    /// of all layers
+    pub fn strokes(&self) -> impl Iterator<Item = &Stroke> {
+        self.layers.iter().flat_map(|l| &l.strokes)
+    }
+
+    /// Drops points of the strokes within `tolerance` of the lines between the others.
+    pub fn simplify(mut self, tolerance: &Tolerance) -> Self {
+        for stroke in self.layers.iter_mut().flat_map(|l| &mut l.strokes) {
+            *stroke = geometry::simplify(stroke, tolerance);
+        }
+        self
+    }
+
+    pub fn translate(mut self, offset: Vector2<f64>) -> Self {
+        let strokes = self.layers.iter_mut().flat_map(|l| &mut l.strokes);
+        for point in strokes.flatten() {
+            *point += offset;
+        }
+        self
+    }
+
+    /// Travels with the pen up from `location` to each stroke and draws it with the pen down.
+    /// Strokes with less than two points are skipped.
+    ///
+    /// Before layers with another pen than the previous one, starting with the
+    /// [`DEFAULT_PEN`], it travels to the park location and holds for a tool change.
+    pub fn segments(&self, location: Point2<f64>, tools: &Tools) -> Vec<Segment> {
+        let park = tools.park.map_or(location, Point2::from);
+        let mut location = location;
+        let mut current_pen = DEFAULT_PEN;
+        let tool_change = Cell::new(None);
+        let mut segments = Vec::new();
+        for layer in &self.layers {
+            let strokes: Vec<&Stroke> = layer.strokes.iter().filter(|s| s.len() >= 2).collect();
+            if strokes.is_empty() {
+                continue;
+            }
+            let velocity = tools.velocity(layer.pen);
+            let pen_delay = tools.pen_delay(layer.pen);
+            let mut push = |start, end, pen| {
+                if nalgebra::distance(&start, &end) >= MIN_SEGMENT_LENGTH {
+                    let path = Path {
+                        start,
+                        end,
+                        velocity,
+                    };
+                    segments.push(Segment {
+                        path,
+                        pen,
+                        pen_delay,
+                        tool_change: tool_change.take(),
+                    });
+                }
+            };
+            if layer.pen != current_pen {
+                push(location, park, Pen::Up);
+                location = park;
+                current_pen = layer.pen;
+                tool_change.set(Some(layer.pen));
+            }
+            for stroke in strokes {
+                push(location, stroke[0], Pen::Up);
+                for w in stroke.windows(2) {
+                    push(w[0], w[1], Pen::Down);
+                }
+                location = stroke[stroke.len() - 1];
+            }
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::PenSettings;
+
+    #[test]
+    fn segments_travel_between_strokes() {
+        let job = Job::new(vec![
+            vec![Point2::new(0.0, -10.0), Point2::new(0.0, -20.0)],
+            vec![Point2::new(5.0, -20.0), Point2::new(5.0, -10.0)],
+        ]);
+        let pens: Vec<_> = job
+            .segments(Point2::new(0.0, -10.0), &Tools::new(1.0))
+            .iter()
+            .map(|s| s.pen)
+            .collect();
+        assert_eq!(pens, [Pen::Down, Pen::Up, Pen::Down]);
+    }
+
+    #[test]
+    fn segments_skip_single_points() {
+        let job = Job::new(vec![vec![Point2::new(3.0, -10.0)]]);
+        assert!(job.segments(Point2::origin(), &Tools::new(1.0)).is_empty());
+    }
+
+    #[test]
+    fn segments_park_before_tool_change() {
+        let layer = |pen, x| Layer {
+            pen,
+            strokes: vec![vec![Point2::new(x, -10.0), Point2::new(x, -20.0)]],
+        };
+        let job = Job::from_layers(vec![layer(2, 5.0), layer(1, 0.0), layer(3, 5.0)]);
+        let mut tools = Tools::new(1.0);
+        tools.park = Some([0.0, -50.0]);
+        tools.pens = vec![PenSettings {
+            number: 2,
+            velocity: Some(4.0),
+            pen_delay: None,
+        }];
+        let segments = job.segments(Point2::new(0.0, -10.0), &tools);
+        let changes: Vec<_> = segments.iter().map(|s| s.tool_change).collect();
+        assert_eq!(changes, [None, None, Some(2), None, None, Some(3), None]);
+        assert_eq!(segments[1].path.end, Point2::new(0.0, -50.0));
+        assert_eq!(segments[2].path.start, Point2::new(0.0, -50.0));
+        assert_eq!(segments[3].path.velocity, 4.0);
+        assert_eq!(segments[6].path.velocity, 1.0);
+    }
+
+    #[test]
+    fn from_layers_joins_same_pen() {
+        let stroke = vec![Point2::new(0.0, -10.0), Point2::new(0.0, -20.0)];
+        let layer = |pen| Layer {
+            pen,
+            strokes: vec![stroke.clone()],
+        };
+        let job = Job::from_layers(vec![layer(3), layer(1), layer(3)]);
+        let pens: Vec<_> = job.layers.iter().map(|l| l.pen).collect();
+        assert_eq!(pens, [1, 3]);
+        assert_eq!(job.layers[1].strokes.len(), 2);
+    }
+
+    #[test]
+    fn parse_simplifies_dense_strokes() {
+        let text = "[[[0, -10], [1, -10.001], [2, -10], [2, -20]]]";
+        let options = Options {
+            tolerance: Tolerance::Fixed(0.01),
+            fill: None,
+        };
+        let job = Job::parse(Format::Points, text, &options).unwrap();
+        let expected = vec![vec![
+            Point2::new(0.0, -10.0),
+            Point2::new(2.0, -10.0),
+            Point2::new(2.0, -20.0),
+        ]];
+        assert_eq!(job, Job::new(expected));
+    }
+
+    #[test]
+    fn format_from_content_type_with_charset() {
+        let actual = Format::from_content_type("text/plain; charset=utf-8").unwrap();
+        assert_eq!(actual, Format::Gcode);
+    }
+}
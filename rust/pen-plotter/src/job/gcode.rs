@@ -0,0 +1,210 @@
+use super::Stroke;
+use crate::geometry::{self, Tolerance};
+use eyre::{eyre, WrapErr};
+use nalgebra::{Point2, Vector2};
+use std::f64::consts::TAU;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Travel,
+    Draw,
+    Clockwise,
+    Counterclockwise,
+}
+
+/// Starts at the origin in absolute millimeter.
+/// Arcs are flattened to lines within `tolerance`, their centers are given relative
+/// to their start by I and J.
+pub fn parse(text: &str, tolerance: &Tolerance) -> eyre::Result<Vec<Stroke>> {
+    let mut strokes = Vec::new();
+    let mut stroke: Stroke = Vec::new();
+    let mut location = Point2::origin();
+    let mut mode = Mode::Travel;
+    let mut absolute = true;
+    let mut scale = 1.0;
+    for (number, line) in text.lines().enumerate() {
+        let words = words(line).wrap_err_with(|| format!("failed to parse line {}", number + 1))?;
+        let mut x = None;
+        let mut y = None;
+        let mut center = Vector2::zeros();
+        for (letter, value) in words {
+            match (letter, value) {
+                ('G', v) if v.fract() == 0.0 => match v as i32 {
+                    0 => mode = Mode::Travel,
+                    1 => mode = Mode::Draw,
+                    2 => mode = Mode::Clockwise,
+                    3 => mode = Mode::Counterclockwise,
+                    20 => scale = 25.4,
+                    21 => scale = 1.0,
+                    90 => absolute = true,
+                    91 => absolute = false,
+                    _ => {}
+                },
+                ('X', v) => x = Some(v * scale),
+                ('Y', v) => y = Some(v * scale),
+                ('I', v) => center.x = v * scale,
+                ('J', v) => center.y = v * scale,
+                ('R', _) => {
+                    return Err(eyre!(
+                        "arcs with radius R are not supported, use I and J, line {}",
+                        number + 1
+                    ))
+                }
+                _ => {}
+            }
+        }
+        let is_arc = matches!(mode, Mode::Clockwise | Mode::Counterclockwise);
+        if x.is_none() && y.is_none() && !(is_arc && center != Vector2::zeros()) {
+            continue;
+        }
+        let target = if absolute {
+            Point2::new(x.unwrap_or(location.x), y.unwrap_or(location.y))
+        } else {
+            location + Vector2::new(x.unwrap_or(0.0), y.unwrap_or(0.0))
+        };
+        match mode {
+            Mode::Travel => {
+                finish(&mut strokes, &mut stroke);
+            }
+            Mode::Draw => {
+                if stroke.is_empty() {
+                    stroke.push(location);
+                }
+                stroke.push(target);
+            }
+            Mode::Clockwise | Mode::Counterclockwise => {
+                if stroke.is_empty() {
+                    stroke.push(location);
+                }
+                arc(
+                    location,
+                    target,
+                    location + center,
+                    mode,
+                    tolerance,
+                    &mut stroke,
+                );
+            }
+        }
+        location = target;
+    }
+    finish(&mut strokes, &mut stroke);
+    Ok(strokes)
+}
+
+/// Ends at `end` even if it is not exactly on the circle through `start`.
+fn arc(
+    start: Point2<f64>,
+    end: Point2<f64>,
+    center: Point2<f64>,
+    mode: Mode,
+    tolerance: &Tolerance,
+    stroke: &mut Stroke,
+) {
+    let angle = |p: Point2<f64>| (p.y - center.y).atan2(p.x - center.x);
+    let start_angle = angle(start);
+    let counterclockwise = (angle(end) - start_angle).rem_euclid(TAU);
+    let sweep = match mode {
+        Mode::Counterclockwise if counterclockwise == 0.0 => TAU,
+        Mode::Counterclockwise => counterclockwise,
+        _ if counterclockwise == 0.0 => -TAU,
+        _ => counterclockwise - TAU,
+    };
+    let radius = nalgebra::distance(&start, &center);
+    geometry::flatten_arc(center, radius, start_angle, sweep, tolerance, stroke);
+    if let Some(last) = stroke.last_mut() {
+        *last = end;
+    }
+}
+
+fn finish(strokes: &mut Vec<Stroke>, stroke: &mut Stroke) {
+    if !stroke.is_empty() {
+        strokes.push(std::mem::take(stroke));
+    }
+}
+
+/// letter and number pairs without comments
+fn words(line: &str) -> eyre::Result<Vec<(char, f64)>> {
+    let line = line.split(';').next().unwrap_or("");
+    let mut words = Vec::new();
+    let mut in_comment = false;
+    let mut current: Option<(char, String)> = None;
+    for c in line.chars() {
+        match c {
+            '(' => in_comment = true,
+            ')' => in_comment = false,
+            _ if in_comment || c.is_whitespace() => {}
+            'a'..='z' | 'A'..='Z' => {
+                words.extend(current.take().map(parse_word).transpose()?);
+                current = Some((c.to_ascii_uppercase(), String::new()));
+            }
+            _ => match &mut current {
+                Some((_, number)) => number.push(c),
+                None => return Err(eyre!("number {c:?} without letter in {line:?}")),
+            },
+        }
+    }
+    words.extend(current.map(parse_word).transpose()?);
+    Ok(words)
+}
+
+fn parse_word((letter, number): (char, String)) -> eyre::Result<(char, f64)> {
+    let value = number
+        .parse()
+        .wrap_err_with(|| format!("failed to parse {letter}{number}"))?;
+    Ok((letter, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Tolerance = Tolerance::Fixed(0.01);
+
+    #[test]
+    fn travel_and_draw() {
+        let text = "G21 G90 (mm)\nG0 X0 Y-10\nG1 X5 F100 ; draw\nY-15\nG0 X7\nG01 X9\n";
+        let actual = parse(text, &TOLERANCE).unwrap();
+        let expected = vec![
+            vec![
+                Point2::new(0.0, -10.0),
+                Point2::new(5.0, -10.0),
+                Point2::new(5.0, -15.0),
+            ],
+            vec![Point2::new(7.0, -15.0), Point2::new(9.0, -15.0)],
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn relative_inches() {
+        let actual = parse("G20 G91\nG1 X1 Y-1\n", &TOLERANCE).unwrap();
+        let expected = vec![vec![Point2::origin(), Point2::new(25.4, -25.4)]];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn clockwise_half_circle() {
+        let strokes = parse("G0 X0 Y-10\nG2 X0 Y-30 I0 J-10\n", &TOLERANCE).unwrap();
+        let stroke = &strokes[0];
+        assert_eq!(stroke[0], Point2::new(0.0, -10.0));
+        assert_eq!(stroke[stroke.len() - 1], Point2::new(0.0, -30.0));
+        assert!(stroke.len() > 10);
+        // clockwise from the top of the circle passes the right
+        assert!(stroke.iter().all(|p| p.x >= -1e-9));
+    }
+
+    #[test]
+    fn counterclockwise_full_circle() {
+        let strokes = parse("G3 I5 J0\n", &TOLERANCE).unwrap();
+        let stroke = &strokes[0];
+        assert_eq!(stroke[stroke.len() - 1], Point2::origin());
+        assert!(stroke.iter().any(|p| (p.x - 10.0).abs() < 1e-9));
+        assert!(stroke[1].y < 0.0);
+    }
+
+    #[test]
+    fn radius_arcs_are_unsupported() {
+        let _ = parse("G2 X1 Y1 R1", &TOLERANCE).unwrap_err();
+    }
+}
@@ -0,0 +1,177 @@
+use super::{Layer, Stroke};
+use crate::pen::{PenNumber, DEFAULT_PEN};
+use eyre::{eyre, WrapErr};
+use nalgebra::{Point2, Vector2};
+
+/// plotter units of 0.025 mm
+const UNITS_PER_MM: f64 = 40.0;
+
+/// ends the text of a label
+const END_OF_TEXT: char = '\u{3}';
+
+/// Starts at the origin in absolute plotter units of 0.025 mm with the [`DEFAULT_PEN`].
+/// Each SP starts a layer, SP0 which puts the pen away is ignored,
+/// as are other commands than IN, PU, PD, PA and PR.
+pub fn parse(text: &str) -> eyre::Result<Vec<Layer>> {
+    let mut layers = Vec::new();
+    let mut layer = Layer {
+        pen: DEFAULT_PEN,
+        strokes: Vec::new(),
+    };
+    let mut stroke: Stroke = Vec::new();
+    let mut location = Point2::origin();
+    let mut down = false;
+    let mut absolute = true;
+    for (mnemonic, parameters) in commands(text)? {
+        match mnemonic.as_str() {
+            "IN" => {
+                finish(&mut layer.strokes, &mut stroke);
+                location = Point2::origin();
+                down = false;
+                absolute = true;
+            }
+            "SP" => {
+                let pen = match parameters[..] {
+                    [] => 0.0,
+                    [pen] => pen,
+                    _ => return Err(eyre!("SP takes one pen number, got {parameters:?}")),
+                };
+                if pen < 0.0 || pen > f64::from(PenNumber::MAX) || pen.fract() != 0.0 {
+                    return Err(eyre!("bad pen number {pen}"));
+                }
+                let pen = pen as PenNumber;
+                if pen != 0 && pen != layer.pen {
+                    finish(&mut layer.strokes, &mut stroke);
+                    let previous = std::mem::replace(
+                        &mut layer,
+                        Layer {
+                            pen,
+                            strokes: Vec::new(),
+                        },
+                    );
+                    layers.push(previous);
+                }
+            }
+            "PU" | "PD" | "PA" | "PR" => {
+                match mnemonic.as_str() {
+                    "PU" => down = false,
+                    "PD" => down = true,
+                    "PA" => absolute = true,
+                    _ => absolute = false,
+                }
+                if !down {
+                    finish(&mut layer.strokes, &mut stroke);
+                }
+                if parameters.len() % 2 != 0 {
+                    return Err(eyre!("odd number of coordinates for {mnemonic}"));
+                }
+                for pair in parameters.chunks_exact(2) {
+                    let value = Vector2::new(pair[0], pair[1]) / UNITS_PER_MM;
+                    let target = if absolute {
+                        Point2::from(value)
+                    } else {
+                        location + value
+                    };
+                    if down {
+                        if stroke.is_empty() {
+                            stroke.push(location);
+                        }
+                        stroke.push(target);
+                    }
+                    location = target;
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(&mut layer.strokes, &mut stroke);
+    layers.push(layer);
+    layers.retain(|l| !l.strokes.is_empty());
+    Ok(layers)
+}
+
+fn finish(strokes: &mut Vec<Stroke>, stroke: &mut Stroke) {
+    if !stroke.is_empty() {
+        strokes.push(std::mem::take(stroke));
+    }
+}
+
+/// two letter mnemonics with their numeric parameters, labels are skipped
+fn commands(text: &str) -> eyre::Result<Vec<(String, Vec<f64>)>> {
+    let mut commands = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() || c == ';' {
+            continue;
+        }
+        let second = chars.next().unwrap_or(' ');
+        if !c.is_ascii_alphabetic() || !second.is_ascii_alphabetic() {
+            return Err(eyre!("expected a command instead of {c:?}{second:?}"));
+        }
+        let mnemonic = format!("{c}{second}").to_ascii_uppercase();
+        if mnemonic == "LB" {
+            chars.by_ref().find(|&c| c == END_OF_TEXT);
+            continue;
+        }
+        let mut arguments = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == ';' || c.is_ascii_alphabetic() {
+                break;
+            }
+            arguments.push(c);
+            chars.next();
+        }
+        let parameters = arguments
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|a| !a.is_empty())
+            .map(|a| {
+                a.parse()
+                    .wrap_err_with(|| format!("failed to parse {a:?} of {mnemonic}"))
+            })
+            .collect::<eyre::Result<_>>()?;
+        commands.push((mnemonic, parameters));
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pen_up_and_down() {
+        let text = "IN;PU0,-400;PD200,-400,200,-600;PU;PR80,0;PD40,0;";
+        let layers = parse(text).unwrap();
+        let expected = vec![Layer {
+            pen: DEFAULT_PEN,
+            strokes: vec![
+                vec![
+                    Point2::new(0.0, -10.0),
+                    Point2::new(5.0, -10.0),
+                    Point2::new(5.0, -15.0),
+                ],
+                vec![Point2::new(7.0, -15.0), Point2::new(8.0, -15.0)],
+            ],
+        }];
+        assert_eq!(layers, expected);
+    }
+
+    #[test]
+    fn select_pens() {
+        let text = "IN;SP2;PU0 0;PD40 0;SP3;PD40 40;SP0;LBSP1 PD;\u{3}PU;";
+        let layers = parse(text).unwrap();
+        let pens: Vec<_> = layers.iter().map(|l| l.pen).collect();
+        assert_eq!(pens, [2, 3]);
+        assert_eq!(
+            layers[1].strokes,
+            [vec![Point2::new(1.0, 0.0), Point2::new(1.0, 1.0)]]
+        );
+    }
+
+    #[test]
+    fn parse_bad_command() {
+        let _ = parse("IN;PD1,x;").unwrap_err();
+        let _ = parse("IN;PD1;").unwrap_err();
+        let _ = parse("IN;SP-1;").unwrap_err();
+    }
+}
@@ -0,0 +1,32 @@
+use super::Stroke;
+use eyre::WrapErr;
+use nalgebra::Point2;
+
+pub fn parse(text: &str) -> eyre::Result<Vec<Stroke>> {
+    let strokes: Vec<Vec<[f64; 2]>> =
+        serde_json::from_str(text).wrap_err("failed to parse point list")?;
+    Ok(strokes
+        .into_iter()
+        .map(|s| s.into_iter().map(Point2::from).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_strokes() {
+        let actual = parse("[[[0, -10], [1.5, -12]], [[3, -10]]]").unwrap();
+        let expected = vec![
+            vec![Point2::new(0.0, -10.0), Point2::new(1.5, -12.0)],
+            vec![Point2::new(3.0, -10.0)],
+        ];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn parse_bad_point() {
+        let _ = parse("[[[0, -10, 3]]]").unwrap_err();
+    }
+}
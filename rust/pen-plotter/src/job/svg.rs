@@ -0,0 +1,352 @@
+use super::{Layer, Options, Stroke};
+use crate::{
+    fill::{self, FillRule},
+    geometry::{self, Tolerance},
+    pen::{PenNumber, DEFAULT_PEN},
+};
+use eyre::{eyre, WrapErr};
+use nalgebra::{Point2, Vector2};
+use roxmltree::{Document, Node};
+use std::f64::consts::TAU;
+use svgtypes::{PointsParser, SimplePathSegment, SimplifyingPathParser};
+
+const INKSCAPE: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+/// User units are millimeter, transforms are ignored.
+/// Curves are flattened to lines within the tolerance.
+/// With a fill, shapes are filled by their fill-rule unless their fill is none.
+/// Elements of Inkscape layers labeled with a number are drawn with that pen.
+pub fn parse(text: &str, options: &Options) -> eyre::Result<Vec<Layer>> {
+    let tolerance = &options.tolerance;
+    let document = Document::parse(text).wrap_err("failed to parse SVG")?;
+    let mut layers: Vec<Layer> = Vec::new();
+    for node in document.descendants().filter(Node::is_element) {
+        let name = node.tag_name().name();
+        let element_strokes = match name {
+            "path" => path(attribute(node, "d")?, tolerance)?,
+            "line" => vec![vec![
+                Point2::new(number(node, "x1")?, number(node, "y1")?),
+                Point2::new(number(node, "x2")?, number(node, "y2")?),
+            ]],
+            "polyline" => vec![points(attribute(node, "points")?)],
+            "polygon" => vec![closed(points(attribute(node, "points")?))],
+            "rect" => vec![rect(node)?],
+            "circle" => {
+                let r = number(node, "r")?;
+                vec![ellipse(node, r, r, tolerance)?]
+            }
+            "ellipse" => vec![ellipse(
+                node,
+                number(node, "rx")?,
+                number(node, "ry")?,
+                tolerance,
+            )?],
+            _ => continue,
+        };
+        let pen = pen(node);
+        if layers.last().is_none_or(|l| l.pen != pen) {
+            layers.push(Layer {
+                pen,
+                strokes: Vec::new(),
+            });
+        }
+        let strokes = &mut layers.last_mut().unwrap().strokes;
+        if let Some(fill) = options.fill.filter(|_| name != "line" && is_filled(node)) {
+            strokes.extend(fill::fill(
+                &element_strokes,
+                &fill,
+                fill_rule(node),
+                tolerance,
+            )?);
+        }
+        strokes.extend(element_strokes);
+    }
+    Ok(layers)
+}
+
+/// from the leading digits of the label of the innermost Inkscape layer, like `2 red`
+fn pen(node: Node) -> PenNumber {
+    node.ancestors()
+        .find(|n| n.attribute((INKSCAPE, "groupmode")) == Some("layer"))
+        .and_then(|n| n.attribute((INKSCAPE, "label")))
+        .and_then(|label| {
+            let label = label.trim_start();
+            let end = label
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(label.len());
+            label[..end].parse().ok()
+        })
+        .filter(|&pen| pen > 0)
+        .unwrap_or(DEFAULT_PEN)
+}
+
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> eyre::Result<&'a str> {
+    node.attribute(name)
+        .ok_or_else(|| eyre!("missing attribute {name} of {}", node.tag_name().name()))
+}
+
+/// by the style or attribute of the node or its nearest ancestor setting it
+fn property<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.ancestors().find_map(|n| {
+        let style = n.attribute("style").unwrap_or("").split(';').find_map(|d| {
+            let (property, value) = d.split_once(':')?;
+            (property.trim() == name).then_some(value)
+        });
+        style.or_else(|| n.attribute(name)).map(str::trim)
+    })
+}
+
+/// SVG fills by default
+fn is_filled(node: Node) -> bool {
+    property(node, "fill").is_none_or(|f| f != "none")
+}
+
+/// nonzero by default and for unknown values
+fn fill_rule(node: Node) -> FillRule {
+    match property(node, "fill-rule") {
+        Some("evenodd") => FillRule::EvenOdd,
+        _ => FillRule::NonZero,
+    }
+}
+
+fn number(node: Node, name: &str) -> eyre::Result<f64> {
+    match node.attribute(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .wrap_err_with(|| format!("failed to parse {name}={value:?}")),
+        None => Ok(0.0),
+    }
+}
+
+fn points(text: &str) -> Stroke {
+    PointsParser::from(text)
+        .map(|(x, y)| Point2::new(x, y))
+        .collect()
+}
+
+fn closed(mut stroke: Stroke) -> Stroke {
+    if let Some(&first) = stroke.first() {
+        stroke.push(first);
+    }
+    stroke
+}
+
+fn rect(node: Node) -> eyre::Result<Stroke> {
+    let (x, y) = (number(node, "x")?, number(node, "y")?);
+    let (width, height) = (number(node, "width")?, number(node, "height")?);
+    Ok(closed(vec![
+        Point2::new(x, y),
+        Point2::new(x + width, y),
+        Point2::new(x + width, y + height),
+        Point2::new(x, y + height),
+    ]))
+}
+
+/// Flattens a circle of the larger radius and scales it,
+/// which brings the lines closer to the ellipse.
+fn ellipse(node: Node, rx: f64, ry: f64, tolerance: &Tolerance) -> eyre::Result<Stroke> {
+    let center = Point2::new(number(node, "cx")?, number(node, "cy")?);
+    let radius = rx.max(ry);
+    if radius <= 0.0 {
+        return Err(eyre!(
+            "radius of {} must be positive",
+            node.tag_name().name()
+        ));
+    }
+    let extremes = [
+        center + Vector2::new(rx, 0.0),
+        center + Vector2::new(0.0, ry),
+        center - Vector2::new(rx, 0.0),
+        center - Vector2::new(0.0, ry),
+    ];
+    let tolerance = Tolerance::Fixed(tolerance.min(&extremes));
+    let mut circle = vec![Point2::new(radius, 0.0)];
+    geometry::flatten_arc(Point2::origin(), radius, 0.0, TAU, &tolerance, &mut circle);
+    Ok(circle
+        .into_iter()
+        .map(|p| center + Vector2::new(p.x * rx, p.y * ry) / radius)
+        .collect())
+}
+
+fn path(data: &str, tolerance: &Tolerance) -> eyre::Result<Vec<Stroke>> {
+    let mut strokes = Vec::new();
+    let mut stroke: Stroke = Vec::new();
+    let mut location = Point2::origin();
+    for segment in SimplifyingPathParser::from(data) {
+        let segment = segment.wrap_err_with(|| format!("failed to parse path {data:?}"))?;
+        match segment {
+            SimplePathSegment::MoveTo { x, y } => {
+                if stroke.len() >= 2 {
+                    strokes.push(std::mem::take(&mut stroke));
+                }
+                location = Point2::new(x, y);
+                stroke = vec![location];
+            }
+            SimplePathSegment::LineTo { x, y } => {
+                location = Point2::new(x, y);
+                stroke.push(location);
+            }
+            SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                let control = [
+                    location,
+                    Point2::new(x1, y1),
+                    Point2::new(x2, y2),
+                    Point2::new(x, y),
+                ];
+                geometry::flatten_cubic(control, tolerance, &mut stroke);
+                location = control[3];
+            }
+            SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                let control = [location, Point2::new(x1, y1), Point2::new(x, y)];
+                geometry::flatten_quadratic(control, tolerance, &mut stroke);
+                location = control[2];
+            }
+            SimplePathSegment::ClosePath => {
+                if let Some(&first) = stroke.first() {
+                    stroke.push(first);
+                    location = first;
+                }
+            }
+        }
+    }
+    if stroke.len() >= 2 {
+        strokes.push(stroke);
+    }
+    Ok(strokes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Tolerance = Tolerance::Fixed(0.01);
+
+    const OPTIONS: Options = Options {
+        tolerance: TOLERANCE,
+        fill: None,
+    };
+
+    fn strokes(text: &str, options: &Options) -> Vec<Stroke> {
+        let layers = parse(text, options).unwrap();
+        layers.into_iter().flat_map(|l| l.strokes).collect()
+    }
+
+    #[test]
+    fn parse_elements() {
+        let text = r#"<svg xmlns="http://www.w3.org/2000/svg">
+            <g>
+                <line x1="0" y1="-10" x2="5" y2="-10"/>
+                <polygon points="0,-20 5,-20 5,-25"/>
+                <path d="M 1 -30 h 4 m 1 0 l 1 1"/>
+            </g>
+        </svg>"#;
+        let actual = strokes(text, &OPTIONS);
+        assert_eq!(actual.len(), 4);
+        assert_eq!(actual[1].len(), 4);
+        assert_eq!(actual[1][3], Point2::new(0.0, -20.0));
+        assert_eq!(
+            actual[2],
+            vec![Point2::new(1.0, -30.0), Point2::new(5.0, -30.0)]
+        );
+        assert_eq!(actual[3][1], Point2::new(7.0, -29.0));
+    }
+
+    #[test]
+    fn flatten_curve_ends_at_end_point() {
+        let strokes = path("M 0 0 C 0 10 10 10 10 0", &TOLERANCE).unwrap();
+        let stroke = &strokes[0];
+        assert!(stroke.len() > 8, "{} points", stroke.len());
+        assert_eq!(stroke[stroke.len() - 1], Point2::new(10.0, 0.0));
+        assert!(stroke.contains(&Point2::new(5.0, 7.5)));
+    }
+
+    #[test]
+    fn coarse_tolerance_flattens_to_fewer_lines() {
+        let data = "M 0 0 Q 5 10 10 0";
+        let fine = path(data, &TOLERANCE).unwrap();
+        let coarse = path(data, &Tolerance::Fixed(1.0)).unwrap();
+        assert!(coarse[0].len() < fine[0].len());
+    }
+
+    #[test]
+    fn parse_ellipse() {
+        let text = r#"<svg><ellipse cx="0" cy="-20" rx="10" ry="5"/></svg>"#;
+        let strokes = strokes(text, &OPTIONS);
+        let stroke = &strokes[0];
+        assert_eq!(stroke[0], Point2::new(10.0, -20.0));
+        assert!(nalgebra::distance(&stroke[0], &stroke[stroke.len() - 1]) < 1e-9);
+        assert!(stroke
+            .iter()
+            .all(|p| p.y <= -15.0 + 1e-9 && p.y >= -25.0 - 1e-9));
+    }
+
+    #[test]
+    fn fill_shapes_unless_fill_is_none() {
+        let text = r#"<svg>
+            <rect x="0" y="-20" width="10" height="10"/>
+            <g fill="none"><rect x="20" y="-20" width="10" height="10"/></g>
+            <rect x="40" y="-20" width="10" height="10" style="stroke:red; fill: none"/>
+            <line x1="0" y1="-40" x2="10" y2="-40"/>
+        </svg>"#;
+        let options = Options {
+            fill: Some(fill::Fill {
+                pattern: fill::Pattern::Hatch,
+                spacing: 1.0,
+                angle: 0.0,
+                center: Point2::origin(),
+            }),
+            ..OPTIONS
+        };
+        let strokes = strokes(text, &options);
+        assert_eq!(strokes.len(), 10 + 4);
+        assert!(strokes[..10].iter().all(|s| s[0].x <= 10.0));
+    }
+
+    #[test]
+    fn fill_rule_of_ancestors() {
+        let text = r#"<svg><g style="fill-rule: evenodd">
+            <path d="M 0 0 H 10 V 10 H 0 Z M 4 4 H 6 V 6 H 4 Z"/>
+            <path d="M 20 0 H 30 V 10 H 20 Z M 24 4 H 26 V 6 H 24 Z" fill-rule="nonzero"/>
+        </g></svg>"#;
+        let document = Document::parse(text).unwrap();
+        let paths: Vec<_> = document
+            .descendants()
+            .filter(|n| n.has_tag_name("path"))
+            .map(fill_rule)
+            .collect();
+        assert_eq!(paths, [FillRule::EvenOdd, FillRule::NonZero]);
+    }
+
+    #[test]
+    fn pens_of_inkscape_layers() {
+        let text = r#"<svg xmlns="http://www.w3.org/2000/svg"
+            xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape">
+            <line x1="0" y1="-10" x2="5" y2="-10"/>
+            <g inkscape:groupmode="layer" inkscape:label="3 blue">
+                <g><line x1="0" y1="-20" x2="5" y2="-20"/></g>
+                <g inkscape:groupmode="layer" inkscape:label="Details">
+                    <line x1="0" y1="-30" x2="5" y2="-30"/>
+                </g>
+            </g>
+            <g inkscape:groupmode="layer" inkscape:label="2">
+                <line x1="0" y1="-40" x2="5" y2="-40"/>
+            </g>
+        </svg>"#;
+        let layers = parse(text, &OPTIONS).unwrap();
+        let pens: Vec<_> = layers.iter().map(|l| (l.pen, l.strokes.len())).collect();
+        assert_eq!(pens, [(1, 1), (3, 1), (1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn parse_bad_svg() {
+        let _ = parse("<svg>", &OPTIONS).unwrap_err();
+    }
+}
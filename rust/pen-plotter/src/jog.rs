@@ -0,0 +1,350 @@
+use crate::{
+    axis::{self, Axis},
+    config::Config,
+    control::{Aborted, Control},
+    path::Point,
+    stepper::{beam::BeamStepper, central::CentralStepper, Stepper, TargetStep},
+    vector_2,
+};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode},
+    queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use eyre::WrapErr;
+use nalgebra::{Point2, Vector2};
+use std::{
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// between checks for an abort while waiting for keys
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// in millimeter
+const STEP_SIZES: [f64; 3] = [0.1, 1.0, 10.0];
+
+const HELP: &str = "arrows: move, a/d: rotate, w/s: extend/retract, t: toggle step size, \
+    o: save work origin, q: quit";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// along the unit vector
+    Move(Vector2<f64>),
+
+    /// towards the right for 1.0, towards the left for -1.0
+    Rotate(f64),
+
+    /// outwards for 1.0, inwards for -1.0
+    Extend(f64),
+    ToggleStepSize,
+    SaveOrigin,
+    Quit,
+}
+
+impl Action {
+    pub fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::Up => Some(Self::Move(vector_2::forward())),
+            KeyCode::Down => Some(Self::Move(vector_2::back())),
+            KeyCode::Left => Some(Self::Move(vector_2::left())),
+            KeyCode::Right => Some(Self::Move(vector_2::right())),
+            KeyCode::Char('a') => Some(Self::Rotate(-1.0)),
+            KeyCode::Char('d') => Some(Self::Rotate(1.0)),
+            KeyCode::Char('w') => Some(Self::Extend(1.0)),
+            KeyCode::Char('s') => Some(Self::Extend(-1.0)),
+            KeyCode::Char('t') => Some(Self::ToggleStepSize),
+            KeyCode::Char('o') => Some(Self::SaveOrigin),
+            KeyCode::Char('q') | KeyCode::Esc => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Moves the pen by hand through the same steppers and devices as plotting.
+pub struct Jog {
+    central: Axis<CentralStepper>,
+    beam: Axis<BeamStepper>,
+    step_size: usize,
+}
+
+impl Jog {
+    pub fn new(central: Axis<CentralStepper>, beam: Axis<BeamStepper>) -> Self {
+        Self {
+            central,
+            beam,
+            step_size: 1,
+        }
+    }
+
+    /// of the central and the beam axis
+    pub fn axes_mut(&mut self) -> (&mut Axis<CentralStepper>, &mut Axis<BeamStepper>) {
+        (&mut self.central, &mut self.beam)
+    }
+
+    /// of the pen in millimeter, derived from the reported step numbers
+    pub fn location(&self) -> Point2<f64> {
+        axis::location(
+            &self.central.stepper,
+            self.central.step,
+            &self.beam.stepper,
+            self.beam.step,
+        )
+    }
+
+    /// in millimeter
+    pub fn step_size(&self) -> f64 {
+        STEP_SIZES[self.step_size]
+    }
+
+    /// Moves the pen for the movement actions and toggles the step size.
+    pub fn apply(&mut self, action: Action) -> eyre::Result<()> {
+        let location = self.location();
+        let step_size = self.step_size();
+        let target = match action {
+            Action::Move(direction) => location + direction * step_size,
+            Action::Rotate(sign) => {
+                let distance = location.coords.magnitude();
+                let orientation = self.central.stepper.orientation(self.central.step);
+                let angle = sign * step_size / distance;
+                vector_2::polar(distance, orientation + angle).into()
+            }
+            Action::Extend(sign) => location + location.coords.normalize() * (sign * step_size),
+            Action::ToggleStepSize => {
+                self.step_size = (self.step_size + 1) % STEP_SIZES.len();
+                return Ok(());
+            }
+            Action::SaveOrigin | Action::Quit => return Ok(()),
+        };
+        self.move_to(target)
+    }
+
+    fn move_to(&mut self, location: Point2<f64>) -> eyre::Result<()> {
+        let target = Point {
+            location,
+            ..Default::default()
+        };
+        let central_target = self.central.stepper.target_step(target)?;
+        let beam_target = self.beam.stepper.target_step(target)?;
+        step_to(&mut self.central, central_target)?;
+        step_to(&mut self.beam, beam_target)
+    }
+
+    fn status(&self) -> String {
+        let location = self.location();
+        format!(
+            "location = ({:.2}, {:.2}), central step = {}, beam step = {}, step size = {} mm",
+            location.x,
+            location.y,
+            self.central.step.0,
+            self.beam.step.0,
+            self.step_size()
+        )
+    }
+}
+
+fn step_to<S: Stepper>(axis: &mut Axis<S>, target: TargetStep) -> eyre::Result<()> {
+    let delta = axis.stepper.delta_steps(axis.step, target)?;
+    axis.step = axis.stepper.position(axis.device.step(delta)?);
+    if !axis.device.reports_steps() {
+        // shows the step a batched device reports instead of the expected one
+        axis.read_back()?;
+    }
+    Ok(())
+}
+
+/// Reads keys from the terminal until quit or `control` aborts
+/// and saves the work origin to `config_path`.
+pub fn run(
+    jog: &mut Jog,
+    mut config: Config,
+    config_path: &Path,
+    control: &Control,
+) -> eyre::Result<()> {
+    let _raw_mode = RawMode::enable()?;
+    let mut stdout = io::stdout();
+    let mut message = HELP.to_owned();
+    loop {
+        queue!(
+            stdout,
+            cursor::MoveToColumn(0),
+            terminal::Clear(ClearType::CurrentLine),
+            Print(jog.status()),
+            Print(format!(" | {message}")),
+        )?;
+        stdout.flush()?;
+        let code = match read_key(control) {
+            Ok(Some(code)) => code,
+            Ok(None) => continue,
+            Err(e) => {
+                queue!(stdout, Print("\r\n"))?;
+                stdout.flush()?;
+                return Err(e);
+            }
+        };
+        let action = match Action::from_key(code) {
+            Some(action) => action,
+            None => continue,
+        };
+        message = match action {
+            Action::Quit => break,
+            Action::SaveOrigin => {
+                let location = jog.location();
+                config.work_origin = Some([location.x, location.y]);
+                match config.save(config_path) {
+                    Ok(()) => format!("saved work origin to {config_path:?}"),
+                    Err(e) => format!("{e:#}"),
+                }
+            }
+            _ => match jog.apply(action) {
+                Ok(()) => String::new(),
+                Err(e) => format!("{e:#}"),
+            },
+        };
+    }
+    queue!(stdout, Print("\r\n"))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// `None` for other events than key presses.
+fn read_key(control: &Control) -> eyre::Result<Option<KeyCode>> {
+    while !event::poll(POLL_INTERVAL).wrap_err("failed to poll terminal events")? {
+        if control.is_aborted() {
+            return Err(Aborted.into());
+        }
+    }
+    match event::read().wrap_err("failed to read terminal event")? {
+        Event::Key(key) => Ok(Some(key.code)),
+        _ => Ok(None),
+    }
+}
+
+/// Restores the terminal when dropped, even on errors.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> eyre::Result<Self> {
+        terminal::enable_raw_mode().wrap_err("failed to enable raw terminal mode")?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(e) = terminal::disable_raw_mode() {
+            log::error!("failed to disable raw terminal mode: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{Device, SimulatedDevice},
+        pen::Pen,
+        stepper::{CurrentStep, DeltaSteps},
+    };
+
+    /// Returns the step before each delta until flushed, like a batched device.
+    struct LaggingDevice(SimulatedDevice);
+
+    impl Device for LaggingDevice {
+        fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+            let before = self.0.step;
+            self.0.step(delta)?;
+            Ok(before)
+        }
+
+        fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+            self.0.set_pen(pen)
+        }
+
+        fn flush(&mut self) -> eyre::Result<CurrentStep> {
+            Ok(self.0.step)
+        }
+
+        fn reports_steps(&self) -> bool {
+            false
+        }
+    }
+
+    fn test_jog() -> Jog {
+        let beam_device = SimulatedDevice {
+            step: CurrentStep(1200),
+            ..Default::default()
+        };
+        let central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        );
+        let beam = Axis::new(BeamStepper::default(), Box::new(beam_device));
+        Jog::new(central.unwrap(), beam.unwrap())
+    }
+
+    /// within the resolution of the default central stepper at 40 mm
+    fn assert_near(actual: Point2<f64>, expected: Point2<f64>) {
+        let error = nalgebra::distance(&actual, &expected);
+        assert!(error < 0.35, "actual = {actual}, expected = {expected}");
+    }
+
+    #[test]
+    fn starts_at_reported_steps() {
+        assert_near(test_jog().location(), Point2::new(0.0, -40.0));
+    }
+
+    #[test]
+    fn move_right() {
+        let mut jog = test_jog();
+        jog.apply(Action::ToggleStepSize).unwrap();
+        jog.apply(Action::Move(vector_2::right())).unwrap();
+        assert_near(jog.location(), Point2::new(10.0, -40.0));
+    }
+
+    #[test]
+    fn extend() {
+        let mut jog = test_jog();
+        jog.apply(Action::Extend(-1.0)).unwrap();
+        assert_near(jog.location(), Point2::new(0.0, -39.0));
+    }
+
+    #[test]
+    fn shows_reported_steps_of_batched_device() {
+        let beam_device = LaggingDevice(SimulatedDevice {
+            step: CurrentStep(1200),
+            ..Default::default()
+        });
+        let central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        );
+        let beam = Axis::new(BeamStepper::default(), Box::new(beam_device));
+        let mut jog = Jog::new(central.unwrap(), beam.unwrap());
+        jog.apply(Action::Extend(-1.0)).unwrap();
+        assert_near(jog.location(), Point2::new(0.0, -39.0));
+    }
+
+    #[test]
+    fn move_out_of_reach() {
+        let mut jog = test_jog();
+        jog.apply(Action::ToggleStepSize).unwrap();
+        for _ in 0..3 {
+            jog.apply(Action::Move(vector_2::back())).unwrap();
+        }
+        let _ = jog.apply(Action::Move(vector_2::back())).unwrap_err();
+        assert_near(jog.location(), Point2::new(0.0, -10.0));
+    }
+
+    #[test]
+    fn toggle_step_size_wraps() {
+        let mut jog = test_jog();
+        for _ in 0..STEP_SIZES.len() {
+            jog.apply(Action::ToggleStepSize).unwrap();
+        }
+        assert_eq!(jog.step_size(), 1.0);
+    }
+}
@@ -1,14 +1,31 @@
-mod angle;
+mod axis;
+mod calibrate;
+mod checkpoint;
 mod cli;
-mod motion;
-mod named;
-mod path;
+mod config;
+mod estimate;
+mod executor;
+mod fill;
+mod geometry;
+mod job;
+mod jog;
+mod optimize;
+mod pattern;
+mod record;
+mod replay;
 mod run;
-mod serial;
-mod stepper;
-mod timer;
-mod vector_2;
+mod server;
+mod shutdown;
+mod telemetry;
+mod text;
+mod tool;
 
-fn main() {
+use pen_plotter_core::{
+    clock, control, controller, device, motion, named, path, pen, polar, serial, stepper, timer,
+    transport, vector_2,
+};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
     run::run()
 }
@@ -0,0 +1,360 @@
+use crate::{
+    job::{Job, Stroke, MIN_SEGMENT_LENGTH},
+    path::Point,
+    pen::DEFAULT_PEN,
+    stepper::{beam::BeamStepper, central::CentralStepper, Stepper},
+    tool::Tools,
+};
+use nalgebra::Point2;
+
+/// of 2-opt over all strokes, each pass is quadratic in the number of strokes
+const MAX_PASSES: usize = 20;
+
+/// of a layer for 2-opt, larger layers keep the nearest neighbour order
+const MAX_TWO_OPT_STROKES: usize = 1000;
+
+/// Estimates travel time between points from the steps the motors need to move.
+///
+/// The motors move at the same time, so the slower one determines the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointCost {
+    pub central: CentralStepper,
+    pub beam: BeamStepper,
+}
+
+/// Target steps of both motors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Joint {
+    central: i16,
+    beam: i16,
+}
+
+/// A stroke with the joints of its ends.
+#[derive(Debug, Clone, PartialEq)]
+struct Item {
+    stroke: Stroke,
+    start: Joint,
+    end: Joint,
+}
+
+impl JointCost {
+    fn joint(&self, location: Point2<f64>) -> eyre::Result<Joint> {
+        let target = Point {
+            location,
+            ..Default::default()
+        };
+        Ok(Joint {
+            central: self.central.target_step(target)?.0,
+            beam: self.beam.target_step(target)?.0,
+        })
+    }
+
+    /// in seconds at the maximum velocity of the motors
+    fn cost(&self, a: Joint, b: Joint) -> f64 {
+        let steps_per_rev = i32::from(self.central.steps_per_rev());
+        let central = (i32::from(b.central) - i32::from(a.central)).rem_euclid(steps_per_rev);
+        let central = central.min(steps_per_rev - central);
+        let beam = (i32::from(b.beam) - i32::from(a.beam)).abs();
+        let central = f64::from(central) * self.central.min_step_interval().as_secs_f64();
+        let beam = f64::from(beam) * self.beam.min_step_interval().as_secs_f64();
+        central.max(beam)
+    }
+
+    /// of the pen-up travel from `start` through all strokes in their order
+    pub fn travel(&self, job: &Job, start: Point2<f64>) -> eyre::Result<f64> {
+        let items = self.items(job.strokes().cloned().collect())?;
+        Ok(self.tour(self.joint(start)?, &items))
+    }
+
+    fn items(&self, strokes: Vec<Stroke>) -> eyre::Result<Vec<Item>> {
+        strokes
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .map(|stroke| {
+                let start = self.joint(stroke[0])?;
+                let end = self.joint(stroke[stroke.len() - 1])?;
+                Ok(Item { stroke, start, end })
+            })
+            .collect()
+    }
+
+    fn tour(&self, start: Joint, items: &[Item]) -> f64 {
+        let mut location = start;
+        let mut total = 0.0;
+        for item in items {
+            total += self.cost(location, item.start);
+            location = item.end;
+        }
+        total
+    }
+}
+
+impl Item {
+    fn reverse(&mut self) {
+        self.stroke.reverse();
+        std::mem::swap(&mut self.start, &mut self.end);
+    }
+}
+
+/// Reorders, reverses and merges the strokes of `job` to shorten the pen-up travel
+/// starting at `start`. Layers keep their order, each starts where the previous one ended
+/// or at the park location of `tools` after a tool change like in [`Job::segments`].
+pub fn optimize(
+    mut job: Job,
+    start: Point2<f64>,
+    tools: &Tools,
+    cost: &JointCost,
+) -> eyre::Result<Job> {
+    let park = cost.joint(tools.park.map_or(start, Point2::from))?;
+    let mut start = cost.joint(start)?;
+    let mut current_pen = DEFAULT_PEN;
+    for layer in &mut job.layers {
+        let strokes = merge(std::mem::take(&mut layer.strokes));
+        let items = cost.items(strokes)?;
+        let drawn = items.iter().any(|i| i.stroke.len() >= 2);
+        if drawn && layer.pen != current_pen {
+            start = park;
+            current_pen = layer.pen;
+        }
+        let mut items = nearest_neighbour(items, start, cost);
+        if items.len() <= MAX_TWO_OPT_STROKES {
+            two_opt(&mut items, start, cost);
+        } else {
+            log::info!(
+                "skipped 2-opt for {} strokes, more than {MAX_TWO_OPT_STROKES}",
+                items.len()
+            );
+        }
+        start = items.last().map_or(start, |i| i.end);
+        layer.strokes = items.into_iter().map(|i| i.stroke).collect();
+    }
+    Ok(job)
+}
+
+fn coincide(a: Point2<f64>, b: Point2<f64>) -> bool {
+    nalgebra::distance(&a, &b) < MIN_SEGMENT_LENGTH
+}
+
+/// Joins strokes whose ends coincide, reversing them where needed.
+fn merge(strokes: Vec<Stroke>) -> Vec<Stroke> {
+    let mut strokes: Vec<Stroke> = strokes.into_iter().filter(|s| !s.is_empty()).collect();
+    let mut i = 0;
+    while i < strokes.len() {
+        let joinable = (0..strokes.len()).filter(|&j| j != i).find_map(|j| {
+            let (a, b) = (&strokes[i], &strokes[j]);
+            let (a_first, a_last) = (a[0], a[a.len() - 1]);
+            let (b_first, b_last) = (b[0], b[b.len() - 1]);
+            if coincide(a_last, b_first) {
+                Some((j, false, false))
+            } else if coincide(a_last, b_last) {
+                Some((j, false, true))
+            } else if coincide(a_first, b_last) {
+                Some((j, true, false))
+            } else if coincide(a_first, b_first) {
+                Some((j, true, true))
+            } else {
+                None
+            }
+        });
+        let (j, prepend, reverse) = match joinable {
+            Some(joinable) => joinable,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let mut other = strokes.remove(j);
+        if j < i {
+            i -= 1;
+        }
+        if reverse {
+            other.reverse();
+        }
+        let stroke = &mut strokes[i];
+        if prepend {
+            other.extend_from_slice(&stroke[1..]);
+            *stroke = other;
+        } else {
+            stroke.extend_from_slice(&other[1..]);
+        }
+    }
+    strokes
+}
+
+/// Visits the nearest stroke end next, reversing the stroke if its last point is nearer.
+fn nearest_neighbour(mut items: Vec<Item>, start: Joint, cost: &JointCost) -> Vec<Item> {
+    let mut ordered = Vec::with_capacity(items.len());
+    let mut location = start;
+    while !items.is_empty() {
+        let (index, reverse, _) = items
+            .iter()
+            .enumerate()
+            .flat_map(|(i, item)| {
+                [
+                    (i, false, cost.cost(location, item.start)),
+                    (i, true, cost.cost(location, item.end)),
+                ]
+            })
+            .fold((0, false, f64::INFINITY), |best, next| {
+                if next.2 < best.2 {
+                    next
+                } else {
+                    best
+                }
+            });
+        let mut item = items.swap_remove(index);
+        if reverse {
+            item.reverse();
+        }
+        location = item.end;
+        ordered.push(item);
+    }
+    ordered
+}
+
+/// Reverses runs of strokes, including single ones, while that shortens the travel.
+fn two_opt(items: &mut [Item], start: Joint, cost: &JointCost) {
+    for _ in 0..MAX_PASSES {
+        let mut improved = false;
+        for i in 0..items.len() {
+            for j in i..items.len() {
+                let before = if i == 0 { start } else { items[i - 1].end };
+                let after = items.get(j + 1).map(|item| item.start);
+                let next_cost = |joint| after.map_or(0.0, |after| cost.cost(joint, after));
+                let current = cost.cost(before, items[i].start) + next_cost(items[j].end);
+                let reversed = cost.cost(before, items[j].end) + next_cost(items[i].start);
+                if reversed < current - f64::EPSILON {
+                    items[i..=j].reverse();
+                    items[i..=j].iter_mut().for_each(Item::reverse);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::Layer;
+
+    fn cost() -> JointCost {
+        JointCost {
+            central: CentralStepper::default(),
+            beam: BeamStepper::default(),
+        }
+    }
+
+    fn line(start: (f64, f64), end: (f64, f64)) -> Stroke {
+        vec![Point2::new(start.0, start.1), Point2::new(end.0, end.1)]
+    }
+
+    #[test]
+    fn merge_coinciding_ends() {
+        let strokes = vec![
+            line((0.0, -20.0), (0.0, -30.0)),
+            line((5.0, -30.0), (0.0, -30.0)),
+            line((5.0, -30.0), (5.0, -40.0)),
+        ];
+        let merged = merge(strokes);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].len(), 4);
+        assert_eq!(merged[0][0], Point2::new(0.0, -20.0));
+        assert_eq!(merged[0][3], Point2::new(5.0, -40.0));
+    }
+
+    #[test]
+    fn reverse_stroke_starting_far_away() {
+        let job = Job::new(vec![line((0.0, -40.0), (0.0, -20.0))]);
+        let start = Point2::new(0.0, -20.0);
+        let optimized = optimize(job, start, &Tools::new(1.0), &cost()).unwrap();
+        assert_eq!(optimized.layers[0].strokes[0][0], start);
+    }
+
+    #[test]
+    fn reorder_shortens_travel() {
+        let job = Job::new(vec![
+            line((0.0, -60.0), (1.0, -60.0)),
+            line((0.0, -20.0), (1.0, -20.0)),
+            line((0.0, -70.0), (1.0, -70.0)),
+            line((0.0, -30.0), (1.0, -30.0)),
+        ]);
+        let start = Point2::new(0.0, -10.0);
+        let cost = cost();
+        let before = cost.travel(&job, start).unwrap();
+        let optimized = optimize(job.clone(), start, &Tools::new(1.0), &cost).unwrap();
+        let after = cost.travel(&optimized, start).unwrap();
+        assert!(after < before, "before = {before}, after = {after}");
+        let first: Vec<_> = optimized.strokes().map(|s| s[0].y.round()).collect();
+        assert_eq!(first, [-20.0, -30.0, -60.0, -70.0]);
+    }
+
+    #[test]
+    fn central_cost_wraps_around() {
+        let cost = cost();
+        let steps_per_rev = cost.central.steps_per_rev();
+        let a = Joint {
+            central: steps_per_rev / 2 - 1,
+            beam: 0,
+        };
+        let b = Joint {
+            central: -steps_per_rev / 2 + 1,
+            beam: 0,
+        };
+        let interval = cost.central.min_step_interval().as_secs_f64();
+        assert!((cost.cost(a, b) - 2.0 * interval).abs() < 1e-9);
+    }
+
+    #[test]
+    fn layers_keep_their_order() {
+        let layer = |pen, y| Layer {
+            pen,
+            strokes: vec![line((0.0, y), (1.0, y))],
+        };
+        let job = Job {
+            layers: vec![layer(1, -70.0), layer(2, -20.0)],
+        };
+        let start = Point2::new(0.0, -10.0);
+        let optimized = optimize(job, start, &Tools::new(1.0), &cost()).unwrap();
+        let pens: Vec<_> = optimized.layers.iter().map(|l| l.pen).collect();
+        assert_eq!(pens, [1, 2]);
+        // the second layer starts near the start, where pens are changed
+        assert_eq!(optimized.layers[1].strokes[0][0], Point2::new(0.0, -20.0));
+    }
+
+    #[test]
+    fn layers_start_at_park() {
+        let layer = |pen, stroke| Layer {
+            pen,
+            strokes: vec![stroke],
+        };
+        let job = Job {
+            layers: vec![
+                layer(1, line((0.0, -70.0), (1.0, -70.0))),
+                layer(2, line((0.0, -20.0), (10.0, -20.0))),
+            ],
+        };
+        let tools = Tools {
+            park: Some([20.0, -20.0]),
+            ..Tools::new(1.0)
+        };
+        let optimized = optimize(job, Point2::new(0.0, -10.0), &tools, &cost()).unwrap();
+        assert_eq!(optimized.layers[1].strokes[0][0], Point2::new(10.0, -20.0));
+    }
+
+    #[test]
+    fn keeps_all_points() {
+        let job = Job::new(vec![
+            line((10.0, -20.0), (10.0, -30.0)),
+            line((-10.0, -20.0), (-10.0, -30.0)),
+            vec![Point2::new(3.0, -40.0)],
+        ]);
+        let start = Point2::new(0.0, -10.0);
+        let optimized = optimize(job, start, &Tools::new(1.0), &cost()).unwrap();
+        let points: usize = optimized.strokes().map(Vec::len).sum();
+        assert_eq!(points, 5);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{job::Job, vector_2};
+use nalgebra::{Point2, Rotation2, Vector2};
+
+const TEETH: usize = 4;
+
+/// of the tangential comb in millimeter
+pub const TANGENTIAL_RADIUS: f64 = 40.0;
+
+/// Combs of strokes that reverse each axis a few times.
+///
+/// The returning strokes of a tooth end short of the outgoing ones by the backlash
+/// of the reversing axis: radial teeth reverse the beam, tangential teeth the centre.
+/// Measure the beam first, the tangential strokes also reverse the beam slightly.
+pub fn backlash() -> Job {
+    let mut points = radial_comb(30.0, 50.0);
+    points.extend(tangential_comb(TANGENTIAL_RADIUS, 20.0));
+    Job::new(vec![points])
+}
+
+/// teeth from `inner` to `outer` radius with 4 mm between them, right of forward
+fn radial_comb(inner: f64, outer: f64) -> Vec<Point2<f64>> {
+    let mut points = Vec::new();
+    for tooth in 0..TEETH {
+        let sideways = vector_2::right() * (8.0 * tooth as f64);
+        let out = inner * vector_2::forward() + sideways;
+        let back = out + vector_2::right() * 4.0;
+        points.push(out.into());
+        points.push(radial(out, outer).into());
+        points.push(radial(back, outer).into());
+        points.push(back.into());
+    }
+    points
+}
+
+/// teeth of `length` millimeter across forward starting at `radius`, 4 mm apart
+fn tangential_comb(radius: f64, length: f64) -> Vec<Point2<f64>> {
+    let mut points = Vec::new();
+    let start = radius * vector_2::forward() + vector_2::left() * (length / 2.0);
+    for tooth in 0..TEETH {
+        let out = start + vector_2::forward() * (8.0 * tooth as f64);
+        let back = out + vector_2::forward() * 4.0;
+        points.push(out.into());
+        points.push((out + vector_2::right() * length).into());
+        points.push((back + vector_2::right() * length).into());
+        points.push(back.into());
+    }
+    points
+}
+
+/// keeps the direction of `v` from the centre but changes its length
+fn radial(v: Vector2<f64>, length: f64) -> Vector2<f64> {
+    let angle = Rotation2::rotation_between(&vector_2::forward(), &v);
+    angle * vector_2::forward() * length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backlash_is_within_default_beam_range() {
+        for point in &backlash().layers[0].strokes[0] {
+            let distance = point.coords.magnitude();
+            assert!((10.0..=80.0).contains(&distance), "{point}");
+        }
+    }
+
+    #[test]
+    fn radial_keeps_direction() {
+        let actual = radial(Vector2::new(3.0, 4.0), 10.0);
+        assert!((actual - Vector2::new(6.0, 8.0)).magnitude() < 1e-9);
+    }
+}
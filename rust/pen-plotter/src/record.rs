@@ -0,0 +1,254 @@
+use crate::{device::Scoped, transport::Transport};
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    /// of the interval the current thread is stepping through
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// One read or write of a transport, a line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// since the recording started in seconds
+    pub time: f64,
+
+    /// central, beam or controller
+    pub device: String,
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+
+    /// of the interval that caused the read or write
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<Context>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// An interval of the executor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    pub stepper: String,
+
+    /// target in millimeter
+    pub location: [f64; 2],
+    pub current_step: i16,
+    pub delta_steps: i16,
+    pub target_step: i16,
+}
+
+/// Attaches `context` to the records of the transports `f` reads and writes on this thread.
+pub fn with_context<T>(context: Context, f: impl FnOnce() -> T) -> T {
+    CONTEXT.with(|current| *current.borrow_mut() = Some(context));
+    let result = f();
+    CONTEXT.with(|current| *current.borrow_mut() = None);
+    result
+}
+
+/// Carries the context of the current thread to the worker of a
+/// [`crate::device::BatchedDevice`] stepping on its behalf.
+pub fn scope() -> Scoped {
+    let context = CONTEXT.with(|context| context.borrow().clone());
+    Box::new(move |f| match context {
+        Some(context) => with_context(context, f),
+        None => f(),
+    })
+}
+
+/// Appends the records of all transports to one file as JSON lines.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: Instant,
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> eyre::Result<Self> {
+        let file =
+            File::create(path).wrap_err_with(|| format!("failed to create recording {path:?}"))?;
+        Ok(Self {
+            start: Instant::now(),
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Wraps `transport` to record its reads and writes as `device`.
+    pub fn record(
+        &self,
+        transport: Box<dyn Transport>,
+        device: &'static str,
+    ) -> Box<dyn Transport> {
+        Box::new(Recording {
+            transport,
+            device,
+            recorder: self.clone(),
+        })
+    }
+
+    /// Writes each record at once so a crash loses at most the last one.
+    fn append(&self, device: &str, direction: Direction, bytes: &[u8]) {
+        let record = Record {
+            time: self.start.elapsed().as_secs_f64(),
+            device: device.to_string(),
+            direction,
+            bytes: bytes.to_vec(),
+            context: CONTEXT.with(|context| context.borrow().clone()),
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+                file.write_all(format!("{line}\n").as_bytes())
+            });
+        if let Err(e) = result {
+            log::warn!("failed to record {direction:?} bytes of {device}: {e}");
+        }
+    }
+}
+
+/// Reads the records of a file written by a [`Recorder`].
+pub fn load(path: &Path) -> eyre::Result<Vec<Record>> {
+    let text =
+        fs::read_to_string(path).wrap_err_with(|| format!("failed to read recording {path:?}"))?;
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .wrap_err_with(|| format!("failed to parse line {} of {path:?}", index + 1))
+        })
+        .collect()
+}
+
+struct Recording {
+    transport: Box<dyn Transport>,
+    device: &'static str,
+    recorder: Recorder,
+}
+
+impl Read for Recording {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.transport.read(buf)?;
+        if len > 0 {
+            self.recorder
+                .append(self.device, Direction::Received, &buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.transport.write(buf)?;
+        self.recorder
+            .append(self.device, Direction::Sent, &buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.transport.flush()
+    }
+}
+
+impl Transport for Recording {
+    fn set_timeout(&mut self, timeout: Duration) -> eyre::Result<()> {
+        self.transport.set_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{BatchedDevice, Device},
+        serial,
+        stepper::DeltaSteps,
+        transport,
+    };
+    use std::{env, process, thread};
+
+    #[test]
+    fn records_both_directions_with_context() {
+        let path = env::temp_dir().join(format!("pen-plotter-record-{}.jsonl", process::id()));
+        let recorder = Recorder::create(&path).unwrap();
+        let (host, mut device) = transport::pair();
+        let mut host = recorder.record(Box::new(host), "beam");
+        let firmware = thread::spawn(move || {
+            let mut bytes = [0; 2];
+            device.read_exact(&mut bytes).unwrap();
+            device.write_all(&bytes).unwrap();
+            device.read_exact(&mut bytes).unwrap();
+        });
+        let context = Context {
+            stepper: "beam stepper".into(),
+            location: [0.0, -10.5],
+            current_step: 0,
+            delta_steps: 20,
+            target_step: 20,
+        };
+        with_context(context.clone(), || {
+            serial::write_steps(&mut host, DeltaSteps(20)).unwrap();
+            serial::read_current_step(&mut host).unwrap()
+        });
+        serial::write_steps(&mut host, DeltaSteps(0)).unwrap();
+        firmware.join().unwrap();
+        let records = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| (r.device.as_str(), r.direction, r.bytes.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("beam", Direction::Sent, vec![0, 20]),
+                ("beam", Direction::Received, vec![0, 20]),
+                ("beam", Direction::Sent, vec![0, 0]),
+            ]
+        );
+        assert_eq!(records[0].context, Some(context));
+        assert_eq!(records[2].context, None);
+        assert!(records[0].time <= records[1].time);
+    }
+
+    #[test]
+    fn batches_carry_context_to_the_worker() {
+        let path =
+            env::temp_dir().join(format!("pen-plotter-record-batch-{}.jsonl", process::id()));
+        let recorder = Recorder::create(&path).unwrap();
+        let (host, mut device) = transport::pair();
+        let host = recorder.record(Box::new(host), "beam");
+        let mut batched = BatchedDevice::new(host).with_scope(scope);
+        let firmware = thread::spawn(move || {
+            let mut frame = [0; 8];
+            device.read_exact(&mut frame).unwrap();
+            device.write_all(&frame[6..]).unwrap();
+        });
+        let context = Context {
+            stepper: "beam stepper".into(),
+            location: [0.0, -10.5],
+            current_step: 0,
+            delta_steps: 20,
+            target_step: 20,
+        };
+        with_context(context.clone(), || batched.step(DeltaSteps(20)).unwrap());
+        firmware.join().unwrap();
+        drop(batched);
+        let records = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|r| r.context.as_ref() == Some(&context)));
+    }
+}
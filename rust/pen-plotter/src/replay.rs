@@ -0,0 +1,333 @@
+use crate::{
+    axis,
+    device::{Device, SimulatedDevice},
+    pen::Pen,
+    record::{Context, Direction, Record},
+    serial::{self, Frame},
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep, Stepper},
+};
+use nalgebra::Point2;
+use std::collections::{HashMap, VecDeque};
+
+/// Where the devices reported the axes during a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Row {
+    /// since the recording started in seconds
+    pub time: f64,
+    pub central: CurrentStep,
+    pub beam: CurrentStep,
+    pub pen: Pen,
+
+    /// of the pen in millimeter
+    pub location: Point2<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub frames: usize,
+    pub responses: usize,
+
+    /// responses other than the simulated firmware predicted
+    pub mismatches: usize,
+}
+
+/// Feeds the sent bytes of each device into a simulated firmware,
+/// compares its responses with the received ones and calls `on_row` with each of them.
+/// The simulation starts at the first response and follows the received steps after mismatches.
+/// The central device wraps its steps at the period of `central` until it is sent another one.
+/// Segments are not simulated, their responses are taken as they are.
+pub fn replay(
+    records: &[Record],
+    central: &CentralStepper,
+    beam: &BeamStepper,
+    mut on_row: impl FnMut(Row),
+) -> eyre::Result<Summary> {
+    let mut summary = Summary::default();
+    let mut firmwares: HashMap<&str, Firmware> = HashMap::new();
+    let mut steps = [CurrentStep(0); 2];
+    let mut pen = Pen::Up;
+    for record in records {
+        let device = record.device.as_str();
+        let firmware = firmwares.entry(device).or_insert_with(|| match device {
+            "controller" => Firmware::new(true, None),
+            "central" => Firmware::new(false, central.period()),
+            _ => Firmware::new(false, None),
+        });
+        match record.direction {
+            Direction::Sent => {
+                firmware.sent.extend(&record.bytes);
+                while let Some((frame, len)) =
+                    serial::parse_frame(&firmware.sent, firmware.multi_axis)?
+                {
+                    firmware.sent.drain(..len);
+                    if let (Frame::Pen(next), "beam" | "controller") = (&frame, device) {
+                        pen = *next;
+                    }
+                    firmware.push(&frame, record.context.clone());
+                    summary.frames += 1;
+                }
+            }
+            Direction::Received => {
+                firmware.received.extend(&record.bytes);
+                while let Some(reported) = firmware.pop(device, record.time, &mut summary) {
+                    match (device, &reported[..]) {
+                        ("central", [step, ..]) => steps[0] = CurrentStep(*step),
+                        ("beam", [step, ..]) => steps[1] = CurrentStep(*step),
+                        (_, [central, beam, ..]) => {
+                            steps = [CurrentStep(*central), CurrentStep(*beam)]
+                        }
+                        _ => {}
+                    }
+                    on_row(Row {
+                        time: record.time,
+                        central: steps[0],
+                        beam: steps[1],
+                        pen,
+                        location: axis::location(central, steps[0], beam, steps[1]),
+                    });
+                }
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Simulates the firmware of one device.
+#[derive(Debug)]
+struct Firmware {
+    multi_axis: bool,
+
+    /// of the one or both motors
+    devices: Vec<SimulatedDevice>,
+
+    /// whether the steps of the devices are known, not until the first response
+    synced: bool,
+    microsteps: Option<i16>,
+
+    /// bytes not parsed yet
+    sent: Vec<u8>,
+    received: Vec<u8>,
+
+    /// responses the firmware owes
+    pending: VecDeque<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    /// steps, none if they can't be predicted
+    predicted: Option<Vec<i16>>,
+    len: usize,
+    context: Option<Context>,
+}
+
+impl Firmware {
+    fn new(multi_axis: bool, period: Option<i16>) -> Self {
+        let device = SimulatedDevice {
+            period,
+            ..SimulatedDevice::default()
+        };
+        let axes = if multi_axis { 2 } else { 1 };
+        Self {
+            multi_axis,
+            devices: vec![device; axes],
+            synced: false,
+            microsteps: None,
+            sent: Vec::new(),
+            received: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, frame: &Frame, context: Option<Context>) {
+        let predicted = self.predict(frame);
+        self.pending.push_back(Pending {
+            predicted,
+            len: frame.response_len(self.multi_axis),
+            context,
+        });
+    }
+
+    fn predict(&mut self, frame: &Frame) -> Option<Vec<i16>> {
+        let previous_microsteps = match frame {
+            Frame::Microsteps(factor) => self.microsteps.replace(*factor),
+            _ => None,
+        };
+        match frame {
+            Frame::Steps(deltas) => {
+                for (device, delta) in self.devices.iter_mut().zip(deltas) {
+                    device.step(*delta).ok()?;
+                }
+            }
+            Frame::Batch(deltas) => {
+                for delta in deltas {
+                    self.devices[0].step(*delta).ok()?;
+                }
+            }
+            Frame::Microsteps(factor) => {
+                let previous = previous_microsteps?;
+                for device in &mut self.devices {
+                    device.set_microsteps(previous, *factor);
+                }
+            }
+            Frame::Period(period) => self.devices[0].set_period(*period),
+            Frame::Pen(pen) => {
+                for device in &mut self.devices {
+                    device.set_pen(*pen).ok()?;
+                }
+            }
+            Frame::Segment { .. } | Frame::SegmentStatus | Frame::ClearSegments => return None,
+        }
+        let steps = self.devices.iter().map(|device| device.step.0).collect();
+        self.synced.then_some(steps)
+    }
+
+    /// Takes the next complete response and returns its steps.
+    fn pop(&mut self, device: &str, time: f64, summary: &mut Summary) -> Option<Vec<i16>> {
+        let len = match self.pending.front() {
+            Some(pending) if self.received.len() >= pending.len => pending.len,
+            Some(_) => return None,
+            None if self.received.is_empty() => return None,
+            None => {
+                log::warn!(
+                    "{device}: unexpected bytes {:?} at {time:.6} s",
+                    self.received
+                );
+                summary.mismatches += 1;
+                self.received.clear();
+                return None;
+            }
+        };
+        let pending = self.pending.pop_front()?;
+        let words: Vec<i16> = self
+            .received
+            .drain(..len)
+            .collect::<Vec<_>>()
+            .chunks_exact(2)
+            .map(|word| i16::from_be_bytes([word[0], word[1]]))
+            .collect();
+        let axes = if self.multi_axis { 2 } else { 1 };
+        let reported = words[..axes].to_vec();
+        summary.responses += 1;
+        if let Some(predicted) = pending.predicted.filter(|p| *p != reported) {
+            summary.mismatches += 1;
+            log::warn!(
+                "{device}: expected {predicted:?} but received {reported:?} at {time:.6} s after {:?}",
+                pending.context
+            );
+        }
+        for (device, step) in self.devices.iter_mut().zip(&reported) {
+            device.step = CurrentStep(*step);
+        }
+        self.synced = true;
+        Some(reported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(device: &str, direction: Direction, bytes: &[u8]) -> Record {
+        Record {
+            time: 0.0,
+            device: device.into(),
+            direction,
+            bytes: bytes.to_vec(),
+            context: None,
+        }
+    }
+
+    #[test]
+    fn replay_finds_mismatches() {
+        use Direction::{Received, Sent};
+        let records = [
+            record("central", Sent, &[0, 0]),
+            record("central", Received, &[0, 5]),
+            record("beam", Sent, &[0x80, 0, 0, 2, 0, 1]),
+            record("beam", Received, &[0, 0]),
+            record("central", Sent, &[0, 3]),
+            // split response
+            record("central", Received, &[0]),
+            record("central", Received, &[8]),
+            record("central", Sent, &[0, 1]),
+            record("central", Received, &[0, 10]),
+        ];
+        let mut rows = Vec::new();
+        let (central, beam) = (CentralStepper::default(), BeamStepper::default());
+        let summary = replay(&records, &central, &beam, |row| rows.push(row)).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                frames: 4,
+                responses: 4,
+                mismatches: 1
+            }
+        );
+        let steps: Vec<_> = rows.iter().map(|row| (row.central.0, row.pen)).collect();
+        assert_eq!(
+            steps,
+            [
+                (5, Pen::Up),
+                (5, Pen::Down),
+                (8, Pen::Down),
+                (10, Pen::Down)
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_controller_with_microsteps() {
+        use Direction::{Received, Sent};
+        let records = [
+            record("controller", Sent, &[0x80, 0, 0, 1, 0, 2]),
+            record("controller", Received, &[0, 4, 0, 6]),
+            record("controller", Sent, &[0x80, 0, 0, 1, 0, 4]),
+            record("controller", Received, &[0, 8, 0, 12]),
+            record("controller", Sent, &[0, 1, 0xff, 0xff]),
+            record("controller", Received, &[0, 9, 0, 11]),
+        ];
+        let mut rows = Vec::new();
+        let (central, beam) = (CentralStepper::default(), BeamStepper::default());
+        let summary = replay(&records, &central, &beam, |row| rows.push(row)).unwrap();
+        assert_eq!(summary.mismatches, 0);
+        let last = rows.last().unwrap();
+        assert_eq!((last.central, last.beam), (CurrentStep(9), CurrentStep(11)));
+    }
+
+    #[test]
+    fn replay_wraps_like_the_firmware() {
+        use Direction::{Received, Sent};
+        let records = [
+            record("beam", Sent, &[0, 0]),
+            record("beam", Received, &[0x7f, 0xff]),
+            record("beam", Sent, &[0x80, 0, 0, 3, 0, 2, 0, 1, 0, 1]),
+            record("beam", Received, &[0x80, 0x01]),
+            record("beam", Sent, &[0, 1]),
+            record("beam", Received, &[0x80, 0x02]),
+        ];
+        let (central, beam) = (CentralStepper::default(), BeamStepper::default());
+        let summary = replay(&records, &central, &beam, |_| {}).unwrap();
+        assert_eq!(summary.mismatches, 0);
+    }
+
+    #[test]
+    fn replay_wraps_central_at_a_revolution() {
+        use Direction::{Received, Sent};
+        let records = [
+            record("central", Sent, &[0, 0]),
+            record("central", Received, &[0x01, 0x8f]),
+            record("central", Sent, &[0, 2]),
+            record("central", Received, &[0, 1]),
+            record("central", Sent, &[0xff, 0xfd]),
+            record("central", Received, &[0xff, 0xfe]),
+            record("central", Sent, &[0x80, 0, 0, 7, 0x03, 0x20]),
+            record("central", Received, &[0xff, 0xfe]),
+            record("central", Sent, &[0x03, 0x22]),
+            record("central", Received, &[0, 0]),
+        ];
+        let (central, beam) = (CentralStepper::default(), BeamStepper::default());
+        assert_eq!(central.period(), Some(400));
+        let summary = replay(&records, &central, &beam, |_| {}).unwrap();
+        assert_eq!(summary.mismatches, 0);
+    }
+}
@@ -1,162 +1,558 @@
 use crate::{
-    cli::Cli,
+    axis::{self, Axis},
+    calibrate,
+    checkpoint::{Checkpoint, Plan},
+    cli::{Cli, Command},
+    clock::{Clock, RealClock, VirtualClock},
+    config::{Config, LinkConfig},
+    control::{Aborted, Control},
+    controller::{self, ControllerAxis, Streamer},
+    device::{BatchedDevice, Device, SimulatedDevice},
+    estimate::Estimate,
+    executor::{self, Progress},
+    fill::Fill,
+    geometry::Tolerance,
+    job::{Format, Job, Options},
+    jog::{self, Jog},
     motion::Motion,
     named::Named,
-    path::{Path, Point},
-    serial,
-    stepper::{
-        beam::BeamStepper, central::CentralStepper, CurrentStep, DeltaSteps, Stepper, TargetStep,
-    },
-    timer::IntervalTimer,
+    optimize::{self, JointCost},
+    pattern,
+    pen::Pen,
+    polar,
+    record::{self, Recorder},
+    replay, serial,
+    server::{self, Daemon},
+    shutdown::{self, Shutdown},
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep, Stepper},
+    telemetry::Telemetry,
+    text::{self, Baseline},
+    tool::Tools,
+    transport::{Endpoint, Link, Transport},
 };
 use clap::Parser;
+use eyre::{eyre, WrapErr};
 use log::LevelFilter;
-use serialport::SerialPort;
+use nalgebra::{Point2, Vector2};
 use std::{
-    fmt::{self, Debug},
-    panic::Location,
-    process,
-    thread::{self, JoinHandle},
-    time::Instant,
+    f64::consts::TAU,
+    fs,
+    io::{self, BufRead},
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
 };
 
-pub fn run() {
+pub fn run() -> ExitCode {
     env_logger::builder()
         .filter_level(LevelFilter::Info)
         .parse_default_env()
         .init();
-    if let Err(e) = try_run() {
-        let report: eyre::Report = e;
-        log::error!("{report:?}")
+    let shutdown = Arc::new(Shutdown::default());
+    let result = try_run(&shutdown);
+    match &result {
+        Err(e) if e.is::<Aborted>() => log::warn!("{e}"),
+        Err(e) => log::error!("{e:?}"),
+        Ok(()) => {}
     }
+    shutdown.exit_code(&result)
 }
 
-fn try_run() -> eyre::Result<()> {
+fn try_run(shutdown: &Arc<Shutdown>) -> eyre::Result<()> {
     let cli = Cli::parse();
-    let motion = Motion {
-        path: path_from_cli(&cli),
-        start: Instant::now(),
+    let config = Config::load(&cli.config)?;
+    let central = CentralStepper::with_microsteps(cli.microsteps)?
+        .with_calibration(config.central)
+        .with_backlash(cli.central_backlash);
+    let beam = BeamStepper::with_microsteps(cli.microsteps)?
+        .with_calibration(config.beam)?
+        .with_backlash(cli.beam_backlash);
+    let origin = Vector2::from(config.work_origin.unwrap_or_default());
+    let options = Options {
+        tolerance: cli.tolerance.map_or_else(
+            || Tolerance::step_resolution(&central, &beam, origin),
+            Tolerance::Fixed,
+        ),
+        fill: cli.fill.map(|pattern| Fill {
+            pattern,
+            spacing: cli.fill_spacing,
+            angle: cli.fill_angle.to_radians(),
+            center: Point2::from(-origin),
+        }),
     };
-    let join_handles = if cli.io {
-        [
-            spawn_step_through_motion(
-                motion,
-                CentralStepper::default(),
-                serial_steps(serial::open(&cli.central)?),
-            ),
-            spawn_step_through_motion(
-                motion,
-                BeamStepper::default(),
-                serial_steps(serial::open(&cli.beam)?),
-            ),
-        ]
+    let tools = Tools {
+        park: config.park,
+        pens: config.pens.clone(),
+        ..Tools::new(cli.velocity)
+    };
+    let mut optimize = false;
+    let job = match &cli.command {
+        None => job_from_cli(&cli)?.translate(origin),
+        Some(Command::BacklashPattern) => {
+            let radius = pattern::TANGENTIAL_RADIUS;
+            log::info!(
+                "beam: {:.2} steps per mm, central: {:.2} steps per mm at radius {radius} mm",
+                beam.steps_per_mm(),
+                f64::from(central.steps_per_rev()) / (TAU * radius),
+            );
+            // at fixed radii around the centre, the work origin would move it off the beam range
+            pattern::backlash()
+        }
+        Some(Command::Calibrate) => return calibrate(&cli, shutdown, config, central, beam),
+        Some(Command::Jog) => {
+            let (central, beam, _) = open_axes(&cli, &config, central, beam)?;
+            let mut jog = Jog::new(central, beam);
+            let control = Arc::new(Control::default());
+            let signals = shutdown.listen(control.clone())?;
+            let result = jog::run(&mut jog, config, &cli.config, &control);
+            signals.close();
+            let (central, beam) = jog.axes_mut();
+            shutdown::park(central, beam, &cli.position)?;
+            return result;
+        }
+        Some(Command::Plot {
+            file,
+            optimize: optimize_file,
+        }) => {
+            optimize = *optimize_file;
+            parse_file(file, &options)?.translate(origin)
+        }
+        Some(Command::Estimate { file, optimize }) => {
+            let job = parse_file(file, &options)?.translate(origin);
+            let location = axis::location(&central, CurrentStep(0), &beam, CurrentStep(0));
+            let job = if *optimize {
+                optimized(job, location, &tools, central, beam)?
+            } else {
+                job
+            };
+            let estimate = Estimate::new(&job.segments(location, &tools), &central, &beam)?;
+            print!("{estimate}");
+            return Ok(());
+        }
+        Some(Command::Replay { file }) => {
+            let records = record::load(file)?;
+            println!("time,central,beam,pen,x,y");
+            let summary = replay::replay(&records, &central, &beam, |row| {
+                let pen = match row.pen {
+                    Pen::Up => "up",
+                    Pen::Down => "down",
+                };
+                println!(
+                    "{:.6},{},{},{pen},{:.3},{:.3}",
+                    row.time, row.central.0, row.beam.0, row.location.x, row.location.y
+                );
+            })?;
+            log::info!(
+                "replayed {} frames and {} responses, {} mismatches",
+                summary.frames,
+                summary.responses,
+                summary.mismatches
+            );
+            return Ok(());
+        }
+        Some(Command::Text {
+            text,
+            size,
+            at,
+            angle,
+            radius,
+            optimize: optimize_text,
+        }) => {
+            optimize = *optimize_text;
+            let angle = angle.to_radians();
+            let baseline = match radius {
+                Some(radius) => Baseline::Arc {
+                    center: Point2::from(-origin),
+                    radius: *radius,
+                    angle,
+                },
+                None => Baseline::Line { start: *at, angle },
+            };
+            let strokes = text::render(text, *size, &baseline)?;
+            Job::new(strokes)
+                .simplify(&options.tolerance)
+                .translate(origin)
+        }
+        Some(Command::Serve { listen, optimize }) => {
+            let (mut central, mut beam, _) = open_axes(&cli, &config, central, beam)?;
+            let telemetry = cli
+                .telemetry
+                .as_deref()
+                .map(Telemetry::create)
+                .transpose()?;
+            let daemon = Daemon::new(&central, &beam, tools, origin, *optimize, options)
+                .with_telemetry(telemetry)
+                .with_checks(max_lateness(&cli)?, cli.on_mismatch)
+                .with_clock(clock(&cli));
+            let daemon = Arc::new(daemon);
+            let signals = shutdown.listen(daemon.clone())?;
+            let result = server::serve(listen, &daemon, &mut central, &mut beam);
+            signals.close();
+            shutdown::park(&mut central, &mut beam, &cli.position)?;
+            return result;
+        }
+        Some(Command::Resume) => {
+            let checkpoint = Checkpoint::load(&cli.checkpoint)?;
+            let (central, beam, streamer) = open_axes(&cli, &config, central, beam)?;
+            let reported = (central.step.0, beam.step.0);
+            if reported != (checkpoint.central_step, checkpoint.beam_step) {
+                log::warn!(
+                    "the devices report central step {}, beam step {} \
+                    but the checkpoint was saved at {}, {}",
+                    reported.0,
+                    reported.1,
+                    checkpoint.central_step,
+                    checkpoint.beam_step
+                );
+            }
+            let location = axis::location(&central.stepper, central.step, &beam.stepper, beam.step);
+            log::info!(
+                "resuming at segment {} of {}",
+                checkpoint.segment,
+                checkpoint.segments().len()
+            );
+            let plan = checkpoint.resume(location);
+            return plot(&cli, shutdown, (central, beam, streamer), plan);
+        }
+    };
+    let (central, beam, streamer) = open_axes(&cli, &config, central, beam)?;
+    let location = axis::location(&central.stepper, central.step, &beam.stepper, beam.step);
+    let job = if optimize {
+        optimized(job, location, &tools, central.stepper, beam.stepper)?
     } else {
-        [
-            spawn_step_through_motion(motion, CentralStepper::default(), log_interval),
-            spawn_step_through_motion(motion, BeamStepper::default(), log_interval),
-        ]
+        job
     };
-    for h in join_handles {
-        exit_on_error(h.join());
-    }
-    Ok(())
+    let plan = Checkpoint::new(&job, location, tools).plan();
+    plot(&cli, shutdown, (central, beam, streamer), plan)
+}
+
+fn parse_file(file: &std::path::Path, options: &Options) -> eyre::Result<Job> {
+    let text = fs::read_to_string(file).wrap_err_with(|| format!("failed to read {file:?}"))?;
+    Job::parse(Format::from_extension(file)?, &text, options)
+        .wrap_err_with(|| format!("failed to parse {file:?}"))
 }
 
-fn path_from_cli(cli: &Cli) -> Path {
-    Path {
-        start: cli.start,
-        end: cli.end,
-        velocity: cli.velocity,
+/// Reorders the strokes of `job` starting at `location` and logs the saved travel time.
+fn optimized(
+    job: Job,
+    location: Point2<f64>,
+    tools: &Tools,
+    central: CentralStepper,
+    beam: BeamStepper,
+) -> eyre::Result<Job> {
+    let cost = JointCost { central, beam };
+    let before = cost.travel(&job, location)?;
+    let job = optimize::optimize(job, location, tools, &cost)?;
+    let after = cost.travel(&job, location)?;
+    log::info!("optimized pen-up travel from {before:.1} s to {after:.1} s");
+    Ok(job)
+}
+
+/// The virtual clock with `--virtual-clock`, the wall clock otherwise.
+fn clock(cli: &Cli) -> Arc<dyn Clock> {
+    if cli.virtual_clock {
+        Arc::new(VirtualClock::default())
+    } else {
+        Arc::new(RealClock)
     }
 }
 
-fn spawn_step_through_motion<S, F>(motion: Motion, stepper: S, f: F) -> JoinHandle<()>
-where
-    S: Stepper + Named + Send + 'static,
-    F: FnMut(IntervalContext) -> eyre::Result<CurrentStep> + Send + 'static,
-{
-    thread::spawn(move || exit_on_error(step_through_motion(motion, stepper, f)))
-}
-
-fn step_through_motion<S, F>(motion: Motion, stepper: S, mut f: F) -> eyre::Result<()>
-where
-    S: Stepper + Named,
-    F: FnMut(IntervalContext) -> eyre::Result<CurrentStep>,
-{
-    let Motion { path, start } = motion;
-    let interval_timer = IntervalTimer {
-        start,
-        duration: path.duration()?,
-        interval: stepper.min_step_interval(),
+/// The `--max-lateness` of an interval, unbounded without one.
+fn max_lateness(cli: &Cli) -> eyre::Result<Option<Duration>> {
+    cli.max_lateness
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .wrap_err_with(|| format!("invalid max lateness of {seconds} seconds"))
+        })
+        .transpose()
+}
+
+/// Executes the plan, or streams it with a streamer, and saves a checkpoint while plotting.
+/// Pauses, resumes and aborts on keyboard input and signals.
+fn plot(
+    cli: &Cli,
+    shutdown: &Arc<Shutdown>,
+    (mut central, mut beam, mut streamer): Axes,
+    mut plan: Plan,
+) -> eyre::Result<()> {
+    let control = Arc::new(Control::new(clock(cli)));
+    let signals = shutdown.listen(control.clone())?;
+    listen_keyboard(Arc::clone(&control));
+    log::info!("press p to pause or resume and a to abort, each followed by Enter");
+    let motions = Motion::sequence(plan.segments.clone(), control.clock().now())?;
+    let segments = match &streamer {
+        Some(_) => {
+            let (stepper, step) = (&central.stepper, central.step);
+            polar::segments(&motions, stepper, &beam.stepper, step, cli.chord_error)?
+        }
+        None => Vec::new(),
     };
-    let start_step =
-        stepper.delta_steps(CurrentStep(0), stepper.target_step(path.interpolate(0.0))?)?;
-    let mut current_step = CurrentStep(start_step.0);
-    interval_timer.for_each_fraction(|fraction| {
-        let target = motion.path.interpolate(fraction);
-        let target_step = stepper.target_step(target)?;
-        let delta_steps = stepper.delta_steps(current_step, target_step)?;
-        current_step = f(IntervalContext {
-            stepper_name: stepper.name(),
-            point: target,
-            current_step,
-            delta_steps,
-            target_step,
-        })?;
-        Ok(())
-    })
+    let mut progress = Progress::at(central.step, beam.step);
+    progress.telemetry = cli
+        .telemetry
+        .as_deref()
+        .map(Telemetry::create)
+        .transpose()?
+        .map(Arc::new);
+    progress.on_mismatch = cli.on_mismatch;
+    progress.max_lateness = max_lateness(cli)?;
+    let done = AtomicBool::new(false);
+    let result = thread::scope(|s| {
+        let saver = s.spawn(|| plan.save_periodically(&progress, &cli.checkpoint, &done));
+        let result = match &mut streamer {
+            Some(streamer) => {
+                log::info!("streaming {} segments", segments.len());
+                let names = (central.stepper.name(), beam.stepper.name());
+                let result =
+                    executor::stream(&motions, &segments, names, streamer, &progress, &control);
+                beam.pen = streamer.pen;
+                result
+            }
+            None => executor::execute(&motions, &mut central, &mut beam, &progress, &control),
+        };
+        done.store(true, Ordering::SeqCst);
+        saver.thread().unpark();
+        result
+    });
+    signals.close();
+    let parked = shutdown::park(&mut central, &mut beam, &cli.position);
+    plan.finish(
+        &result,
+        &progress,
+        (central.step, beam.step),
+        &cli.checkpoint,
+    )?;
+    parked?;
+    result
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
-struct IntervalContext {
-    pub stepper_name: &'static str,
-    pub point: Point,
-    pub current_step: CurrentStep,
-    pub delta_steps: DeltaSteps,
-    pub target_step: TargetStep,
+/// Reads `p` to pause or resume and `a` to abort, each followed by Enter.
+fn listen_keyboard(control: Arc<Control>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return log::warn!("failed to read keyboard: {e}"),
+            };
+            match line.trim() {
+                "p" if control.is_paused() => control.resume(),
+                "p" => control.pause(),
+                "a" => control.abort(),
+                other => log::warn!("unknown key {other:?}, use p to pause or resume, a to abort"),
+            }
+        }
+    });
 }
 
-impl fmt::Display for IntervalContext {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}: delta = {}, target = {}, location = {}",
-            self.stepper_name, self.delta_steps.0, self.target_step.0, self.point.location
-        )
-    }
+/// Stops before the next move on signals.
+fn calibrate(
+    cli: &Cli,
+    shutdown: &Arc<Shutdown>,
+    config: Config,
+    central: CentralStepper,
+    beam: BeamStepper,
+) -> eyre::Result<()> {
+    let (mut central_device, mut beam_device, _) = open_devices(cli, &config, &central, &beam)?;
+    let mut wizard = calibrate::Wizard {
+        input: io::stdin().lock(),
+        output: io::stdout(),
+        microsteps: cli.microsteps,
+        central_steps_per_rev: central.steps_per_rev(),
+        control: Arc::new(Control::default()),
+    };
+    let signals = shutdown.listen(wizard.control.clone())?;
+    let result = wizard.run(config, &mut *central_device, &mut *beam_device);
+    signals.close();
+    let config = result?;
+    config.save(&cli.config)?;
+    log::info!("saved calibration to {:?}: {config:?}", cli.config);
+    Ok(())
 }
 
-fn log_interval(interval: IntervalContext) -> eyre::Result<CurrentStep> {
-    if interval.delta_steps.0 != 0 {
-        log::info!("{interval}");
-    }
-    Ok(CurrentStep(interval.target_step.0))
-}
-
-fn serial_steps(
-    mut serial: Box<dyn SerialPort>,
-) -> impl FnMut(IntervalContext) -> eyre::Result<CurrentStep> + Send {
-    move |interval| {
-        if interval.delta_steps.0 != 0 {
-            log::info!("{interval}");
-            serial::write_steps(&mut serial, interval.delta_steps)?;
-            let current_step = serial::read_current_step(&mut serial)?;
-            log::info!("received {current_step:?}");
-            Ok(current_step)
+/// The central and the beam axis, and a streamer to their controller with `--segments`.
+type Axes = (Axis<CentralStepper>, Axis<BeamStepper>, Option<Streamer>);
+
+/// of the central and the beam axis, and a streamer to their controller with `--segments`
+type Devices = (Box<dyn Device>, Box<dyn Device>, Option<Streamer>);
+
+fn open_axes(
+    cli: &Cli,
+    config: &Config,
+    central: CentralStepper,
+    beam: BeamStepper,
+) -> eyre::Result<Axes> {
+    let (central_device, beam_device, streamer) = open_devices(cli, config, &central, &beam)?;
+    Ok((
+        Axis::new(central, central_device)?,
+        Axis::new(beam, beam_device)?,
+        streamer,
+    ))
+}
+
+/// Opens the central and the beam device, simulated ones without `--io`.
+fn open_devices(
+    cli: &Cli,
+    config: &Config,
+    central: &impl Stepper,
+    beam: &impl Stepper,
+) -> eyre::Result<Devices> {
+    let recorder = match (cli.io, &cli.record) {
+        (true, Some(path)) => Some(Recorder::create(path)?),
+        _ => None,
+    };
+    let recorder = recorder.as_ref();
+    if let (true, Some(endpoint)) = (cli.io, &cli.device) {
+        let link = link(cli, config.device_link)?;
+        let (central, beam) = open_controller(endpoint, &link, recorder, central, beam)?;
+        let streamer = if cli.segments {
+            Some(central.streamer()?)
         } else {
-            Ok(interval.current_step)
+            None
+        };
+        Ok((Box::new(central), Box::new(beam), streamer))
+    } else if cli.io {
+        let central_link = link(cli, config.central_link)?;
+        let beam_link = link(cli, config.beam_link)?;
+        let central = open_transport(
+            cli,
+            "central",
+            &cli.central,
+            &central_link,
+            recorder,
+            central,
+        )?;
+        let beam = open_transport(cli, "beam", &cli.beam, &beam_link, recorder, beam)?;
+        if cli.batch {
+            let batched = |transport| match recorder {
+                Some(_) => BatchedDevice::new(transport).with_scope(record::scope),
+                None => BatchedDevice::new(transport),
+            };
+            Ok((Box::new(batched(central)), Box::new(batched(beam)), None))
+        } else {
+            Ok((Box::new(central), Box::new(beam), None))
         }
+    } else {
+        let central = SimulatedDevice {
+            period: central.period(),
+            ..SimulatedDevice::default()
+        };
+        Ok((
+            Box::new(central),
+            Box::new(SimulatedDevice::default()),
+            None,
+        ))
     }
 }
 
-#[track_caller]
-fn exit_on_error<T, E>(result: Result<T, E>)
-where
-    E: Debug,
-{
-    if let Err(e) = result {
-        let location = Location::caller();
-        log::error!("exit at {location} caused by error {e:?}");
-        process::exit(-1)
+/// A single stroke from the start to the end point.
+fn job_from_cli(cli: &Cli) -> eyre::Result<Job> {
+    let end = cli.end.ok_or_else(|| eyre!("missing end point"))?;
+    Ok(Job::new(vec![vec![cli.start, end]]))
+}
+
+/// Opens one microcontroller driving both motors like [`open_transport`].
+fn open_controller(
+    endpoint: &Endpoint,
+    link: &Link,
+    recorder: Option<&Recorder>,
+    central: &impl Stepper,
+    beam: &impl Stepper,
+) -> eyre::Result<(ControllerAxis, ControllerAxis)> {
+    let microsteps = central.microsteps();
+    if beam.microsteps() != microsteps {
+        return Err(eyre!(
+            "a single controller drives both motors with the same microsteps, \
+            not {microsteps:?} and {:?}",
+            beam.microsteps()
+        ));
+    }
+    if let Some(baud) = endpoint.baud(link) {
+        // the axes talk to the controller one after another at worst
+        let rate = [central.min_step_interval(), beam.min_step_interval()]
+            .iter()
+            .map(|interval| 1.0 / interval.as_secs_f64())
+            .sum::<f64>();
+        let name = format!("controller at {endpoint}");
+        let interval = Duration::from_secs_f64(1.0 / rate);
+        serial::check_bandwidth(&name, baud, interval, serial::MULTI_AXIS_MESSAGE_BITS)?;
     }
+    let transport = link
+        .retry(|| {
+            let mut transport = open_recorded(endpoint, link, recorder, "controller")?;
+            serial::write_microsteps(&mut transport, microsteps)?;
+            let steps = serial::read_multi_axis_steps(&mut transport)?;
+            log::info!("switched {endpoint} to {microsteps:?} at {steps:?}");
+            Ok(transport)
+        })
+        .wrap_err_with(|| format!("failed to open controller at {endpoint}"))?;
+    Ok(controller::split(transport))
+}
+
+/// Opens the endpoint once, recording it as `device` with a recorder.
+fn open_recorded(
+    endpoint: &Endpoint,
+    link: &Link,
+    recorder: Option<&Recorder>,
+    device: &'static str,
+) -> eyre::Result<Box<dyn Transport>> {
+    let transport = endpoint.open(link)?;
+    Ok(match recorder {
+        Some(recorder) => recorder.record(transport, device),
+        None => transport,
+    })
+}
+
+/// The command line overrides the config.
+fn link(cli: &Cli, config: LinkConfig) -> eyre::Result<Link> {
+    let default = Link::default();
+    let seconds = |value: Option<f64>, default, name| match value {
+        Some(value) => Duration::try_from_secs_f64(value)
+            .wrap_err_with(|| format!("invalid {name} of {value} seconds")),
+        None => Ok(default),
+    };
+    Ok(Link {
+        baud: cli.baud.or(config.baud),
+        timeout: seconds(cli.timeout.or(config.timeout), default.timeout, "timeout")?,
+        retries: cli.retries.or(config.retries).unwrap_or(default.retries),
+        backoff: seconds(cli.backoff.or(config.backoff), default.backoff, "backoff")?,
+    })
+}
+
+/// Checks that the link keeps up with the stepper, then opens it and sets the microsteps
+/// and the period of a stepper with one, retrying both.
+fn open_transport(
+    cli: &Cli,
+    name: &'static str,
+    endpoint: &Endpoint,
+    link: &Link,
+    recorder: Option<&Recorder>,
+    stepper: &impl Stepper,
+) -> eyre::Result<Box<dyn Transport>> {
+    if let Some(baud) = endpoint.baud(link) {
+        let name = format!("{name} motor at {endpoint}");
+        let step_bits = if cli.batch {
+            serial::BATCHED_STEP_BITS
+        } else {
+            serial::MESSAGE_BITS
+        };
+        serial::check_bandwidth(&name, baud, stepper.min_step_interval(), step_bits)?;
+    }
+    let microsteps = stepper.microsteps();
+    link.retry(|| {
+        let mut transport = open_recorded(endpoint, link, recorder, name)?;
+        serial::write_microsteps(&mut transport, microsteps)?;
+        let current_step = serial::read_current_step(&mut transport)?;
+        log::info!("switched {endpoint} to {microsteps:?} at {current_step:?}");
+        if let Some(period) = stepper.period() {
+            serial::write_period(&mut transport, period)?;
+            let current_step = serial::read_current_step(&mut transport)?;
+            log::info!("wrapped {endpoint} at {period} steps at {current_step:?}");
+        }
+        Ok(transport)
+    })
+    .wrap_err_with(|| format!("failed to open {name} motor at {endpoint}"))
 }
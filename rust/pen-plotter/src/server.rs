@@ -0,0 +1,770 @@
+use crate::{
+    axis::{self, Axis},
+    clock::{Clock, RealClock},
+    control::{Aborted, Control},
+    executor::{self, OnMismatch, Progress},
+    job::{Format, Job, Options},
+    motion::Motion,
+    optimize::{self, JointCost},
+    shutdown::Controlled,
+    stepper::{beam::BeamStepper, central::CentralStepper, CurrentStep},
+    telemetry::Telemetry,
+    tool::Tools,
+};
+use eyre::eyre;
+use nalgebra::Vector2;
+use serde::Serialize;
+use std::{
+    io::Cursor,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response};
+
+type JobId = u64;
+
+/// Queues jobs submitted over HTTP and plots them one after another.
+pub struct Daemon {
+    state: Mutex<State>,
+    changed: Condvar,
+    central: CentralStepper,
+    beam: BeamStepper,
+
+    /// velocity and pen changes of every job
+    tools: Tools,
+
+    /// added to every submitted job
+    origin: Vector2<f64>,
+
+    /// whether to reorder the strokes of jobs
+    optimize: bool,
+
+    /// for parsing submitted jobs
+    options: Options,
+
+    /// receives the samples of all jobs
+    telemetry: Option<Arc<Telemetry>>,
+
+    /// of an interval before a job fails, unbounded if `None`
+    max_lateness: Option<Duration>,
+    on_mismatch: OnMismatch,
+
+    /// shared by all jobs
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    jobs: Vec<Entry>,
+    next_id: JobId,
+    paused: bool,
+    shutdown: bool,
+    running: Option<Running>,
+
+    /// of the axes between jobs
+    steps: (CurrentStep, CurrentStep),
+}
+
+#[derive(Debug, Clone)]
+struct Running {
+    id: JobId,
+    progress: Arc<Progress>,
+    control: Arc<Control>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: JobId,
+    state: JobState,
+
+    /// taken when the job starts
+    job: Option<Job>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobStatus {
+    pub id: JobId,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+    pub paused: bool,
+    pub current_job: Option<JobId>,
+
+    /// of the current job, from 0 to 1
+    pub progress: Option<f64>,
+
+    /// deadlines both axes missed in the finished motions of the current job
+    pub missed_deadlines: Option<u64>,
+
+    /// of an interval in the finished motions of the current job in seconds
+    pub max_lateness: Option<f64>,
+
+    /// of the pen in millimeter
+    pub location: [f64; 2],
+    pub queued: Vec<JobId>,
+}
+
+impl Daemon {
+    pub fn new(
+        central: &Axis<CentralStepper>,
+        beam: &Axis<BeamStepper>,
+        tools: Tools,
+        origin: Vector2<f64>,
+        optimize: bool,
+        options: Options,
+    ) -> Self {
+        Self {
+            state: Mutex::new(State {
+                next_id: 1,
+                steps: (central.step, beam.step),
+                ..Default::default()
+            }),
+            changed: Condvar::new(),
+            central: central.stepper,
+            beam: beam.stepper,
+            tools,
+            origin,
+            optimize,
+            options,
+            telemetry: None,
+            max_lateness: None,
+            on_mismatch: OnMismatch::default(),
+            clock: Arc::new(RealClock),
+        }
+    }
+
+    /// Fails jobs on late intervals and mismatching steps like a plot.
+    pub fn with_checks(mut self, max_lateness: Option<Duration>, on_mismatch: OnMismatch) -> Self {
+        self.max_lateness = max_lateness;
+        self.on_mismatch = on_mismatch;
+        self
+    }
+
+    /// Runs the jobs on `clock` instead of the wall clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Writes the samples of all jobs to `telemetry`.
+    pub fn with_telemetry(mut self, telemetry: Option<Telemetry>) -> Self {
+        self.telemetry = telemetry.map(Arc::new);
+        self
+    }
+
+    pub fn submit(&self, job: Job) -> JobId {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.jobs.push(Entry {
+            id,
+            state: JobState::Queued,
+            job: Some(job.translate(self.origin)),
+        });
+        self.changed.notify_all();
+        id
+    }
+
+    /// Removes a queued job or aborts the running one.
+    pub fn cancel(&self, id: JobId) -> eyre::Result<JobStatus> {
+        let mut state = self.lock();
+        if let Some(running) = &state.running {
+            if running.id == id {
+                running.control.abort();
+            }
+        }
+        let entry = state
+            .jobs
+            .iter_mut()
+            .find(|e| e.id == id)
+            .ok_or_else(|| eyre!("unknown job {id}"))?;
+        if entry.state == JobState::Queued {
+            entry.state = JobState::Cancelled;
+            entry.job = None;
+        }
+        Ok(entry.status())
+    }
+
+    /// Holds the running job and doesn't start queued ones until [`Daemon::resume`].
+    pub fn pause(&self) {
+        let mut state = self.lock();
+        state.paused = true;
+        if let Some(running) = &state.running {
+            running.control.pause();
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.lock();
+        state.paused = false;
+        if let Some(running) = &state.running {
+            running.control.resume();
+        }
+        self.changed.notify_all();
+    }
+
+    /// Makes [`Daemon::work`] return after the running job.
+    pub fn shutdown(&self) {
+        self.lock().shutdown = true;
+        self.changed.notify_all();
+    }
+
+    pub fn job(&self, id: JobId) -> Option<JobStatus> {
+        self.lock()
+            .jobs
+            .iter()
+            .find(|e| e.id == id)
+            .map(Entry::status)
+    }
+
+    pub fn jobs(&self) -> Vec<JobStatus> {
+        self.lock().jobs.iter().map(Entry::status).collect()
+    }
+
+    pub fn status(&self) -> Status {
+        let state = self.lock();
+        let (steps, progress) = match &state.running {
+            Some(running) => (running.progress.steps(), Some(running.progress.fraction())),
+            None => (state.steps, None),
+        };
+        let timing = state.running.as_ref().map(|r| r.progress.timing());
+        let location = axis::location(&self.central, steps.0, &self.beam, steps.1);
+        Status {
+            paused: state.paused,
+            current_job: state.running.as_ref().map(|r| r.id),
+            progress,
+            missed_deadlines: timing.map(|timing| timing.missed),
+            max_lateness: timing.map(|timing| timing.max_lateness.as_secs_f64()),
+            location: [location.x, location.y],
+            queued: state
+                .jobs
+                .iter()
+                .filter(|e| e.state == JobState::Queued)
+                .map(|e| e.id)
+                .collect(),
+        }
+    }
+
+    /// Plots queued jobs until [`Daemon::shutdown`].
+    pub fn work(&self, central: &mut Axis<CentralStepper>, beam: &mut Axis<BeamStepper>) {
+        while let Some((job, running)) = self.next_job() {
+            let id = running.id;
+            log::info!("starting job {id}");
+            let result = self.plot(&job, central, beam, &running);
+            let job_state = match result {
+                Ok(()) => JobState::Done,
+                Err(e) if e.is::<Aborted>() => JobState::Cancelled,
+                Err(e) => {
+                    log::error!("job {id} failed: {e:?}");
+                    JobState::Failed {
+                        error: format!("{e:#}"),
+                    }
+                }
+            };
+            log::info!("finished job {id}: {job_state:?}");
+            let mut state = self.lock();
+            state.running = None;
+            state.steps = (central.step, beam.step);
+            if let Some(entry) = state.jobs.iter_mut().find(|e| e.id == id) {
+                entry.state = job_state;
+            }
+        }
+    }
+
+    fn next_job(&self) -> Option<(Job, Running)> {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return None;
+            }
+            if !state.paused {
+                let steps = state.steps;
+                let queued = state.jobs.iter_mut().find(|e| e.state == JobState::Queued);
+                if let Some(entry) = queued {
+                    entry.state = JobState::Running;
+                    let job = entry.job.take().unwrap_or_default();
+                    let id = entry.id;
+                    let running = Running {
+                        id,
+                        progress: Arc::new(Progress {
+                            telemetry: self.telemetry.clone(),
+                            max_lateness: self.max_lateness,
+                            on_mismatch: self.on_mismatch,
+                            ..Progress::at(steps.0, steps.1)
+                        }),
+                        control: Arc::new(Control::new(Arc::clone(&self.clock))),
+                    };
+                    state.running = Some(running.clone());
+                    return Some((job, running));
+                }
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn plot(
+        &self,
+        job: &Job,
+        central: &mut Axis<CentralStepper>,
+        beam: &mut Axis<BeamStepper>,
+        running: &Running,
+    ) -> eyre::Result<()> {
+        let location = axis::location(&central.stepper, central.step, &beam.stepper, beam.step);
+        let optimized;
+        let job = if self.optimize {
+            let cost = JointCost {
+                central: self.central,
+                beam: self.beam,
+            };
+            optimized = optimize::optimize(job.clone(), location, &self.tools, &cost)?;
+            &optimized
+        } else {
+            job
+        };
+        let segments = job.segments(location, &self.tools);
+        let motions = Motion::sequence(segments, running.control.clock().now())?;
+        executor::execute(&motions, central, beam, &running.progress, &running.control)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Controlled for Daemon {
+    fn pause(&self) {
+        Daemon::pause(self)
+    }
+
+    fn resume(&self) {
+        Daemon::resume(self)
+    }
+
+    /// Aborts the running job and stops the daemon.
+    fn abort(&self) {
+        self.shutdown();
+        if let Some(running) = &self.lock().running {
+            running.control.abort();
+        }
+    }
+}
+
+impl Entry {
+    fn status(&self) -> JobStatus {
+        JobStatus {
+            id: self.id,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Plots in a background thread and answers HTTP requests on `listen`
+/// until the daemon is aborted.
+pub fn serve(
+    listen: &str,
+    daemon: &Daemon,
+    central: &mut Axis<CentralStepper>,
+    beam: &mut Axis<BeamStepper>,
+) -> eyre::Result<()> {
+    let http =
+        tiny_http::Server::http(listen).map_err(|e| eyre!("failed to listen on {listen}: {e}"))?;
+    log::info!("listening on {listen}");
+    thread::scope(|s| {
+        s.spawn(|| {
+            daemon.work(central, beam);
+            http.unblock();
+        });
+        handle_requests(&http, daemon);
+    });
+    Ok(())
+}
+
+/// Returns when the server is unblocked.
+pub fn handle_requests(http: &tiny_http::Server, daemon: &Daemon) {
+    for mut request in http.incoming_requests() {
+        let response = handle(daemon, &mut request);
+        if let Err(e) = request.respond(response) {
+            log::warn!("failed to respond: {e}");
+        }
+    }
+}
+
+type JsonResponse = Response<Cursor<Vec<u8>>>;
+
+fn handle(daemon: &Daemon, request: &mut Request) -> JsonResponse {
+    let url = request.url().to_owned();
+    let segments: Vec<&str> = url
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["status"]) => json(200, &daemon.status()),
+        (Method::Post, ["pause"]) => {
+            daemon.pause();
+            json(200, &daemon.status())
+        }
+        (Method::Post, ["resume"]) => {
+            daemon.resume();
+            json(200, &daemon.status())
+        }
+        (Method::Get, ["jobs"]) => json(200, &daemon.jobs()),
+        (Method::Post, ["jobs"]) => match read_job(request, &daemon.options) {
+            Ok(job) => {
+                let id = daemon.submit(job);
+                json(201, &serde_json::json!({ "id": id }))
+            }
+            Err(e) => error(400, e),
+        },
+        (Method::Get, ["jobs", id]) => match parse_id(id) {
+            Ok(id) => match daemon.job(id) {
+                Some(status) => json(200, &status),
+                None => error(404, eyre!("unknown job {id}")),
+            },
+            Err(e) => error(400, e),
+        },
+        (Method::Delete, ["jobs", id]) => match parse_id(id).and_then(|id| daemon.cancel(id)) {
+            Ok(status) => json(200, &status),
+            Err(e) => error(404, e),
+        },
+        _ => error(404, eyre!("no route for {} {url}", request.method())),
+    }
+}
+
+fn read_job(request: &mut Request, options: &Options) -> eyre::Result<Job> {
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Content-Type"))
+        .map(|h| h.value.as_str().to_owned())
+        .ok_or_else(|| eyre!("missing Content-Type header"))?;
+    let format = Format::from_content_type(&content_type)?;
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    Job::parse(format, &body, options)
+}
+
+fn parse_id(id: &str) -> eyre::Result<JobId> {
+    id.parse().map_err(|e| eyre!("invalid job id {id:?}: {e}"))
+}
+
+fn json<T: Serialize>(status: u16, value: &T) -> JsonResponse {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    let header = Header::from_bytes("Content-Type", "application/json")
+        .unwrap_or_else(|()| unreachable!("static header is valid"));
+    Response::from_data(body)
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn error(status: u16, e: eyre::Report) -> JsonResponse {
+    json(status, &serde_json::json!({ "error": format!("{e:#}") }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::VirtualClock,
+        device::{Device, SimulatedDevice},
+        geometry::Tolerance,
+        pen::Pen,
+        stepper::DeltaSteps,
+    };
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::mpsc,
+    };
+
+    /// Blocks its first step until released, so the job is running.
+    struct GatedDevice {
+        device: SimulatedDevice,
+        reached: Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
+    }
+
+    impl Device for GatedDevice {
+        fn step(&mut self, delta: DeltaSteps) -> eyre::Result<CurrentStep> {
+            if delta.0 != 0 {
+                if let Some((reached, released)) = self.reached.take() {
+                    reached.send(())?;
+                    released.recv()?;
+                }
+            }
+            self.device.step(delta)
+        }
+
+        fn set_pen(&mut self, pen: Pen) -> eyre::Result<CurrentStep> {
+            self.device.set_pen(pen)
+        }
+    }
+
+    fn axes() -> (Axis<CentralStepper>, Axis<BeamStepper>) {
+        let central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        );
+        let beam = Axis::new(BeamStepper::default(), Box::new(SimulatedDevice::default()));
+        (central.unwrap(), beam.unwrap())
+    }
+
+    fn request(
+        address: SocketAddr,
+        method: &str,
+        path: &str,
+        body: Option<(&str, &str)>,
+    ) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        let (content_type, body) = body.unwrap_or(("text/plain", ""));
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+            Content-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    /// Runs the daemon with simulated devices and a loopback HTTP server during `f`.
+    fn with_server(f: impl FnOnce(SocketAddr, &Daemon)) {
+        let (mut central, mut beam) = axes();
+        let daemon = Daemon::new(
+            &central,
+            &beam,
+            Tools::new(500.0),
+            Vector2::zeros(),
+            false,
+            OPTIONS,
+        );
+        let http = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let address = http.server_addr().to_ip().unwrap();
+        thread::scope(|s| {
+            s.spawn(|| daemon.work(&mut central, &mut beam));
+            s.spawn(|| handle_requests(&http, &daemon));
+            f(address, &daemon);
+            http.unblock();
+            daemon.shutdown();
+        });
+    }
+
+    fn wait_for(address: SocketAddr, id: u64, state: &str) {
+        for _ in 0..200 {
+            let (_, job) = request(address, "GET", &format!("/jobs/{id}"), None);
+            if job["state"] == state {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("job {id} is not {state}");
+    }
+
+    const POINTS: &str = "[[[0, -10], [0, -20], [5, -20]]]";
+
+    const OPTIONS: Options = Options {
+        tolerance: Tolerance::Fixed(0.01),
+        fill: None,
+    };
+
+    #[test]
+    fn plot_points() {
+        with_server(|address, _| {
+            let (status, body) =
+                request(address, "POST", "/jobs", Some(("application/json", POINTS)));
+            assert_eq!(status, 201);
+            assert_eq!(body["id"], 1);
+            wait_for(address, 1, "done");
+            let (_, status) = request(address, "GET", "/status", None);
+            assert_eq!(status["current_job"], serde_json::Value::Null);
+            assert_eq!(status["missed_deadlines"], serde_json::Value::Null);
+            let x = status["location"][0].as_f64().unwrap();
+            let y = status["location"][1].as_f64().unwrap();
+            assert!((x - 5.0).abs() < 0.5 && (y + 20.0).abs() < 0.5, "{status}");
+        });
+    }
+
+    #[test]
+    fn pause_queue_and_cancel() {
+        with_server(|address, _| {
+            request(address, "POST", "/pause", None);
+            let body = Some(("text/x-gcode", "G0 X0 Y-10\nG1 X0 Y-20"));
+            request(address, "POST", "/jobs", body);
+            request(address, "POST", "/jobs", body);
+            let (_, status) = request(address, "GET", "/status", None);
+            assert_eq!(status["queued"], serde_json::json!([1, 2]));
+            let (status, job) = request(address, "DELETE", "/jobs/1", None);
+            assert_eq!((status, job["state"].as_str()), (200, Some("cancelled")));
+            request(address, "POST", "/resume", None);
+            wait_for(address, 2, "done");
+            let (_, job) = request(address, "GET", "/jobs/1", None);
+            assert_eq!(job["state"], "cancelled");
+        });
+    }
+
+    #[test]
+    fn bad_requests() {
+        with_server(|address, _| {
+            let (status, body) = request(address, "POST", "/jobs", Some(("text/html", "")));
+            assert_eq!(status, 400);
+            assert!(body["error"].as_str().unwrap().contains("content type"));
+            let (status, _) = request(address, "GET", "/jobs/7", None);
+            assert_eq!(status, 404);
+            let (status, _) = request(address, "PUT", "/status", None);
+            assert_eq!(status, 404);
+        });
+    }
+
+    #[test]
+    fn cancel_running_job() {
+        let (mut central, mut beam) = axes();
+        let daemon = Daemon::new(
+            &central,
+            &beam,
+            Tools::new(1.0),
+            Vector2::zeros(),
+            false,
+            OPTIONS,
+        );
+        let job = Job::parse(Format::Points, POINTS, &OPTIONS).unwrap();
+        let id = daemon.submit(job);
+        thread::scope(|s| {
+            s.spawn(|| daemon.work(&mut central, &mut beam));
+            while daemon.status().current_job.is_none() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            daemon.cancel(id).unwrap();
+            daemon.shutdown();
+        });
+        assert_eq!(daemon.job(id).unwrap().state, JobState::Cancelled);
+    }
+
+    #[test]
+    fn pause_holds_running_job() {
+        let (mut central, _) = axes();
+        let (reached, gate_reached) = mpsc::channel();
+        let (release, gate_released) = mpsc::channel();
+        let gated = GatedDevice {
+            device: SimulatedDevice::default(),
+            reached: Some((reached, gate_released)),
+        };
+        let mut beam = Axis::new(BeamStepper::default(), Box::new(gated)).unwrap();
+        let daemon = Daemon::new(
+            &central,
+            &beam,
+            Tools::new(20.0),
+            Vector2::zeros(),
+            false,
+            OPTIONS,
+        )
+        .with_clock(Arc::new(VirtualClock::default()));
+        let job = Job::parse(Format::Points, POINTS, &OPTIONS).unwrap();
+        let id = daemon.submit(job);
+        let is_held = || {
+            let state = daemon.lock();
+            state.running.as_ref().is_some_and(|r| r.control.is_held())
+        };
+        thread::scope(|s| {
+            s.spawn(|| daemon.work(&mut central, &mut beam));
+            gate_reached.recv().unwrap();
+            daemon.pause();
+            release.send(()).unwrap();
+            // the virtual clock stands still once both axes hold
+            while !is_held() {
+                thread::yield_now();
+            }
+            let held = daemon.status();
+            for _ in 0..100 {
+                thread::yield_now();
+            }
+            assert_eq!(daemon.status(), held);
+            assert!(held.paused);
+            assert_eq!(held.current_job, Some(id));
+            assert!(held.missed_deadlines.is_some());
+            daemon.resume();
+            daemon.shutdown();
+        });
+        assert_eq!(daemon.job(id).unwrap().state, JobState::Done);
+    }
+
+    #[test]
+    fn optimize_submitted_jobs() {
+        let (mut central, mut beam) = axes();
+        let daemon = Daemon::new(
+            &central,
+            &beam,
+            Tools::new(500.0),
+            Vector2::zeros(),
+            true,
+            OPTIONS,
+        );
+        let far = "[[[0, -50], [0, -60]], [[0, -12], [0, -20]]]";
+        let id = daemon.submit(Job::parse(Format::Points, far, &OPTIONS).unwrap());
+        thread::scope(|s| {
+            s.spawn(|| daemon.work(&mut central, &mut beam));
+            while let JobState::Queued | JobState::Running = daemon.job(id).unwrap().state {
+                thread::sleep(Duration::from_millis(1));
+            }
+            daemon.shutdown();
+        });
+        assert_eq!(daemon.job(id).unwrap().state, JobState::Done);
+        // the near stroke first, so the far one ends the job
+        assert_eq!(beam.step, CurrentStep(2000));
+    }
+
+    #[test]
+    fn jobs_write_telemetry() {
+        let path = std::env::temp_dir().join(format!(
+            "pen-plotter-server-telemetry-{}.jsonl",
+            std::process::id()
+        ));
+        let (mut central, mut beam) = axes();
+        let daemon = Daemon::new(
+            &central,
+            &beam,
+            Tools::new(500.0),
+            Vector2::zeros(),
+            false,
+            OPTIONS,
+        )
+        .with_telemetry(Some(Telemetry::create(&path).unwrap()));
+        let ids = [
+            daemon.submit(Job::parse(Format::Points, POINTS, &OPTIONS).unwrap()),
+            daemon.submit(Job::parse(Format::Points, POINTS, &OPTIONS).unwrap()),
+        ];
+        thread::scope(|s| {
+            s.spawn(|| daemon.work(&mut central, &mut beam));
+            while let JobState::Queued | JobState::Running = daemon.job(ids[1]).unwrap().state {
+                thread::sleep(Duration::from_millis(1));
+            }
+            daemon.shutdown();
+        });
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let steppers: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["stepper"].clone())
+            .collect();
+        assert!(steppers.contains(&"central stepper".into()));
+        assert!(steppers.contains(&"beam stepper".into()));
+    }
+}
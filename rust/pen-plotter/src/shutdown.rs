@@ -0,0 +1,222 @@
+use crate::{
+    axis::{self, Axis},
+    control::{Aborted, Control},
+    stepper::{beam::BeamStepper, central::CentralStepper},
+};
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use signal_hook::{
+    consts::signal::{SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2},
+    iterator::{Handle, Signals},
+};
+use std::{
+    fs,
+    path::Path,
+    process::{self, ExitCode},
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+/// exit code after an abort by the user
+const ABORTED: u8 = 2;
+
+/// What signals act on.
+pub trait Controlled: Send + Sync {
+    fn pause(&self);
+
+    fn resume(&self);
+
+    /// Stops at the next interval of the motors.
+    fn abort(&self);
+}
+
+impl Controlled for Control {
+    fn pause(&self) {
+        Control::pause(self)
+    }
+
+    fn resume(&self) {
+        Control::resume(self)
+    }
+
+    fn abort(&self) {
+        Control::abort(self)
+    }
+}
+
+/// Coordinates stopping on signals and errors and picks the exit code.
+///
+/// The motors stop between two messages to their microcontrollers,
+/// never between sending steps and receiving the current step.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    /// the first SIGINT or SIGTERM received, 0 for none
+    signal: AtomicI32,
+}
+
+impl Shutdown {
+    /// Aborts `target` on SIGINT, SIGTERM and SIGQUIT and pauses and resumes it
+    /// on SIGUSR1 and SIGUSR2.
+    /// A second SIGINT or SIGTERM exits immediately.
+    ///
+    /// Stops listening when the returned handle is closed.
+    pub fn listen(self: &Arc<Self>, target: Arc<dyn Controlled>) -> eyre::Result<Handle> {
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGQUIT, SIGUSR1, SIGUSR2])
+            .wrap_err("failed to register signal handlers")?;
+        let handle = signals.handle();
+        let shutdown = Arc::clone(self);
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                log::info!("received signal {signal}");
+                match signal {
+                    SIGUSR1 => target.pause(),
+                    SIGUSR2 => target.resume(),
+                    SIGQUIT => target.abort(),
+                    _ => {
+                        if shutdown.signal.swap(signal, Ordering::SeqCst) != 0 {
+                            log::error!("exit immediately on second signal {signal}");
+                            process::exit(128 + signal);
+                        }
+                        target.abort();
+                    }
+                }
+            }
+        });
+        Ok(handle)
+    }
+
+    /// 128 plus the signal number after SIGINT or SIGTERM, 2 after an abort and 1 after errors.
+    pub fn exit_code(&self, result: &eyre::Result<()>) -> ExitCode {
+        let signal = self.signal.load(Ordering::SeqCst);
+        match result {
+            _ if signal != 0 => ExitCode::from(128 + signal as u8),
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) if e.is::<Aborted>() => ExitCode::from(ABORTED),
+            Err(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
+/// Where the axes stopped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub central_step: i16,
+    pub beam_step: i16,
+
+    /// of the pen in millimeter
+    pub location: [f64; 2],
+}
+
+impl Position {
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        let text = toml::to_string(self).wrap_err("failed to serialize position")?;
+        fs::write(path, text).wrap_err_with(|| format!("failed to write position {path:?}"))
+    }
+}
+
+/// Lifts the pen, reads the final steps back from the devices and saves them to `path`.
+/// Failing devices keep their last reported step.
+pub fn park(
+    central: &mut Axis<CentralStepper>,
+    beam: &mut Axis<BeamStepper>,
+    path: &Path,
+) -> eyre::Result<Position> {
+    if let Err(e) = beam.lift_pen() {
+        log::error!("failed to lift the pen: {e:?}");
+    }
+    if let Err(e) = central.read_back() {
+        log::warn!("failed to read back the central step: {e:?}");
+    }
+    if let Err(e) = beam.read_back() {
+        log::warn!("failed to read back the beam step: {e:?}");
+    }
+    let location = axis::location(&central.stepper, central.step, &beam.stepper, beam.step);
+    let position = Position {
+        central_step: central.step.0,
+        beam_step: beam.step.0,
+        location: [location.x, location.y],
+    };
+    position.save(path)?;
+    log::info!("stopped at location {location}, saved to {path:?}: {position:?}");
+    Ok(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        device::{Device, SimulatedDevice},
+        pen::Pen,
+        stepper::{CurrentStep, DeltaSteps},
+    };
+    use std::env;
+
+    /// Fails every request.
+    struct BrokenDevice;
+
+    impl Device for BrokenDevice {
+        fn step(&mut self, _: DeltaSteps) -> eyre::Result<CurrentStep> {
+            Err(eyre::eyre!("disconnected"))
+        }
+
+        fn set_pen(&mut self, _: Pen) -> eyre::Result<CurrentStep> {
+            Err(eyre::eyre!("disconnected"))
+        }
+    }
+
+    #[test]
+    fn park_lifts_pen_and_saves_position() {
+        let mut central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        )
+        .unwrap();
+        let mut beam =
+            Axis::new(BeamStepper::default(), Box::new(SimulatedDevice::default())).unwrap();
+        beam.set_pen(Pen::Down).unwrap();
+        beam.device.step(DeltaSteps(400)).unwrap();
+        let path = env::temp_dir().join(format!("pen-plotter-park-test-{}.toml", process::id()));
+        let position = park(&mut central, &mut beam, &path).unwrap();
+        assert_eq!(beam.pen, Some(Pen::Up));
+        assert_eq!(position.beam_step, 400);
+        let saved: Position = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, position);
+    }
+
+    #[test]
+    fn park_keeps_last_step_of_broken_device() {
+        let mut central = Axis::new(
+            CentralStepper::default(),
+            Box::new(SimulatedDevice::default()),
+        )
+        .unwrap();
+        let mut beam = Axis {
+            stepper: BeamStepper::default(),
+            device: Box::new(BrokenDevice),
+            step: CurrentStep(7),
+            pen: Some(Pen::Down),
+        };
+        let path = env::temp_dir().join(format!(
+            "pen-plotter-park-broken-test-{}.toml",
+            process::id()
+        ));
+        let position = park(&mut central, &mut beam, &path).unwrap();
+        assert_eq!(position.beam_step, 7);
+    }
+
+    #[test]
+    fn exit_codes() {
+        let shutdown = Shutdown::default();
+        assert_eq!(shutdown.exit_code(&Ok(())), ExitCode::SUCCESS);
+        assert_eq!(shutdown.exit_code(&Err(Aborted.into())), ExitCode::from(2));
+        assert_eq!(
+            shutdown.exit_code(&Err(eyre::eyre!("failed"))),
+            ExitCode::FAILURE
+        );
+        shutdown.signal.store(SIGTERM, Ordering::SeqCst);
+        assert_eq!(shutdown.exit_code(&Ok(())), ExitCode::from(143));
+    }
+}
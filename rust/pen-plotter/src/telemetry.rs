@@ -0,0 +1,212 @@
+use eyre::WrapErr;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+const CSV_HEADER: &str =
+    "time,motion,stepper,fraction,x,y,target_step,delta_steps,reported_step,latency,lateness";
+
+/// One interval of one axis.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Sample {
+    pub stepper: &'static str,
+
+    /// of the motion, past 1 in its last interval
+    pub fraction: f64,
+
+    /// in millimeter
+    pub target: [f64; 2],
+    pub target_step: i16,
+    pub delta_steps: i16,
+
+    /// `None` if the device only returned the step it expected, like a batched one
+    pub reported_step: Option<i16>,
+
+    /// from sending the delta until the device reported its step in seconds,
+    /// `None` without steps to send or a reported step
+    pub latency: Option<f64>,
+
+    /// of the interval after its deadline in seconds
+    pub lateness: f64,
+}
+
+/// Writes a sample of every interval of both axes to a file,
+/// as CSV if its name ends with `.csv` and as JSON lines otherwise.
+#[derive(Debug)]
+pub struct Telemetry {
+    start: Instant,
+    csv: bool,
+    file: Mutex<BufWriter<File>>,
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    time: f64,
+    motion: usize,
+    #[serde(flatten)]
+    sample: &'a Sample,
+}
+
+impl Telemetry {
+    pub fn create(path: &Path) -> eyre::Result<Self> {
+        let file =
+            File::create(path).wrap_err_with(|| format!("failed to create telemetry {path:?}"))?;
+        let csv = path.extension().is_some_and(|extension| extension == "csv");
+        let mut file = BufWriter::new(file);
+        if csv {
+            writeln!(file, "{CSV_HEADER}")?;
+        }
+        Ok(Self {
+            start: Instant::now(),
+            csv,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes a sample of the motion with the given index, stamped with the seconds since the start.
+    pub fn write(&self, motion: usize, sample: &Sample) {
+        let line = Line {
+            time: self.start.elapsed().as_secs_f64(),
+            motion,
+            sample,
+        };
+        let line = match self.csv {
+            true => Ok(line.csv()),
+            false => serde_json::to_string(&line),
+        };
+        let result = line.map_err(eyre::Report::from).and_then(|line| {
+            let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(writeln!(file, "{line}")?)
+        });
+        if let Err(e) = result {
+            log::warn!("failed to write telemetry: {e}");
+        }
+    }
+
+    /// Writes the buffered samples to the file.
+    pub fn flush(&self) -> eyre::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(file.flush()?)
+    }
+}
+
+impl Line<'_> {
+    fn csv(&self) -> String {
+        let Sample {
+            stepper,
+            fraction,
+            target: [x, y],
+            target_step,
+            delta_steps,
+            reported_step,
+            latency,
+            lateness,
+        } = self.sample;
+        let reported_step = reported_step
+            .map(|step| step.to_string())
+            .unwrap_or_default();
+        let latency = latency
+            .map(|latency| latency.to_string())
+            .unwrap_or_default();
+        format!(
+            "{},{},{stepper},{fraction},{x},{y},{target_step},{delta_steps},{reported_step},{latency},{lateness}",
+            self.time, self.motion
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    fn sample() -> Sample {
+        Sample {
+            stepper: "beam stepper",
+            fraction: 0.5,
+            target: [0.0, -10.5],
+            target_step: 20,
+            delta_steps: 2,
+            reported_step: Some(20),
+            latency: Some(0.001),
+            lateness: 0.0,
+        }
+    }
+
+    #[test]
+    fn write_json_lines() {
+        let path = env::temp_dir().join(format!("pen-plotter-telemetry-{}.jsonl", process::id()));
+        let telemetry = Telemetry::create(&path).unwrap();
+        telemetry.write(3, &sample());
+        telemetry.write(
+            3,
+            &Sample {
+                latency: None,
+                ..sample()
+            },
+        );
+        telemetry.flush().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["motion"], 3);
+        assert_eq!(lines[0]["stepper"], "beam stepper");
+        assert_eq!(lines[0]["target"], serde_json::json!([0.0, -10.5]));
+        assert_eq!(lines[0]["latency"], 0.001);
+        assert!(lines[1]["latency"].is_null());
+        assert!(lines[0]["time"].as_f64().unwrap() <= lines[1]["time"].as_f64().unwrap());
+    }
+
+    #[test]
+    fn write_csv() {
+        let path = env::temp_dir().join(format!("pen-plotter-telemetry-{}.csv", process::id()));
+        let telemetry = Telemetry::create(&path).unwrap();
+        telemetry.write(
+            0,
+            &Sample {
+                latency: None,
+                ..sample()
+            },
+        );
+        telemetry.write(
+            1,
+            &Sample {
+                reported_step: None,
+                latency: None,
+                ..sample()
+            },
+        );
+        drop(telemetry);
+        let text = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        let fields: Vec<_> = lines[1].split(',').collect();
+        assert_eq!(fields.len(), CSV_HEADER.split(',').count());
+        assert_eq!(
+            fields[1..],
+            [
+                "0",
+                "beam stepper",
+                "0.5",
+                "0",
+                "-10.5",
+                "20",
+                "2",
+                "20",
+                "",
+                "0"
+            ]
+        );
+        assert!(lines[2].contains(",2,,,0"), "{}", lines[2]);
+    }
+}
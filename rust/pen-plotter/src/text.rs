@@ -0,0 +1,202 @@
+mod simplex;
+
+use crate::job::Stroke;
+use eyre::eyre;
+use nalgebra::{Point2, Rotation2, Vector2};
+
+/// of capitals in font units
+const CAP_HEIGHT: f64 = 21.0;
+
+/// between baselines in font units
+const LINE_HEIGHT: f64 = 32.0;
+
+/// of lines in font units, longer ones are split before bending them along an arc
+const MAX_LINE: f64 = 1.0;
+
+/// Where text is written, in millimeter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Baseline {
+    /// straight from `start`, rotated by `angle` radians counterclockwise
+    Line { start: Point2<f64>, angle: f64 },
+
+    /// clockwise along a circle about `center` from `angle` radians counterclockwise
+    /// from the x axis, the letters stand on the circle
+    Arc {
+        center: Point2<f64>,
+        radius: f64,
+        angle: f64,
+    },
+}
+
+/// Converts `text` to strokes of the Hershey simplex font with capitals `size` millimeter high.
+/// Lines of the text are written below each other, unknown characters as `?`.
+pub fn render(text: &str, size: f64, baseline: &Baseline) -> eyre::Result<Vec<Stroke>> {
+    let scale = size / CAP_HEIGHT;
+    let mut strokes = Vec::new();
+    for (row, line) in text.lines().enumerate() {
+        let mut advance = Vector2::new(0.0, -LINE_HEIGHT * row as f64);
+        for c in line.chars() {
+            let (width, vertices) = glyph(c);
+            for stroke in glyph_strokes(vertices)? {
+                let stroke = match baseline {
+                    Baseline::Line { .. } => stroke,
+                    Baseline::Arc { .. } => split(&stroke, MAX_LINE),
+                };
+                let stroke = stroke.into_iter().map(|p| (p + advance) * scale);
+                strokes.push(place(stroke.collect(), baseline));
+            }
+            advance.x += f64::from(width);
+        }
+    }
+    Ok(strokes)
+}
+
+fn glyph(c: char) -> (i8, &'static [i8]) {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    simplex::GLYPHS[index]
+}
+
+/// in font units, split where the pen lifts
+fn glyph_strokes(vertices: &[i8]) -> eyre::Result<Vec<Stroke>> {
+    let mut strokes = vec![Vec::new()];
+    for pair in vertices.chunks(2) {
+        match pair {
+            [-1, -1] => strokes.push(Vec::new()),
+            &[x, y] => {
+                let point = Point2::new(f64::from(x), f64::from(y));
+                strokes.last_mut().unwrap().push(point);
+            }
+            _ => {
+                return Err(eyre!(
+                    "glyph with an odd number of coordinates {vertices:?}"
+                ))
+            }
+        }
+    }
+    strokes.retain(|s| s.len() >= 2);
+    Ok(strokes)
+}
+
+/// Moves a stroke in millimeter relative to the start of the baseline onto the baseline.
+fn place(stroke: Stroke, baseline: &Baseline) -> Stroke {
+    match *baseline {
+        Baseline::Line { start, angle } => {
+            let rotation = Rotation2::new(angle);
+            stroke
+                .into_iter()
+                .map(|p| start + rotation * p.coords)
+                .collect()
+        }
+        Baseline::Arc {
+            center,
+            radius,
+            angle,
+        } => stroke
+            .into_iter()
+            .map(|p| {
+                let orientation = angle - p.x / radius;
+                let distance = radius + p.y;
+                center + Vector2::new(orientation.cos(), orientation.sin()) * distance
+            })
+            .collect(),
+    }
+}
+
+/// Splits the lines of `stroke` into lines no longer than `max`.
+fn split(stroke: &[Point2<f64>], max: f64) -> Stroke {
+    let mut points = stroke[..1].to_vec();
+    for w in stroke.windows(2) {
+        let parts = (nalgebra::distance(&w[0], &w[1]) / max).ceil().max(1.0) as usize;
+        points.extend((1..=parts).map(|i| w[0] + (w[1] - w[0]) * (i as f64 / parts as f64)));
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    fn line() -> Baseline {
+        Baseline::Line {
+            start: Point2::origin(),
+            angle: 0.0,
+        }
+    }
+
+    fn assert_near(actual: Point2<f64>, expected: Point2<f64>) {
+        assert!(
+            nalgebra::distance(&actual, &expected) < 1e-9,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn glyphs_advance_by_their_width() {
+        let strokes = render("II", CAP_HEIGHT, &line()).unwrap();
+        assert_eq!(
+            strokes,
+            [
+                vec![Point2::new(4.0, 21.0), Point2::new(4.0, 0.0)],
+                vec![Point2::new(12.0, 21.0), Point2::new(12.0, 0.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn pen_lifts_between_parts_of_glyph() {
+        assert_eq!(render("A", 1.0, &line()).unwrap().len(), 3);
+        assert_eq!(render("a b", 1.0, &line()).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn rotate_and_scale() {
+        let baseline = Baseline::Line {
+            start: Point2::new(10.0, -20.0),
+            angle: FRAC_PI_2,
+        };
+        let strokes = render("I", 2.0 * CAP_HEIGHT, &baseline).unwrap();
+        assert_near(strokes[0][0], Point2::new(-32.0, -12.0));
+        assert_near(strokes[0][1], Point2::new(10.0, -12.0));
+    }
+
+    #[test]
+    fn lines_below_each_other() {
+        let strokes = render("I\nI", CAP_HEIGHT, &line()).unwrap();
+        assert_eq!(strokes[1][1], Point2::new(4.0, -LINE_HEIGHT));
+    }
+
+    #[test]
+    fn odd_coordinates_fail() {
+        assert!(glyph_strokes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn unknown_characters_as_question_mark() {
+        assert_eq!(
+            render("ä", 1.0, &line()).unwrap(),
+            render("?", 1.0, &line()).unwrap()
+        );
+    }
+
+    #[test]
+    fn arc_letters_stand_on_circle() {
+        let baseline = Baseline::Arc {
+            center: Point2::new(0.0, -10.0),
+            radius: 100.0,
+            angle: FRAC_PI_2,
+        };
+        let strokes = render("-", CAP_HEIGHT, &baseline).unwrap();
+        let stroke = &strokes[0];
+        assert_eq!(stroke.len(), 19);
+        for p in stroke {
+            let distance = nalgebra::distance(p, &Point2::new(0.0, -10.0));
+            assert!((distance - 109.0).abs() < 1e-9);
+        }
+        // clockwise from the top
+        assert!(stroke[0].x > 0.0 && stroke[18].x > stroke[0].x);
+    }
+}
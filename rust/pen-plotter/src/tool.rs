@@ -0,0 +1,117 @@
+use crate::{motion::PEN_DELAY, pen::PenNumber};
+use eyre::{eyre, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Settings of a pen, missing values are taken from [`Tools`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PenSettings {
+    pub number: PenNumber,
+
+    /// in mm/s
+    pub velocity: Option<f64>,
+
+    /// to wait for the pen to go up or down in seconds
+    pub pen_delay: Option<f64>,
+}
+
+/// How to plot with each pen and where to change pens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tools {
+    /// in mm/s
+    pub velocity: f64,
+
+    /// in seconds
+    pub pen_delay: f64,
+
+    /// location in millimeter to change pens at, none changes them where the job started
+    pub park: Option<[f64; 2]>,
+    pub pens: Vec<PenSettings>,
+}
+
+impl Tools {
+    /// Plots with all pens at `velocity`.
+    pub fn new(velocity: f64) -> Self {
+        Self {
+            velocity,
+            pen_delay: PEN_DELAY.as_secs_f64(),
+            park: None,
+            pens: Vec::new(),
+        }
+    }
+
+    /// in mm/s
+    pub fn velocity(&self, pen: PenNumber) -> f64 {
+        self.settings(pen)
+            .and_then(|s| s.velocity)
+            .unwrap_or(self.velocity)
+    }
+
+    pub fn pen_delay(&self, pen: PenNumber) -> Duration {
+        let seconds = self.settings(pen).and_then(|s| s.pen_delay);
+        Duration::from_secs_f64(seconds.unwrap_or(self.pen_delay))
+    }
+
+    /// Fails on pen delays that are negative or not a number.
+    pub fn validate(&self) -> eyre::Result<()> {
+        validate_pen_delay(self.pen_delay)?;
+        self.pens.iter().try_for_each(PenSettings::validate)
+    }
+
+    fn settings(&self, pen: PenNumber) -> Option<&PenSettings> {
+        self.pens.iter().find(|s| s.number == pen)
+    }
+}
+
+impl PenSettings {
+    /// Fails on a pen delay that is negative or not a number.
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.pen_delay
+            .map_or(Ok(()), validate_pen_delay)
+            .wrap_err_with(|| format!("invalid settings of pen {}", self.number))
+    }
+}
+
+fn validate_pen_delay(seconds: f64) -> eyre::Result<()> {
+    Duration::try_from_secs_f64(seconds)
+        .map(drop)
+        .map_err(|_| eyre!("pen delay of {seconds} s is not a duration"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pen_settings_override_defaults() {
+        let tools = Tools {
+            pens: vec![PenSettings {
+                number: 2,
+                velocity: Some(3.0),
+                pen_delay: None,
+            }],
+            ..Tools::new(1.5)
+        };
+        assert_eq!(tools.velocity(1), 1.5);
+        assert_eq!(tools.velocity(2), 3.0);
+        assert_eq!(tools.pen_delay(2), PEN_DELAY);
+    }
+
+    #[test]
+    fn invalid_pen_delay_fails() {
+        let pen = |pen_delay| PenSettings {
+            number: 2,
+            pen_delay: Some(pen_delay),
+            ..Default::default()
+        };
+        assert!(pen(0.5).validate().is_ok());
+        assert!(pen(-0.5).validate().is_err());
+        assert!(pen(f64::NAN).validate().is_err());
+        let tools = Tools {
+            pen_delay: -1.0,
+            ..Tools::new(1.5)
+        };
+        assert!(tools.validate().is_err());
+    }
+}